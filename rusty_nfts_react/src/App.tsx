@@ -1,17 +1,71 @@
-import React, { useState, useEffect } from "react";
-import init, { apply_filter } from "../public/pkg";
+import React, { useState, useEffect, useRef } from "react";
+import init, {
+  describe_filter,
+  filter_label,
+  list_filters,
+  list_output_formats,
+  register_lut,
+} from "../public/pkg";
+import { FilterClient } from "./filterClient";
 import "./index.css";
 
+// A parameter value: a number for sliders, a string for choices and colours, rows of numbers for matrices
+type ParamValue = number | string | number[][];
+
+// Mirrors the Rust FilterSpec struct (one stage of a pipeline)
+interface FilterSpec {
+  filter: string;
+  params: Record<string, ParamValue>;
+}
+
+// Mirrors the Rust ParamInfo struct (describe_filter returns a JSON array of these)
+// 'type' says which kind of control to show
+type ParamInfo =
+  | { name: string; label: string; type: "number"; min: number; max: number; default: number; step: number; integer: boolean }
+  | { name: string; label: string; type: "choice"; options: string[]; default: string }
+  | { name: string; label: string; type: "color"; default: string }
+  | { name: string; label: string; type: "matrix"; default: number[][] };
+
+// "0 -1 0\n-1 5 -1\n0 -1 0" => [[0, -1, 0], [-1, 5, -1], [0, -1, 0]] (numbers can be split by spaces or commas)
+const parseMatrix = (text: string): number[][] =>
+  text
+    .split("\n")
+    .map((line) => line.split(/[\s,]+/).filter((cell) => cell !== "").map(Number))
+    .filter((row) => row.length > 0);
+
 function App() {
   const [selectedFile, setSelectedFile] = useState<File | null>(null);
   const [filteredImage, setFilteredImage] = useState<string | null>(null);
   const [filterType, setFilterType] = useState<string>("grayscale");
   const [wasmInitialized, setWasmInitialized] = useState(false);
+  const [errorMessage, setErrorMessage] = useState<string | null>(null);
+  // Object URLs of the histograms (drawn by Rust) of the image before and after filtering
+  const [histograms, setHistograms] = useState<{ before: string; after: string } | null>(null);
+  // The filter names come from Rust (list_filters), so the <select> can never offer a filter that doesn't exist
+  const [filterNames, setFilterNames] = useState<string[]>([]);
+  // The adjustable parameters of the selected filter, and the current slider values
+  const [filterParams, setFilterParams] = useState<ParamInfo[]>([]);
+  const [paramValues, setParamValues] = useState<Record<string, ParamValue>>({});
+  // Filters that have been added to the chain, applied in order before the currently selected one
+  const [chain, setChain] = useState<FilterSpec[]>([]);
+  // The format to download in (the names come from Rust's list_output_formats) and its file extension
+  const [outputFormats, setOutputFormats] = useState<string[]>([]);
+  const [outputFormat, setOutputFormat] = useState<string>("png");
+  const [outputExtension, setOutputExtension] = useState<string>("png");
+  // The filtering itself runs in a Web Worker (filterWorker.ts), on every core when the browser allows it
+  // The decoded upload lives in Rust in the worker (ImageSession), so it's only decoded once no matter how many filters are tried
+  // Refs rather than state, because changing them doesn't need a re-render
+  const clientRef = useRef<FilterClient | null>(null);
+  const sessionOpenRef = useRef(false);
 
   useEffect(() => {
+    clientRef.current = new FilterClient();
+    // The filter names and parameters are cheap to look up, so they still come from wasm on the page itself
     const initializeWasm = async () => {
       try {
         await init({});
+        setFilterNames(list_filters());
+        setOutputFormats(list_output_formats());
         setWasmInitialized(true);
       } catch (error) {
         console.error("Failed to initialize WebAssembly module", error);
@@ -19,66 +73,141 @@ function App() {
     };
 
     initializeWasm();
+    return () => {
+      clientRef.current?.terminate();
+      clientRef.current = null;
+    };
   }, []);
 
+  // Whenever the filter changes, ask Rust which parameters it has and reset the sliders to their defaults
+  useEffect(() => {
+    if (!wasmInitialized) {
+      return;
+    }
+    const params: ParamInfo[] = JSON.parse(describe_filter(filterType));
+    setFilterParams(params);
+    setParamValues(Object.fromEntries(params.map((param) => [param.name, param.default])));
+  }, [filterType, wasmInitialized]);
+
+  // Frees the current session's memory in wasm (JS's garbage collector can't see it)
+  const closeSession = () => {
+    if (sessionOpenRef.current) {
+      clientRef.current?.close();
+      sessionOpenRef.current = false;
+    }
+  };
+
   const handleFileChange = (event: React.ChangeEvent<HTMLInputElement>) => {
     if (event.target.files && event.target.files.length > 0) {
       setSelectedFile(event.target.files[0]);
       setFilteredImage(null);
+      setHistograms(null);
+      setErrorMessage(null);
+      closeSession();
     }
   };
 
+  // Reads the file and decodes it into a new ImageSession (in the worker)
+  const openSession = async (client: FilterClient, file: File) => {
+    // arrayBuffer() => the file's data as binary
+    // We used 'u8' in Rust (perfect for representing the pixel values) so create an array of unsigned 8-bit integers from it
+    const imgData = new Uint8Array(await file.arrayBuffer());
+    await client.open(imgData);
+    sessionOpenRef.current = true;
+  };
+
 const handleFilterApply = async () => {
-  if (!wasmInitialized) {
+  const client = clientRef.current;
+  if (!wasmInitialized || !client) {
     console.warn("WebAssembly module not yet initialized");
     return; 
   }
 
   if (selectedFile) {
-    // Create a new FileReader to read the file's data
-    // FileReader is a built-in JavaScript object that allows web applications to asynchronously read the contents of files
-    const reader = new FileReader();
-
-    // onload => Event handler that is called when the file reading operation is successfully completed
-    reader.onload = async (e: ProgressEvent<FileReader>) => {
-      const result = e.target?.result; // Get the result of the file reading operation
-
-      // Check if the result exists and is not a string (it should be binary data)
-      if (result && typeof result !== "string") {
-        // We used 'u8' in Rust (perfect for representing the pixel values) so create an array of unsigned 8-bit integers from the 'result'
-        const imgData = new Uint8Array(result);
-
-        // Apply the selected filter using the WebAssembly function `apply_filter`
-        // This function takes the image data and the selected filter type as arguments
-        const filteredData = apply_filter(imgData, filterType);
-
-        // Create a Blob (Binary Large Object) from the filtered data, specifying that it's a PNG image
-        // Why do we need a blob? A Uint8Array is still just raw binary data
-        // So, for it to be downloadable or viewable image file, it needs to be encapsulated in a blob. 
-        // The type is the MIME type -> tells the browser how to interpret the data
-        const filteredBlob = new Blob([filteredData], { type: "image/png" });
-
-        // Generate a URL for the Blob and set it as the filtered image to display it
-        setFilteredImage(URL.createObjectURL(filteredBlob));
+    // Apply the chain plus the selected filter to the session using `preview`
+    // This takes a JSON description of every filter (and its slider values) and the output format as arguments
+    // Rust runs every stage on the already decoded image and encodes it once at the end
+    // If Rust returns an error (e.g. a corrupt file), it is thrown here as a normal JS Error
+    const pipeline = JSON.stringify({
+      stages: [...chain, { filter: filterType, params: paramValues }],
+    });
+    const output = JSON.stringify({ format: outputFormat });
+    const histogram = JSON.stringify({ width: 256, height: 80, channels: "rgb" });
+    let filteredData: Uint8Array;
+    let mimeType: string;
+    let histogramPngs: { before: Uint8Array; after: Uint8Array } | undefined;
+    try {
+      if (!sessionOpenRef.current) {
+        await openSession(client, selectedFile);
       }
-    };
+      const encoded = await client.preview(pipeline, output, histogram);
+      filteredData = encoded.bytes;
+      mimeType = encoded.mimeType;
+      histogramPngs = encoded.histograms;
+      setOutputExtension(encoded.extension);
+    } catch (error) {
+      setFilteredImage(null);
+      setHistograms(null);
+      setErrorMessage(error instanceof Error ? error.message : String(error));
+      return;
+    }
+    setErrorMessage(null);
 
-    // Start reading the selected file as an ArrayBuffer (binary data)
-    // reader.onload is async (so waits to be triggered) and set up before 'readAsArrayBuffer' so it is guaranteed to be ready when the file is read
-    reader.readAsArrayBuffer(selectedFile);
+    // Create a Blob (Binary Large Object) from the filtered data, using the MIME type Rust gave us (e.g. "image/jpeg")
+    // Why do we need a blob? A Uint8Array is still just raw binary data
+    // So, for it to be downloadable or viewable image file, it needs to be encapsulated in a blob. 
+    // The type is the MIME type -> tells the browser how to interpret the data
+    const filteredBlob = new Blob([filteredData], { type: mimeType });
+
+    // Generate a URL for the Blob and set it as the filtered image to display it
+    setFilteredImage(URL.createObjectURL(filteredBlob));
+    const pngUrl = (bytes: Uint8Array) => URL.createObjectURL(new Blob([bytes], { type: "image/png" }));
+    setHistograms(histogramPngs ? { before: pngUrl(histogramPngs.before), after: pngUrl(histogramPngs.after) } : null);
   }
 };
 
+  // Loads a .cube colour grade and adds it to the filter list, named after the file (e.g. "teal_orange.cube" => "teal_orange")
+  // It's registered on the page too, so describe_filter and filter_label know about it
+  const handleLutChange = async (event: React.ChangeEvent<HTMLInputElement>) => {
+    const file = event.target.files?.[0];
+    // Clear it, so picking the same file again still fires onChange
+    event.target.value = "";
+    if (!file || !clientRef.current) {
+      return;
+    }
+    const name = file.name.replace(/\.cube$/i, "");
+    try {
+      const cube = await file.text();
+      register_lut(name, cube);
+      await clientRef.current.registerLut(name, cube);
+    } catch (error) {
+      setErrorMessage(error instanceof Error ? error.message : String(error));
+      return;
+    }
+    setErrorMessage(null);
+    setFilterNames(list_filters());
+    setFilterType(name);
+  };
+
+  // Adds the selected filter (with its current slider values) to the end of the chain
+  const handleAddToChain = () => {
+    setChain([...chain, { filter: filterType, params: paramValues }]);
+  };
+
   const handleReset = () => {
+    closeSession();
     setSelectedFile(null);
     setFilteredImage(null);
+    setHistograms(null);
+    setErrorMessage(null);
+    setChain([]);
   };
 
   const handleDownload = () => {
     if (filteredImage) {
       const link = document.createElement("a");
       link.href = filteredImage;
-      link.download = "rusty_nft.png";
+      link.download = `rusty_nft.${outputExtension}`;
       link.click();
     }
   };
@@ -97,6 +226,10 @@ const handleFilterApply = async () => {
             Choose File
             <input type="file" onChange={handleFileChange} className="hidden" />
           </label>
+          <label className="text-center font-lacquer cursor-pointer bg-purple-500 text-black p-2 rounded-md hover:bg-purple-600 transform transition">
+            Load LUT
+            <input type="file" accept=".cube" onChange={handleLutChange} disabled={!wasmInitialized} className="hidden" />
+          </label>
           {selectedFile && (
             <p className="text-lg text-gray-700 font-lacquer">
               {selectedFile.name}
@@ -109,21 +242,108 @@ const handleFilterApply = async () => {
           </label>
           <select
             id="filter-select"
+            value={filterType}
             onChange={(e) => setFilterType(e.target.value)}
             className="bg-gray-100 border border-gray-300 rounded-md ml-2 font-cang text-3xl text-center"
           >
-            <option value="grayscale">Grayscale</option>
-            <option value="blur">Blur</option>
-            <option value="huerotate">Hue Rotate</option>
-            <option value="invert">Invert Colors</option>
-            <option value="sepia">Sepia</option>
-            <option value="pixelate">Pixelate</option>
-            <option value="emboss">Emboss</option>
-            <option value="sharpen">Sharpen</option>
-            <option value="posterize">Posterize</option>
+            {filterNames.map((name) => (
+              <option key={name} value={name}>
+                {filter_label(name)}
+              </option>
+            ))}
           </select>
         </div>
+        <div className="flex ml-2 mt-2">
+          <label htmlFor="format-select" className="font-doodle text-3xl">
+            Format:
+          </label>
+          <select
+            id="format-select"
+            value={outputFormat}
+            onChange={(e) => setOutputFormat(e.target.value)}
+            className="bg-gray-100 border border-gray-300 rounded-md ml-2 font-cang text-3xl text-center"
+          >
+            {outputFormats.map((format) => (
+              <option key={format} value={format}>
+                {format === "same_as_input" ? "Same as upload" : format.toUpperCase()}
+              </option>
+            ))}
+          </select>
+        </div>
+        {filterParams.map((param) => (
+          <div key={param.name} className="flex items-center gap-2 mt-2 w-full">
+            <label htmlFor={`param-${param.name}`} className="font-cang text-2xl w-1/3">
+              {param.label}:
+            </label>
+            {param.type === "number" && (
+              <>
+                <input
+                  id={`param-${param.name}`}
+                  type="range"
+                  min={param.min}
+                  max={param.max}
+                  step={param.step}
+                  value={paramValues[param.name] ?? param.default}
+                  onChange={(e) =>
+                    setParamValues({ ...paramValues, [param.name]: Number(e.target.value) })
+                  }
+                  className="flex-grow"
+                />
+                <span className="font-cang text-2xl w-12 text-right">
+                  {paramValues[param.name] ?? param.default}
+                </span>
+              </>
+            )}
+            {param.type === "choice" && (
+              <select
+                id={`param-${param.name}`}
+                value={paramValues[param.name] ?? param.default}
+                onChange={(e) => setParamValues({ ...paramValues, [param.name]: e.target.value })}
+                className="flex-grow bg-gray-100 border border-gray-300 rounded-md font-cang text-2xl text-center"
+              >
+                {param.options.map((option) => (
+                  <option key={option} value={option}>
+                    {option}
+                  </option>
+                ))}
+              </select>
+            )}
+            {param.type === "color" && (
+              // The colour picker only does #rrggbb, so the alpha part of the default is dropped here
+              <input
+                id={`param-${param.name}`}
+                type="color"
+                value={String(paramValues[param.name] ?? param.default).slice(0, 7)}
+                onChange={(e) => setParamValues({ ...paramValues, [param.name]: e.target.value })}
+                className="flex-grow"
+              />
+            )}
+            {param.type === "matrix" && (
+              // Uncontrolled (defaultValue) so half-typed rows don't get rewritten while typing
+              // Rust checks the matrix when the filter is applied (odd size, same length rows, ...)
+              <textarea
+                key={`${filterType}-${param.name}`}
+                id={`param-${param.name}`}
+                rows={param.default.length}
+                defaultValue={param.default.map((row) => row.join(" ")).join("\n")}
+                onChange={(e) => setParamValues({ ...paramValues, [param.name]: parseMatrix(e.target.value) })}
+                className="flex-grow bg-white border border-gray-300 rounded-md font-mono text-lg p-1"
+              />
+            )}
+          </div>
+        ))}
+        {chain.length > 0 && (
+          <p className="font-cang text-xl mt-2 text-center">
+            Chain: {chain.map((stage) => filter_label(stage.filter)).join(" → ")} → {filter_label(filterType)}
+          </p>
+        )}
         <div className="flex justify-center w-full gap-5 mt-2">
+          <button
+            onClick={handleAddToChain}
+            className="bg-yellow-500 text-black p-1 font-marker rounded-md text-opacity-85 text-1xl w-5/12 hover:bg-yellow-600 transform transition duration-700 hover:scale-110 hover:text-white"
+          >
+            Add to Chain
+          </button>
           <button
             onClick={handleFilterApply}
             className="bg-blue-500 text-black p-1 font-marker rounded-md text-opacity-85 text-1xl w-5/12 hover:bg-blue-600 transform transition duration-700 hover:scale-110 hover:text-white"
@@ -137,6 +357,11 @@ const handleFilterApply = async () => {
             Reset
           </button>
         </div>
+        {errorMessage && (
+          <p className="text-lg text-red-600 font-cang mt-2 text-center">
+            {errorMessage}
+          </p>
+        )}
       </div>
       {selectedFile && (
       <div className="flex justify-center gap-5 mt-3 bg-gray-100 p-5 pt-2 rounded-lg shadow-lg shadow-gray-500">
@@ -147,6 +372,9 @@ const handleFilterApply = async () => {
               alt="Original"
               className="max-w-9/12 max-h-60 rounded-md shadow-lg shadow-gray-400"
             />
+            {histograms && (
+              <img src={histograms.before} alt="Original histogram" className="mt-2 bg-gray-800 rounded-md" />
+            )}
           </div>
         {filteredImage && (
           <div className="flex flex-col items-center">
@@ -156,6 +384,9 @@ const handleFilterApply = async () => {
               alt="Filtered"
               className="max-w-9/12 max-h-60 rounded-md shadow-lg shadow-gray-400"
             />
+            {histograms && (
+              <img src={histograms.after} alt="Filtered histogram" className="mt-2 bg-gray-800 rounded-md" />
+            )}
           </div>
         )}
         </div>
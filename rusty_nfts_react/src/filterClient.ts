@@ -0,0 +1,65 @@
+import type { WorkerRequest, WorkerResponse } from "./filterWorker";
+
+// Distributes Omit over each member of the union (plain Omit would merge them)
+type Request = WorkerRequest extends infer R ? (R extends WorkerRequest ? Omit<R, "id"> : never) : never;
+
+export interface FilteredImage {
+  bytes: Uint8Array;
+  mimeType: string;
+  extension: string;
+  // PNGs of the histograms before and after the pipeline, if they were asked for
+  histograms?: { before: Uint8Array; after: Uint8Array };
+}
+
+// The page's side of filterWorker.ts: turns each message and its reply into a Promise
+export class FilterClient {
+  private worker = new Worker(new URL("./filterWorker.ts", import.meta.url), { type: "module" });
+  private nextId = 0;
+  private pending = new Map<number, { resolve: (response: WorkerResponse) => void }>();
+
+  constructor() {
+    this.worker.onmessage = ({ data: response }: MessageEvent<WorkerResponse>) => {
+      this.pending.get(response.id)?.resolve(response);
+      this.pending.delete(response.id);
+    };
+  }
+
+  // Decodes the upload once, every preview after this filters the decoded image
+  async open(bytes: Uint8Array): Promise<void> {
+    await this.send({ type: "open", bytes });
+  }
+
+  // Runs a JSON pipeline on the open image and encodes it with the JSON output options
+  // histogram (JSON histogram options, see histogram.rs) => draw the before and after histograms too
+  async preview(pipeline: string, output: string, histogram?: string): Promise<FilteredImage> {
+    const result = await this.send({ type: "preview", pipeline, output, histogram });
+    if (!result) {
+      throw new Error("The worker didn't send an image back");
+    }
+    return result;
+  }
+
+  // Adds a .cube LUT to the worker's filters, under 'name'
+  async registerLut(name: string, cube: string): Promise<void> {
+    await this.send({ type: "registerLut", name, cube });
+  }
+
+  // Frees the open image's memory in wasm
+  async close(): Promise<void> {
+    await this.send({ type: "close" });
+  }
+
+  terminate() {
+    this.worker.terminate();
+  }
+
+  private send(request: Request): Promise<FilteredImage | undefined> {
+    const id = this.nextId++;
+    return new Promise((resolve, reject) => {
+      this.pending.set(id, {
+        resolve: (response) => (response.ok ? resolve(response.result) : reject(new Error(response.error))),
+      });
+      this.worker.postMessage({ ...request, id });
+    });
+  }
+}
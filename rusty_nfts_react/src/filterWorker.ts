@@ -0,0 +1,114 @@
+// Runs the filters in a Web Worker, so the page stays responsive while a big image is being filtered
+//
+// If the page is cross-origin isolated (see the headers in vite.config.ts) and the threaded build exists
+// in public/pkg-threads, the filters are also shared out across every core (see threads.rs, this part is still experimental)
+// Otherwise it quietly falls back to the normal single threaded build in public/pkg
+
+// The wasm-bindgen module (both builds have the same exports, the threaded one adds initThreadPool)
+type Pkg = typeof import("../public/pkg") & {
+  initThreadPool?: (numThreads: number) => Promise<unknown>;
+};
+
+// Messages from FilterClient (filterClient.ts)
+export type WorkerRequest =
+  | { id: number; type: "open"; bytes: Uint8Array }
+  // histogram => also draw the histograms of the image before and after the pipeline, with these JSON options
+  | { id: number; type: "preview"; pipeline: string; output: string; histogram?: string }
+  | { id: number; type: "registerLut"; name: string; cube: string }
+  | { id: number; type: "close" };
+
+// Replies to FilterClient, matched up by 'id'
+export type WorkerResponse =
+  | {
+      id: number;
+      ok: true;
+      result?: { bytes: Uint8Array; mimeType: string; extension: string; histograms?: { before: Uint8Array; after: Uint8Array } };
+    }
+  | { id: number; ok: false; error: string };
+
+// Loads a build from public/ (a URL rather than an import, so Vite leaves it alone)
+const load = async (dir: string): Promise<Pkg> => {
+  const pkg: Pkg = await import(/* @vite-ignore */ new URL(`/${dir}/rusty_filter_rust.js`, self.location.origin).href);
+  await pkg.default({});
+  return pkg;
+};
+
+const loadPkg = async (): Promise<{ pkg: Pkg; threads: number }> => {
+  // Shared memory (and so threads) is only allowed on cross-origin isolated pages
+  if (self.crossOriginIsolated) {
+    try {
+      const pkg = await load("pkg-threads");
+      if (pkg.initThreadPool) {
+        const threads = navigator.hardwareConcurrency || 1;
+        await pkg.initThreadPool(threads);
+        return { pkg, threads };
+      }
+    } catch (error) {
+      console.warn("Threaded build unavailable, using the single threaded one", error);
+    }
+  }
+  return { pkg: await load("pkg"), threads: 1 };
+};
+
+const ready = loadPkg().then(({ pkg, threads }) => {
+  console.info(`Filtering with ${threads} thread${threads === 1 ? "" : "s"}`);
+  return pkg;
+});
+
+// Copies an EncodedImage's bytes out of wasm memory and frees it
+const pngBytes = (encoded: { bytes: Uint8Array; free: () => void }): Uint8Array => {
+  const bytes = encoded.bytes;
+  encoded.free();
+  return bytes;
+};
+
+// The decoded upload (see ImageSession in session.rs)
+let session: InstanceType<Pkg["ImageSession"]> | null = null;
+
+self.onmessage = async ({ data: request }: MessageEvent<WorkerRequest>) => {
+  try {
+    const pkg = await ready;
+    switch (request.type) {
+      case "open":
+        session?.free();
+        session = null;
+        session = new pkg.ImageSession(request.bytes);
+        self.postMessage({ id: request.id, ok: true } satisfies WorkerResponse);
+        break;
+      case "preview": {
+        if (!session) {
+          throw new Error("No image is open");
+        }
+        const encoded = session.preview(request.pipeline, request.output);
+        const result = { bytes: encoded.bytes, mimeType: encoded.mimeType, extension: encoded.extension };
+        // The result lives in wasm memory, so free it once we've copied what we need
+        encoded.free();
+        // Both are PNGs. 'after' decodes the preview again, which is simpler than keeping its pixels around
+        const histograms = request.histogram
+          ? {
+              before: pngBytes(session.render_histogram(request.histogram)),
+              after: pngBytes(pkg.render_histogram(result.bytes, request.histogram)),
+            }
+          : undefined;
+        const transfer = [result.bytes.buffer, ...(histograms ? [histograms.before.buffer, histograms.after.buffer] : [])];
+        // transfer => hands the bytes over to the page instead of copying them
+        self.postMessage({ id: request.id, ok: true, result: { ...result, histograms } } satisfies WorkerResponse, { transfer });
+        break;
+      }
+      case "registerLut":
+        // The worker has its own copy of the wasm module (and so its own filter registry)
+        pkg.register_lut(request.name, request.cube);
+        self.postMessage({ id: request.id, ok: true } satisfies WorkerResponse);
+        break;
+      case "close":
+        session?.free();
+        session = null;
+        self.postMessage({ id: request.id, ok: true } satisfies WorkerResponse);
+        break;
+    }
+  } catch (error) {
+    // Rust errors arrive as normal JS Errors, send the message back to be shown on the page
+    const message = error instanceof Error ? error.message : String(error);
+    self.postMessage({ id: request.id, ok: false, error: message } satisfies WorkerResponse);
+  }
+};
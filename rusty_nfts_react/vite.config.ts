@@ -1,7 +1,20 @@
 import { defineConfig } from 'vite'
 import react from '@vitejs/plugin-react'
 
+// Makes the page 'cross-origin isolated', which browsers require before they allow the shared memory
+// used by the multithreaded wasm build (public/pkg-threads)
+// credentialless (rather than require-corp) so the Google Fonts still load
+// Browsers without credentialless support just aren't isolated, and use the single threaded build
+const crossOriginIsolation = {
+  'Cross-Origin-Opener-Policy': 'same-origin',
+  'Cross-Origin-Embedder-Policy': 'credentialless',
+}
+
 // https://vitejs.dev/config/
 export default defineConfig({
   plugins: [react()],
+  server: { headers: crossOriginIsolation },
+  preview: { headers: crossOriginIsolation },
+  // The filter worker is an ES module (it imports the wasm-bindgen module)
+  worker: { format: 'es' },
 })
@@ -0,0 +1,68 @@
+use std::time::{Duration, Instant};
+
+use image::{imageops, Rgba, RgbaImage};
+use rusty_filter_rust::filters::{apply_convolution, AlphaMode, box_blur, fast_gaussian_blur, gaussian_blur, BorderMode, Kernel};
+
+// Compares the blur methods on a 12 megapixel image (a typical phone photo)
+// Run with: cargo bench --bench blur
+// The old path (imageops::blur) and the 2D convolution are only timed at small sigmas, because they get very slow
+
+const WIDTH: u32 = 4000;
+const HEIGHT: u32 = 3000;
+
+fn main() {
+    let img = test_image();
+    println!("{}x{} image, best of 3 runs", WIDTH, HEIGHT);
+    println!("{:<8}{:>16}{:>16}{:>16}{:>16}{:>16}", "sigma", "imageops::blur", "convolution", "gaussian", "box", "fast_gaussian");
+
+    for sigma in [2.0f32, 5.0, 10.0, 25.0, 50.0] {
+        let old = (sigma <= 10.0).then(|| time(|| imageops::blur(&img, sigma)));
+        // A 2D kernel can only go up to 31x31, i.e. sigma 5
+        let convolution = (sigma <= 5.0).then(|| {
+            let radius = ((3.0 * sigma).ceil() as usize).min(Kernel::MAX_SIZE / 2);
+            let kernel = Kernel::gaussian(radius, sigma).expect("radius is within the limit");
+            time(|| apply_convolution(&img, &kernel, BorderMode::Clamp, AlphaMode::default()))
+        });
+        let gaussian = time(|| gaussian_blur(&img, sigma, AlphaMode::default()));
+        let box_radius = ((12.0 * sigma * sigma + 1.0).sqrt() / 2.0).round() as u32;
+        let boxed = time(|| box_blur(&img, box_radius, AlphaMode::default()));
+        let fast = time(|| fast_gaussian_blur(&img, sigma, AlphaMode::default()));
+
+        println!(
+            "{:<8}{:>16}{:>16}{:>16}{:>16}{:>16}",
+            sigma,
+            format_time(old),
+            format_time(convolution),
+            format_time(Some(gaussian)),
+            format_time(Some(boxed)),
+            format_time(Some(fast))
+        );
+    }
+}
+
+// A noisy gradient, so there is something to actually blur
+fn test_image() -> RgbaImage {
+    RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        let noise = (x.wrapping_mul(7919) ^ y.wrapping_mul(104729)) % 64;
+        Rgba([(x % 256) as u8, (y % 256) as u8, noise as u8 * 4, 255])
+    })
+}
+
+fn time<T>(run: impl Fn() -> T) -> Duration {
+    (0..3)
+        .map(|_| {
+            let start = Instant::now();
+            // black_box => stops the compiler from throwing the unused result (and the work) away
+            std::hint::black_box(run());
+            start.elapsed()
+        })
+        .min()
+        .expect("ran at least once")
+}
+
+fn format_time(time: Option<Duration>) -> String {
+    match time {
+        Some(time) => format!("{:.0} ms", time.as_secs_f64() * 1000.0),
+        None => "-".to_string(),
+    }
+}
@@ -0,0 +1,82 @@
+use std::time::{Duration, Instant};
+
+use image::{Rgba, RgbaImage};
+use rusty_filter_rust::{FilterSpec, Pipeline};
+
+// Times every built-in filter on a 12 megapixel image (a typical phone photo)
+// Run it both ways to see what the "parallel" feature buys on your machine:
+//   cargo bench --bench filters
+//   cargo bench --bench filters --features parallel
+// (RAYON_NUM_THREADS=n limits the number of threads used by the parallel version)
+
+const WIDTH: u32 = 4000;
+const HEIGHT: u32 = 3000;
+
+fn main() {
+    let img = test_image();
+    println!("{}x{} image, best of 3 runs, {}", WIDTH, HEIGHT, mode());
+
+    let specs = [
+        FilterSpec::new("grayscale"),
+        FilterSpec::new("blur"),
+        FilterSpec::new("huerotate"),
+        FilterSpec::new("invert"),
+        FilterSpec::new("sepia"),
+        FilterSpec::new("pixelate"),
+        FilterSpec::new("emboss"),
+        FilterSpec::new("sharpen"),
+        FilterSpec::new("posterize"),
+        FilterSpec::new("posterize").with("dither", "ordered"),
+        FilterSpec::new("brightness"),
+        FilterSpec::new("contrast"),
+        FilterSpec::new("exposure"),
+        FilterSpec::new("gamma"),
+        FilterSpec::new("saturation"),
+        FilterSpec::new("vibrance"),
+        FilterSpec::new("levels"),
+        FilterSpec::new("curves"),
+        FilterSpec::new("equalize"),
+        FilterSpec::new("clahe"),
+        FilterSpec::new("edges"),
+        FilterSpec::new("laplacian"),
+        FilterSpec::new("canny"),
+        FilterSpec::new("morphology"),
+        FilterSpec::new("morphology").with("shape", "square").with("radius", 5),
+    ];
+    for spec in specs {
+        // Build the filter up front, so only the filtering itself is timed
+        let filter = Pipeline::new().then(spec.clone()).build().expect("built-in filters are valid").remove(0);
+        let time = time(|| filter.apply(&img));
+        println!("{:<56}{:>8.0} ms", spec.to_json(), time.as_secs_f64() * 1000.0);
+    }
+}
+
+#[cfg(feature = "parallel")]
+fn mode() -> String {
+    format!("parallel ({} threads)", rayon::current_num_threads())
+}
+
+#[cfg(not(feature = "parallel"))]
+fn mode() -> String {
+    "serial".to_string()
+}
+
+// A noisy gradient with some transparency, so every filter has real work to do
+fn test_image() -> RgbaImage {
+    RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        let noise = (x.wrapping_mul(7919) ^ y.wrapping_mul(104729)) % 64;
+        Rgba([(x % 256) as u8, (y % 256) as u8, noise as u8 * 4, ((x + y) % 256) as u8])
+    })
+}
+
+fn time<T>(run: impl Fn() -> T) -> Duration {
+    (0..3)
+        .map(|_| {
+            let start = Instant::now();
+            // black_box => stops the compiler from throwing the unused result (and the work) away
+            std::hint::black_box(run());
+            start.elapsed()
+        })
+        .min()
+        .expect("ran at least once")
+}
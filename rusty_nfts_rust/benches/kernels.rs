@@ -0,0 +1,81 @@
+use std::time::{Duration, Instant};
+
+use rusty_filter_rust::kernels::{self, scalar};
+
+// Times the kernels this build uses (SIMD, if it has them) against the scalar ones
+// (that they give exactly the same bytes is checked by the tests in src/kernels/mod.rs)
+//   cargo bench --bench kernels
+//   RUSTFLAGS='-C target-cpu=native' cargo bench --bench kernels   # AVX2, if your CPU has it
+//   cargo bench --bench kernels --no-default-features              # scalar against itself
+
+const WIDTH: usize = 4000;
+const HEIGHT: usize = 3000;
+
+fn main() {
+    println!("kernels: {}", kernels::BACKEND);
+
+    time_all();
+}
+
+fn time_all() {
+    println!("{}x{} image, best of 3 runs", WIDTH, HEIGHT);
+    let image = random_bytes(WIDTH * HEIGHT * 4, 42);
+    let steps = [85.0f32; 3];
+
+    time_pair("invert", &image, kernels::invert_row, scalar::invert_row);
+    time_pair("sepia", &image, kernels::sepia_row, scalar::sepia_row);
+    time_pair("posterize", &image, |row| kernels::posterize_row(row, steps, [0.0; 4]), |row| scalar::posterize_row(row, steps, [0.0; 4]));
+
+    // The convolution on the image as floats (each input row running 2 pixels into the next, for the padding)
+    let floats: Vec<f32> = image.iter().map(|&value| value as f32).collect();
+    let input_row = |y: usize| &floats[y * WIDTH * 4..(y * WIDTH + WIDTH + 2) * 4];
+    let sharpen = [0.0, -1.0, 0.0, -1.0, 5.0, -1.0, 0.0, -1.0, 0.0];
+    let convolve = |kernel: fn([&[f32]; 3], &[f32; 9], &mut [f32])| {
+        time(|| {
+            let mut out = vec![0.0f32; WIDTH * 4];
+            for y in 0..HEIGHT - 3 {
+                kernel([input_row(y), input_row(y + 1), input_row(y + 2)], &sharpen, &mut out);
+            }
+            out
+        })
+    };
+    print_times("convolve 3x3", convolve(kernels::convolve_3x3_row), convolve(scalar::convolve_3x3_row));
+}
+
+fn time_pair(name: &str, image: &[u8], kernel: impl Fn(&mut [u8]), reference: impl Fn(&mut [u8])) {
+    // The kernels work in place, so each run just carries on from the last one's result
+    // (copying the image each time took longer than some of the kernels)
+    let mut copy = image.to_vec();
+    let mut run = |f: &dyn Fn(&mut [u8])| time(|| copy.chunks_exact_mut(WIDTH * 4).for_each(f));
+    print_times(name, run(&kernel), run(&reference));
+}
+
+fn print_times(name: &str, kernel: Duration, reference: Duration) {
+    let ms = |time: Duration| time.as_secs_f64() * 1000.0;
+    println!("{:<16}{:>8.1} ms{:>8.1} ms (scalar){:>8.1}x", name, ms(kernel), ms(reference), ms(reference) / ms(kernel));
+}
+
+fn time<T>(mut run: impl FnMut() -> T) -> Duration {
+    (0..3)
+        .map(|_| {
+            let start = Instant::now();
+            // black_box => stops the compiler from throwing the unused result (and the work) away
+            std::hint::black_box(run());
+            start.elapsed()
+        })
+        .min()
+        .expect("ran at least once")
+}
+
+// A simple xorshift, so the 'random' bytes are the same every run (and there's no need for the rand crate)
+fn random_bytes(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 24) as u8
+        })
+        .collect()
+}
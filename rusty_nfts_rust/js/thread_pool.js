@@ -0,0 +1,43 @@
+// The JavaScript half of threads.rs (only part of the "wasm-threads" build)
+// wasm-bindgen copies this file into pkg/snippets/, next to the generated rusty_filter_rust.js
+//
+// This same file is also the script every pool worker runs: a worker started with the name below
+// waits for the module and memory, loads its own copy of the module on that (shared) memory,
+// then hands itself over to Rust as one of rayon's threads
+
+const WORKER_NAME = "rusty_filter_pool_worker";
+
+// Starts a worker for each of the pool's threads, then builds the pool once every one of them
+// has loaded the module and is waiting for its thread (see threads.rs)
+export async function startWorkers(module, memory, builder) {
+  const workers = [];
+  for (let i = 0; i < builder.numThreads(); i++) {
+    workers.push(
+      new Promise((resolve, reject) => {
+        const worker = new Worker(new URL(import.meta.url), { type: "module", name: WORKER_NAME });
+        worker.addEventListener("message", () => resolve(worker), { once: true });
+        worker.addEventListener("error", reject, { once: true });
+        worker.postMessage({ module, memory });
+      }),
+    );
+  }
+  const ready = await Promise.all(workers);
+  builder.build();
+  return ready;
+}
+
+// Only runs inside a pool worker
+if (typeof self !== "undefined" && self.name === WORKER_NAME) {
+  self.addEventListener(
+    "message",
+    async ({ data: { module, memory } }) => {
+      // pkg/snippets/<crate>/js/thread_pool.js => pkg/rusty_filter_rust.js
+      const pkg = await import("../../../rusty_filter_rust.js");
+      await pkg.default({ module_or_path: module, memory });
+      // Tell startWorkers we're ready, then wait for a rayon thread and become it (this never returns)
+      self.postMessage("ready");
+      pkg.runPoolWorker();
+    },
+    { once: true },
+  );
+}
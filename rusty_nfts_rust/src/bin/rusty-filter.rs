@@ -0,0 +1,517 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::Parser;
+use image::ImageFormat;
+use rusty_filter_rust::{try_apply_pipeline_with_output, try_register_lut, with_registry, FilterSpec, OutputFormat, OutputOptions, Pipeline};
+
+// Batch filters images from the command line, using exactly the same filters as the web app
+// e.g.
+//   rusty-filter --filter sepia --filter sharpen photos/ --out-dir filtered/
+//   rusty-filter --filter '{ "filter": "blur", "params": { "sigma": 2.5 } }' --format jpeg cat.png
+//   rusty-filter --pipeline look.json --recursive photos/ --name '{stem}.{ext}' --out-dir out/
+//   rusty-filter --lut teal_orange.cube --filter '{ "filter": "teal_orange", "params": { "intensity": 60 } }' photos/
+// A file that fails (can't be read, isn't an image, ...) is reported and skipped, the rest still get done
+#[derive(Parser)]
+#[command(name = "rusty-filter", version, about = "Applies filters to image files and folders of images")]
+struct Args {
+    #[arg(required_unless_present = "list_filters", help = "Image files, or folders of images, to filter")]
+    inputs: Vec<PathBuf>,
+
+    // Repeat it to chain filters, e.g. -f grayscale -f sharpen
+    #[arg(
+        short,
+        long = "filter",
+        value_name = "FILTER",
+        help = "A filter name (e.g. sepia) or a JSON filter spec (e.g. '{\"filter\": \"blur\", \"params\": {\"sigma\": 2}}'), repeat to chain filters"
+    )]
+    filters: Vec<String>,
+
+    #[arg(short, long, value_name = "FILE", conflicts_with = "filters", help = "A JSON pipeline file, i.e. { \"stages\": [...] }")]
+    pipeline: Option<PathBuf>,
+
+    // Registered before the pipeline is read, so --filter and --pipeline can use them
+    #[arg(
+        short,
+        long = "lut",
+        value_name = "FILE",
+        help = "A .cube LUT to add as a filter, named after the file (e.g. teal_orange.cube => teal_orange), repeat for more"
+    )]
+    luts: Vec<PathBuf>,
+
+    #[arg(short, long, value_name = "DIR", help = "Where to write the results (defaults to next to each input)")]
+    out_dir: Option<PathBuf>,
+
+    // OutputFormat implements FromStr, so clap can parse it straight from the argument
+    #[arg(long, default_value = "png", help = "png, jpeg, webp, bmp, gif, tiff or same_as_input")]
+    format: OutputFormat,
+
+    #[arg(short, long, default_value_t = 90, value_parser = clap::value_parser!(u8).range(1..=100), help = "JPEG quality (1 - 100)")]
+    quality: u8,
+
+    // Files inside a folder that match this pattern (e.g. cat_sepia.png from an earlier run) are skipped, see is_earlier_result
+    #[arg(
+        short,
+        long,
+        default_value = "{stem}_{filters}.{ext}",
+        help = "How to name the results: {stem} is the input name without its extension, {ext} the output extension, {filters} the filter names and {index} the file's number in the batch"
+    )]
+    name: String,
+
+    #[arg(short, long, help = "Look inside sub-folders too (the folder structure is kept under --out-dir)")]
+    recursive: bool,
+
+    #[arg(long, help = "Replace results that already exist (otherwise those files are reported as errors)")]
+    overwrite: bool,
+
+    #[arg(long, help = "List the available filters and their parameters, then exit")]
+    list_filters: bool,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    if let Err(e) = load_luts(&args) {
+        eprintln!("error: {}", e);
+        return ExitCode::from(2);
+    }
+
+    if args.list_filters {
+        print_filters();
+        return ExitCode::SUCCESS;
+    }
+
+    // Work out (and check) the pipeline before touching any files, so a typo fails straight away
+    let pipeline = match read_pipeline(&args) {
+        Ok(pipeline) => pipeline,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+    let options = OutputOptions::new(args.format).with_quality(args.quality);
+
+    // Every file written so far, so two inputs that end up with the same name (cat.png and cat.jpg) can't overwrite each other
+    let mut written_paths = HashSet::new();
+    let mut written = 0;
+    let mut failed = 0;
+    for (index, input) in find_inputs(&args, &pipeline).into_iter().enumerate() {
+        let result = input.and_then(|input| {
+            let output = filter_file(&args, &pipeline, &options, &input, index + 1, &mut written_paths)?;
+            Ok((input, output))
+        });
+        match result {
+            Ok((input, output)) => {
+                println!("{} -> {}", input.path.display(), output.display());
+                written += 1;
+            }
+            Err(e) => {
+                eprintln!("error: {}", e);
+                failed += 1;
+            }
+        }
+    }
+
+    eprintln!("{} written, {} failed", written, failed);
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+// Adds every --lut file to the filter registry
+fn load_luts(args: &Args) -> Result<(), Box<dyn Error>> {
+    for path in &args.luts {
+        let name = path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+        let cube = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        try_register_lut(&name, &cube).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+// Builds the pipeline from either --pipeline or the --filter options
+// (no filters at all is fine, that just converts the images to --format)
+fn read_pipeline(args: &Args) -> Result<Pipeline, Box<dyn Error>> {
+    let pipeline = match &args.pipeline {
+        Some(path) => {
+            let json = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            Pipeline::from_json(&json)?
+        }
+        None => {
+            let mut pipeline = Pipeline::new();
+            for filter in &args.filters {
+                // Anything that looks like JSON is a full spec, otherwise it's just a filter name
+                let spec = if filter.trim_start().starts_with('{') { FilterSpec::from_json(filter)? } else { FilterSpec::new(filter) };
+                pipeline = pipeline.then(spec);
+            }
+            pipeline
+        }
+    };
+    pipeline.validate()?;
+    Ok(pipeline)
+}
+
+// A file to filter, plus the folder it came from relative to the folder that was given
+// (so 'photos/2024/cat.png' from 'photos/' is written to '<out-dir>/2024/')
+struct Input {
+    path: PathBuf,
+    relative_dir: PathBuf,
+}
+
+// Every file named on the command line, plus every image inside the named folders
+// A path that can't be read shows up as an error, so it's reported without stopping the others
+// Images in the folders that look like results of an earlier run are left out (see is_earlier_result),
+// but a file named on the command line is always filtered
+fn find_inputs(args: &Args, pipeline: &Pipeline) -> Vec<Result<Input, Box<dyn Error>>> {
+    let is_result = |path: &Path| is_earlier_result(&args.name, path, pipeline);
+    let mut inputs = Vec::new();
+    for path in &args.inputs {
+        if path.is_dir() {
+            find_images(path, path, args.recursive, &is_result, &mut inputs);
+        } else {
+            inputs.push(Ok(Input { path: path.clone(), relative_dir: PathBuf::new() }));
+        }
+    }
+    inputs
+}
+
+fn find_images(root: &Path, dir: &Path, recursive: bool, is_result: &dyn Fn(&Path) -> bool, inputs: &mut Vec<Result<Input, Box<dyn Error>>>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            inputs.push(Err(format!("{}: {}", dir.display(), e).into()));
+            return;
+        }
+    };
+
+    // Sorted, so the batch (and {index}) comes out in the same order every time
+    let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+    paths.sort();
+
+    for path in paths {
+        if path.is_dir() {
+            if recursive {
+                find_images(root, &path, recursive, is_result, inputs);
+            }
+        } else if is_result(&path) {
+            eprintln!("skipping {}: looks like the result of an earlier run (name it on the command line to filter it anyway)", path.display());
+        } else if ImageFormat::from_path(&path).is_ok() {
+            // Only pick up files with an image extension, so a stray .txt or .DS_Store isn't an 'error'
+            let relative_dir = dir.strip_prefix(root).unwrap_or(Path::new("")).to_path_buf();
+            inputs.push(Ok(Input { path, relative_dir }));
+        }
+    }
+}
+
+// Reads, filters and writes one file, returning where the result went
+// 'written_paths' is every file written earlier in the batch, this one is added to it
+fn filter_file(
+    args: &Args,
+    pipeline: &Pipeline,
+    options: &OutputOptions,
+    input: &Input,
+    index: usize,
+    written_paths: &mut HashSet<PathBuf>,
+) -> Result<PathBuf, Box<dyn Error>> {
+    // Every error gets the file name in front of it, so you can tell which file it was about
+    let with_path = |e: &dyn std::fmt::Display| format!("{}: {}", input.path.display(), e);
+
+    let bytes = fs::read(&input.path).map_err(|e| with_path(&e))?;
+    let encoded = try_apply_pipeline_with_output(&bytes, pipeline, options).map_err(|e| with_path(&e))?;
+
+    let file_name = output_name(&args.name, &input.path, &encoded.extension(), pipeline, index);
+    let dir = match &args.out_dir {
+        Some(out_dir) => out_dir.join(&input.relative_dir),
+        None => input.path.parent().map(Path::to_path_buf).unwrap_or_default(),
+    };
+    let output = dir.join(file_name);
+
+    // Even --overwrite doesn't replace a result from this batch, that would silently lose one of the two
+    if written_paths.contains(&output) {
+        let reason = format!("{} was already written by an earlier file in this batch (add {{index}} to --name to tell them apart)", output.display());
+        return Err(with_path(&reason).into());
+    }
+    if output.exists() && !args.overwrite {
+        return Err(with_path(&format!("{} already exists (use --overwrite to replace it)", output.display())).into());
+    }
+    fs::create_dir_all(&dir).map_err(|e| with_path(&e))?;
+    fs::write(&output, encoded.into_bytes()).map_err(|e| with_path(&e))?;
+    written_paths.insert(output.clone());
+    Ok(output)
+}
+
+// Fills in the --name pattern, e.g. "{stem}_{filters}.{ext}" => "cat_sepia-sharpen.png"
+fn output_name(pattern: &str, input: &Path, extension: &str, pipeline: &Pipeline, index: usize) -> String {
+    let stem = input.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+
+    pattern
+        .replace("{stem}", &stem)
+        .replace("{ext}", extension)
+        .replace("{filters}", &filters_name(pipeline))
+        .replace("{index}", &index.to_string())
+}
+
+// What {filters} becomes, e.g. "sepia-sharpen" (or "converted" when there aren't any)
+fn filters_name(pipeline: &Pipeline) -> String {
+    let filters: Vec<&str> = pipeline.stages.iter().map(|stage| stage.filter.as_str()).collect();
+    if filters.is_empty() { "converted".to_string() } else { filters.join("-") }
+}
+
+// The pieces of a --name pattern, with {filters} already filled in (it's the same for every file)
+#[derive(Debug, Clone, PartialEq)]
+enum NamePart {
+    Text(String),
+    Stem,
+    Extension,
+    Index,
+}
+
+fn name_parts(pattern: &str, pipeline: &Pipeline) -> Vec<NamePart> {
+    let pattern = pattern.replace("{filters}", &filters_name(pipeline));
+    let placeholders = [("{stem}", NamePart::Stem), ("{ext}", NamePart::Extension), ("{index}", NamePart::Index)];
+
+    let mut parts = Vec::new();
+    let mut rest = pattern.as_str();
+    // Split off the text up to the next placeholder, then the placeholder itself, until there are none left
+    while let Some((start, placeholder, part)) =
+        placeholders.iter().filter_map(|(placeholder, part)| Some((rest.find(placeholder)?, placeholder, part))).min_by_key(|(start, ..)| *start)
+    {
+        if start > 0 {
+            parts.push(NamePart::Text(rest[..start].to_string()));
+        }
+        parts.push(part.clone());
+        rest = &rest[start + placeholder.len()..];
+    }
+    if !rest.is_empty() {
+        parts.push(NamePart::Text(rest.to_string()));
+    }
+    parts
+}
+
+// Whether a file could have been written by a run with this --name pattern and pipeline,
+// e.g. cat_sepia.png for "{stem}_{filters}.{ext}" with a sepia filter
+// Without this, running the same command on a folder twice would filter the first run's results again (cat_sepia_sepia.png)
+// {ext} can be any image extension (so --format same_as_input is covered), {stem} any name and {index} any number
+// A pattern that doesn't add anything to the name ({stem}.{ext}) can't tell results from originals, so nothing is skipped then
+fn is_earlier_result(pattern: &str, path: &Path, pipeline: &Pipeline) -> bool {
+    let parts = name_parts(pattern, pipeline);
+    if parts == [NamePart::Stem, NamePart::Text(".".to_string()), NamePart::Extension] {
+        return false;
+    }
+    let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    matches_parts(&parts, file_name)
+}
+
+// Tries every way of splitting 'name' between the parts (file names are short, so this is quick enough)
+fn matches_parts(parts: &[NamePart], name: &str) -> bool {
+    match parts.split_first() {
+        None => name.is_empty(),
+        Some((NamePart::Text(text), rest)) => name.strip_prefix(text.as_str()).is_some_and(|name| matches_parts(rest, name)),
+        Some((part, rest)) => (1..=name.len()).filter(|&end| name.is_char_boundary(end)).any(|end| {
+            let (value, name) = name.split_at(end);
+            let fits = match part {
+                NamePart::Extension => ImageFormat::from_extension(value).is_some(),
+                NamePart::Index => value.bytes().all(|byte| byte.is_ascii_digit()),
+                _ => true,
+            };
+            fits && matches_parts(rest, name)
+        }),
+    }
+}
+
+// e.g. "blur (Blur): sigma, method, alpha"
+fn print_filters() {
+    with_registry(|registry| {
+        for filter in registry.filters() {
+            let params: Vec<&str> = filter.params().iter().map(|param| param.name).collect();
+            if params.is_empty() {
+                println!("{} ({})", filter.name(), filter.label());
+            } else {
+                println!("{} ({}): {}", filter.name(), filter.label(), params.join(", "));
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn args(args: &[&str]) -> Args {
+        Args::try_parse_from(["rusty-filter"].iter().chain(args)).unwrap()
+    }
+
+    fn sepia() -> Pipeline {
+        Pipeline::new().then(FilterSpec::new("sepia"))
+    }
+
+    // A fresh, empty folder for one test (the process id keeps two test runs apart)
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rusty-filter-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // A tiny image, in whatever format the extension says
+    fn write_image(path: &Path) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        RgbImage::from_pixel(2, 2, Rgb([200, 100, 50])).save(path).unwrap();
+    }
+
+    fn found(args: &Args, pipeline: &Pipeline) -> Vec<(PathBuf, PathBuf)> {
+        find_inputs(args, pipeline).into_iter().map(|input| input.unwrap()).map(|input| (input.path, input.relative_dir)).collect()
+    }
+
+    #[test]
+    fn placeholders() {
+        let input = Path::new("photos/cat.jpeg");
+        let pipeline = sepia().then(FilterSpec::new("sharpen"));
+        assert_eq!(output_name("{stem}", input, "png", &pipeline, 3), "cat");
+        assert_eq!(output_name("{ext}", input, "png", &pipeline, 3), "png");
+        assert_eq!(output_name("{filters}", input, "png", &pipeline, 3), "sepia-sharpen");
+        assert_eq!(output_name("{index}", input, "png", &pipeline, 3), "3");
+        assert_eq!(output_name("{stem}_{filters}.{ext}", input, "png", &pipeline, 3), "cat_sepia-sharpen.png");
+        assert_eq!(output_name("{index}-{stem}-{index}.{ext}", input, "jpg", &pipeline, 12), "12-cat-12.jpg");
+        assert_eq!(output_name("result.png", input, "jpg", &pipeline, 1), "result.png");
+    }
+
+    // No filters at all just converts the image
+    #[test]
+    fn empty_pipeline() {
+        assert_eq!(output_name("{stem}_{filters}.{ext}", Path::new("cat.png"), "webp", &Pipeline::new(), 1), "cat_converted.webp");
+    }
+
+    #[test]
+    fn earlier_results() {
+        let is_result = |pattern: &str, name: &str| is_earlier_result(pattern, Path::new(name), &sepia());
+        assert!(is_result("{stem}_{filters}.{ext}", "cat_sepia.png"));
+        assert!(is_result("{stem}_{filters}.{ext}", "my_cat_sepia.jpg"));
+        assert!(is_result("{stem}_{filters}.{ext}", "cat_sepia_sepia.tiff"));
+        assert!(!is_result("{stem}_{filters}.{ext}", "cat.png"));
+        assert!(!is_result("{stem}_{filters}.{ext}", "cat_sepia.txt"));
+        assert!(!is_result("{stem}_{filters}.{ext}", "cat_blur.png"));
+        assert!(!is_result("{stem}_{filters}.{ext}", "_sepia.png"), "{{stem}} can't be empty");
+        assert!(is_result("{index}-{stem}.{ext}", "12-cat.png"));
+        assert!(!is_result("{index}-{stem}.{ext}", "x2-cat.png"));
+        assert!(!is_result("out/{stem}.{ext}", "out/cat.png"), "only the file name is matched");
+        // Can't tell these apart from the originals
+        assert!(!is_result("{stem}.{ext}", "cat.png"));
+    }
+
+    // Flat only looks in the folder itself, recursive in every sub-folder too (remembering which one)
+    // Files that aren't images, and earlier results, are left out unless they're named on the command line
+    #[test]
+    fn scanning() {
+        let dir = temp_dir("scanning");
+        for name in ["b.png", "a.jpg", "a_sepia.png", "2024/c.png", "2024/deep/d.gif", "2024/c_sepia.png"] {
+            write_image(&dir.join(name));
+        }
+        fs::write(dir.join("notes.txt"), "not an image").unwrap();
+        let root = dir.to_str().unwrap();
+
+        assert_eq!(found(&args(&["-f", "sepia", root]), &sepia()), [
+            (dir.join("a.jpg"), PathBuf::new()),
+            (dir.join("b.png"), PathBuf::new()),
+        ]);
+        assert_eq!(found(&args(&["-f", "sepia", "--recursive", root]), &sepia()), [
+            (dir.join("2024/c.png"), PathBuf::from("2024")),
+            (dir.join("2024/deep/d.gif"), PathBuf::from("2024/deep")),
+            (dir.join("a.jpg"), PathBuf::new()),
+            (dir.join("b.png"), PathBuf::new()),
+        ]);
+        // With another filter, a_sepia.png is just an image
+        let blur = Pipeline::new().then(FilterSpec::new("blur"));
+        assert_eq!(found(&args(&["-f", "blur", root]), &blur).len(), 3);
+        // Named on the command line, anything goes
+        let named = dir.join("a_sepia.png");
+        assert_eq!(found(&args(&["-f", "sepia", named.to_str().unwrap()]), &sepia()), [(named, PathBuf::new())]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Sub-folders are recreated under --out-dir, and without one the result goes next to its input
+    #[test]
+    fn output_folders() {
+        let dir = temp_dir("output-folders");
+        write_image(&dir.join("in/2024/cat.png"));
+        let out_dir = dir.join("out");
+        let into_out_dir = args(&["-f", "sepia", "-r", dir.join("in").to_str().unwrap(), "--out-dir", out_dir.to_str().unwrap()]);
+        let options = OutputOptions::new(OutputFormat::Png);
+
+        let mut written_paths = HashSet::new();
+        for (index, input) in find_inputs(&into_out_dir, &sepia()).into_iter().enumerate() {
+            let output = filter_file(&into_out_dir, &sepia(), &options, &input.unwrap(), index + 1, &mut written_paths).unwrap();
+            assert_eq!(output, out_dir.join("2024/cat_sepia.png"));
+            assert!(output.is_file());
+        }
+
+        let next_to_input = args(&["-f", "sepia", dir.join("in").to_str().unwrap()]);
+        let input = Input { path: dir.join("in/2024/cat.png"), relative_dir: PathBuf::from("2024") };
+        let output = filter_file(&next_to_input, &sepia(), &options, &input, 1, &mut HashSet::new()).unwrap();
+        assert_eq!(output, dir.join("in/2024/cat_sepia.png"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn overwriting() {
+        let dir = temp_dir("overwriting");
+        write_image(&dir.join("cat.png"));
+        let input = Input { path: dir.join("cat.png"), relative_dir: PathBuf::new() };
+        let options = OutputOptions::new(OutputFormat::Png);
+
+        let run = |extra: &[&str]| {
+            let args = args(&[&["-f", "sepia", dir.to_str().unwrap()], extra].concat());
+            filter_file(&args, &sepia(), &options, &input, 1, &mut HashSet::new())
+        };
+        let output = run(&[]).unwrap();
+        fs::write(&output, "keep me").unwrap();
+        let error = run(&[]).unwrap_err().to_string();
+        assert!(error.contains("already exists"), "{}", error);
+        assert_eq!(fs::read(&output).unwrap(), b"keep me");
+        assert_eq!(run(&["--overwrite"]).unwrap(), output);
+        assert!(image::open(&output).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // cat.png and cat.jpg both become cat_sepia.png: the second one fails rather than replacing the first
+    #[test]
+    fn same_name_in_one_batch() {
+        let dir = temp_dir("same-name");
+        write_image(&dir.join("cat.jpg"));
+        write_image(&dir.join("cat.png"));
+        for extra in [&[][..], &["--overwrite"]] {
+            let args = args(&[&["-f", "sepia", dir.to_str().unwrap()], extra].concat());
+            let options = OutputOptions::new(args.format);
+            let mut written_paths = HashSet::new();
+            let results: Vec<_> = find_inputs(&args, &sepia())
+                .into_iter()
+                .enumerate()
+                .map(|(index, input)| filter_file(&args, &sepia(), &options, &input.unwrap(), index + 1, &mut written_paths))
+                .collect();
+            assert_eq!(*results[0].as_ref().unwrap(), dir.join("cat_sepia.png"));
+            let error = results[1].as_ref().unwrap_err().to_string();
+            assert!(error.contains("already written by an earlier file"), "{}", error);
+            fs::remove_file(dir.join("cat_sepia.png")).unwrap();
+        }
+
+        // {index} tells them apart
+        let args = args(&["-f", "sepia", "--name", "{stem}_{index}.{ext}", dir.to_str().unwrap()]);
+        let mut written_paths = HashSet::new();
+        for (index, input) in find_inputs(&args, &sepia()).into_iter().enumerate() {
+            filter_file(&args, &sepia(), &OutputOptions::new(args.format), &input.unwrap(), index + 1, &mut written_paths).unwrap();
+        }
+        assert_eq!(written_paths, HashSet::from([dir.join("cat_1.png"), dir.join("cat_2.png")]));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
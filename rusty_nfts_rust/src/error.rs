@@ -0,0 +1,80 @@
+use std::fmt;
+use image::ImageError;
+
+// Every way that filtering an image can go wrong
+// Native Rust callers get this as a normal 'std::error::Error'
+// and wasm-bindgen turns it into a JavaScript 'Error' (with the message below) for the React app
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterError {
+    // The bytes looked like an image format we know, but the data inside was broken
+    Decode(String),
+    // We couldn't work out what the image format was, or we can't read/write that format
+    UnsupportedFormat(String),
+    // The filter name didn't match any filter we know about
+    UnknownFilter(String),
+    // A filter was given a parameter outside of its allowed range
+    InvalidParameter { name: String, reason: String },
+    // Turning the processed pixels back into an image file failed
+    Encode(String),
+    // The image has more pixels than we are willing to hold in memory
+    ImageTooLarge { width: u32, height: u32, max_pixels: u64 },
+    // The image crate's own limits (e.g. a single allocation it won't make) were hit while decoding
+    LimitsExceeded(String),
+    // Reading the image's bytes failed, e.g. the file ended in the middle of the pixel data
+    Io(String),
+    // A .cube LUT file couldn't be read (the reason says which line, if it was one line)
+    InvalidLut(String),
+}
+
+// Display => the human readable message (this is what ends up in the UI)
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterError::Decode(reason) => write!(f, "Failed to decode image: {}", reason),
+            FilterError::UnsupportedFormat(reason) => write!(f, "Unsupported image format: {}", reason),
+            FilterError::UnknownFilter(name) => write!(f, "Unknown filter '{}'", name),
+            FilterError::InvalidParameter { name, reason } => {
+                write!(f, "Invalid value for parameter '{}': {}", name, reason)
+            }
+            FilterError::Encode(reason) => write!(f, "Failed to encode image: {}", reason),
+            FilterError::ImageTooLarge { width, height, max_pixels } => write!(
+                f,
+                "Image is too large ({}x{}), the maximum is {} pixels",
+                width, height, max_pixels
+            ),
+            FilterError::LimitsExceeded(reason) => write!(f, "Image exceeds the decoder's limits: {}", reason),
+            FilterError::Io(reason) => write!(f, "Failed to read image: {}", reason),
+            FilterError::InvalidLut(reason) => write!(f, "Failed to read LUT: {}", reason),
+        }
+    }
+}
+
+// An empty impl is enough, Debug + Display give us everything 'Error' needs
+impl std::error::Error for FilterError {}
+
+// Lets us use '?' on anything from the image crate while decoding
+// (encoding errors are mapped by hand, so they end up as 'Encode' rather than 'Decode')
+impl From<ImageError> for FilterError {
+    fn from(err: ImageError) -> Self {
+        match err {
+            ImageError::Unsupported(e) => FilterError::UnsupportedFormat(e.to_string()),
+            ImageError::Limits(e) => FilterError::LimitsExceeded(e.to_string()),
+            ImageError::IoError(e) => FilterError::Io(e.to_string()),
+            other => FilterError::Decode(other.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::error::{LimitError, LimitErrorKind};
+
+    #[test]
+    fn image_errors() {
+        let limits = ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory));
+        assert!(matches!(FilterError::from(limits), FilterError::LimitsExceeded(_)));
+        let io = ImageError::IoError(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+        assert!(matches!(FilterError::from(io), FilterError::Io(_)));
+    }
+}
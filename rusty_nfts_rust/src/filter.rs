@@ -0,0 +1,32 @@
+use image::RgbaImage;
+
+use crate::error::FilterError;
+use crate::spec::{ParamInfo, Params};
+
+// Anything that can filter an image
+// Every built-in filter implements this, and other crates can implement it too and
+// register their own filters at startup (see register_filter)
+//
+// A filter value is always fully configured: the one stored in the registry uses the default
+// parameters, and configure() makes a new one with the parameters from a FilterSpec
+// 'Send + Sync' => safe to share between threads (the registry is a global)
+pub trait Filter: Send + Sync {
+    // The name used in FilterSpecs and JSON, e.g. "sepia"
+    fn name(&self) -> &str;
+
+    // A nicer name for the UI, defaults to the name
+    fn label(&self) -> &str {
+        self.name()
+    }
+
+    // The parameter schema, i.e. which knobs this filter has (defaults to none)
+    fn params(&self) -> &[ParamInfo] {
+        &[]
+    }
+
+    // Makes a copy of this filter that uses the given (already validated) parameters
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError>;
+
+    // Does the actual filtering, returning a new image
+    fn apply(&self, img: &RgbaImage) -> RgbaImage;
+}
@@ -0,0 +1,179 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Serialize, Serializer};
+use wasm_bindgen::prelude::*;
+
+use crate::error::FilterError;
+
+// Every built-in filter, as a proper type instead of a loose string
+// The built-in filters in the registry take their names from here, so native code can
+// refer to them without stringly typed names (and a typo like "sephia" won't compile)
+// #[wasm_bindgen] on an enum => JavaScript gets it as 'FilterKind.Sepia' etc.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FilterKind {
+    Grayscale,
+    Blur,
+    HueRotate,
+    Invert,
+    Sepia,
+    Pixelate,
+    Emboss,
+    Sharpen,
+    Posterize,
+    Convolve,
+    Brightness,
+    Contrast,
+    Exposure,
+    Gamma,
+    Saturation,
+    Vibrance,
+    Levels,
+    Curves,
+    Equalize,
+    Clahe,
+    EdgeDetect,
+    Laplacian,
+    Canny,
+    Morphology,
+}
+
+impl FilterKind {
+    // All of the filters, in the order they should be shown to the user
+    pub const ALL: [FilterKind; 24] = [
+        FilterKind::Grayscale,
+        FilterKind::Blur,
+        FilterKind::HueRotate,
+        FilterKind::Invert,
+        FilterKind::Sepia,
+        FilterKind::Pixelate,
+        FilterKind::Emboss,
+        FilterKind::Sharpen,
+        FilterKind::Posterize,
+        FilterKind::Convolve,
+        FilterKind::Brightness,
+        FilterKind::Contrast,
+        FilterKind::Exposure,
+        FilterKind::Gamma,
+        FilterKind::Saturation,
+        FilterKind::Vibrance,
+        FilterKind::Levels,
+        FilterKind::Curves,
+        FilterKind::Equalize,
+        FilterKind::Clahe,
+        FilterKind::EdgeDetect,
+        FilterKind::Laplacian,
+        FilterKind::Canny,
+        FilterKind::Morphology,
+    ];
+
+    // The name used by JavaScript (and by FromStr below)
+    // &'static str => a string that lives for the whole program (it's baked into the binary)
+    pub fn name(self) -> &'static str {
+        match self {
+            FilterKind::Grayscale => "grayscale",
+            FilterKind::Blur => "blur",
+            FilterKind::HueRotate => "huerotate",
+            FilterKind::Invert => "invert",
+            FilterKind::Sepia => "sepia",
+            FilterKind::Pixelate => "pixelate",
+            FilterKind::Emboss => "emboss",
+            FilterKind::Sharpen => "sharpen",
+            FilterKind::Posterize => "posterize",
+            FilterKind::Convolve => "convolve",
+            FilterKind::Brightness => "brightness",
+            FilterKind::Contrast => "contrast",
+            FilterKind::Exposure => "exposure",
+            FilterKind::Gamma => "gamma",
+            FilterKind::Saturation => "saturation",
+            FilterKind::Vibrance => "vibrance",
+            FilterKind::Levels => "levels",
+            FilterKind::Curves => "curves",
+            FilterKind::Equalize => "equalize",
+            FilterKind::Clahe => "clahe",
+            FilterKind::EdgeDetect => "edges",
+            FilterKind::Laplacian => "laplacian",
+            FilterKind::Canny => "canny",
+            FilterKind::Morphology => "morphology",
+        }
+    }
+
+    // A nicer name for showing in the UI
+    pub fn label(self) -> &'static str {
+        match self {
+            FilterKind::Grayscale => "Grayscale",
+            FilterKind::Blur => "Blur",
+            FilterKind::HueRotate => "Hue Rotate",
+            FilterKind::Invert => "Invert Colors",
+            FilterKind::Sepia => "Sepia",
+            FilterKind::Pixelate => "Pixelate",
+            FilterKind::Emboss => "Emboss",
+            FilterKind::Sharpen => "Sharpen",
+            FilterKind::Posterize => "Posterize",
+            FilterKind::Convolve => "Custom Kernel",
+            FilterKind::Brightness => "Brightness",
+            FilterKind::Contrast => "Contrast",
+            FilterKind::Exposure => "Exposure",
+            FilterKind::Gamma => "Gamma",
+            FilterKind::Saturation => "Saturation",
+            FilterKind::Vibrance => "Vibrance",
+            FilterKind::Levels => "Levels",
+            FilterKind::Curves => "Curves",
+            FilterKind::Equalize => "Equalize",
+            FilterKind::Clahe => "Local Contrast (CLAHE)",
+            FilterKind::EdgeDetect => "Edge Detect",
+            FilterKind::Laplacian => "Laplacian of Gaussian",
+            FilterKind::Canny => "Canny Edges",
+            FilterKind::Morphology => "Morphology",
+        }
+    }
+}
+
+// FromStr => lets us do "sepia".parse::<FilterKind>()
+impl FromStr for FilterKind {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Look through every filter for one with a matching name
+        FilterKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| FilterError::UnknownFilter(s.to_string()))
+    }
+}
+
+impl fmt::Display for FilterKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// In JSON a FilterKind is just its name, e.g. "sepia"
+// There's no Deserialize: FilterSpec reads the name as a String (so other crates' filters work too), use parse() instead
+impl Serialize for FilterKind {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+        for kind in FilterKind::ALL {
+            assert_eq!(kind.name().parse::<FilterKind>(), Ok(kind));
+            assert_eq!(kind.to_string(), kind.name());
+            assert_eq!(serde_json::to_string(&kind).unwrap(), format!("\"{}\"", kind.name()));
+        }
+    }
+
+    #[test]
+    fn unknown_names() {
+        for name in ["sephia", "Sepia", " sepia", ""] {
+            assert_eq!(name.parse::<FilterKind>(), Err(FilterError::UnknownFilter(name.to_string())));
+        }
+    }
+}
@@ -0,0 +1,396 @@
+use image::{Rgba, RgbaImage};
+
+use super::alpha::to_channel;
+use crate::error::FilterError;
+use crate::filter::Filter;
+use crate::filter_kind::FilterKind;
+use crate::parallel::map_pixels;
+use crate::spec::{ParamInfo, Params};
+
+// The basic photo 'adjustments': fixing the tones and colours of a photo, rather than giving it a look
+// Each one is its own filter, so they can be chained (e.g. exposure => contrast => saturation => sepia)
+// They all leave alpha as it is
+//
+// Brightness, contrast, exposure and gamma change each channel on its own, without looking at the other two,
+// so they work out all 256 possible results up front (a 'lookup table') and then just look every value up
+
+// Brightness, contrast, saturation and vibrance go from -100 to 100 (0 => no change)
+const BRIGHTNESS_PARAMS: [ParamInfo; 1] = [ParamInfo::number("amount", "Amount", -100.0, 100.0, 20.0, 1.0)];
+const CONTRAST_PARAMS: [ParamInfo; 1] = [ParamInfo::number("amount", "Amount", -100.0, 100.0, 20.0, 1.0)];
+// In 'stops' like a camera: +1 => twice the light, -1 => half
+const EXPOSURE_PARAMS: [ParamInfo; 1] = [ParamInfo::number("stops", "Stops", -5.0, 5.0, 1.0, 0.1)];
+// 1 => no change, above 1 => lighter midtones, below 1 => darker
+const GAMMA_PARAMS: [ParamInfo; 1] = [ParamInfo::number("gamma", "Gamma", 0.1, 10.0, 1.5, 0.05)];
+const SATURATION_PARAMS: [ParamInfo; 1] = [ParamInfo::number("amount", "Amount", -100.0, 100.0, 50.0, 1.0)];
+const VIBRANCE_PARAMS: [ParamInfo; 1] = [ParamInfo::number("amount", "Amount", -100.0, 100.0, 50.0, 1.0)];
+
+// Lightens (or darkens) every colour by the same amount
+// 100 => adds 255 (everything white), -100 => takes 255 away (everything black)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Brightness {
+    pub amount: f32,
+}
+
+impl Default for Brightness {
+    fn default() -> Self {
+        Brightness { amount: 20.0 }
+    }
+}
+
+impl Filter for Brightness {
+    fn name(&self) -> &str {
+        FilterKind::Brightness.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Brightness.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &BRIGHTNESS_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        Ok(Box::new(Brightness { amount: params.number("amount")? as f32 }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        let offset = self.amount / 100.0 * 255.0;
+        apply_lut(img, &lookup_table(|value| value + offset))
+    }
+}
+
+// Pushes colours away from (or, below 0, pulls them towards) mid grey
+// 100 => everything is either black or white, -100 => everything is mid grey
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contrast {
+    pub amount: f32,
+}
+
+impl Default for Contrast {
+    fn default() -> Self {
+        Contrast { amount: 20.0 }
+    }
+}
+
+impl Filter for Contrast {
+    fn name(&self) -> &str {
+        FilterKind::Contrast.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Contrast.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &CONTRAST_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        Ok(Box::new(Contrast { amount: params.number("amount")? as f32 }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        // How much to stretch the distance from mid grey by
+        // Above 0 it grows faster towards the end (50 => x2, 90 => x10) so 100 can reach 'infinite' contrast,
+        // below 0 it shrinks evenly (-50 => x0.5)
+        let amount = (self.amount / 100.0).clamp(-1.0, 1.0);
+        let factor = if amount > 0.0 { 1.0 / (1.0 - amount).max(1e-3) } else { 1.0 + amount };
+        apply_lut(img, &lookup_table(|value| (value - 127.5) * factor + 127.5))
+    }
+}
+
+// Like changing a camera's exposure: multiplies the amount of light by 2^stops
+// Done in linear light (see srgb_to_linear), so +1 stop looks like twice the light rather than just 'brighter'
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Exposure {
+    pub stops: f32,
+}
+
+impl Default for Exposure {
+    fn default() -> Self {
+        Exposure { stops: 1.0 }
+    }
+}
+
+impl Filter for Exposure {
+    fn name(&self) -> &str {
+        FilterKind::Exposure.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Exposure.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &EXPOSURE_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        Ok(Box::new(Exposure { stops: params.number("stops")? as f32 }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        let scale = 2.0f32.powf(self.stops);
+        apply_lut(img, &lookup_table(|value| linear_to_srgb(srgb_to_linear(value / 255.0) * scale) * 255.0))
+    }
+}
+
+// Bends the midtones without moving black or white: value = value ^ (1 / gamma) (on a 0 - 1 scale)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gamma {
+    pub gamma: f32,
+}
+
+impl Default for Gamma {
+    fn default() -> Self {
+        Gamma { gamma: 1.5 }
+    }
+}
+
+impl Filter for Gamma {
+    fn name(&self) -> &str {
+        FilterKind::Gamma.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Gamma.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &GAMMA_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        Ok(Box::new(Gamma { gamma: params.number("gamma")? as f32 }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        // .max() => a gamma of 0 (only possible from Rust, Params keeps it above 0.1) would divide by zero
+        let exponent = 1.0 / self.gamma.max(1e-3);
+        apply_lut(img, &lookup_table(|value| (value / 255.0).powf(exponent) * 255.0))
+    }
+}
+
+// Makes every colour more (or less) colourful
+// -100 => grayscale, 100 => twice as far from grey as it was
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Saturation {
+    pub amount: f32,
+}
+
+impl Default for Saturation {
+    fn default() -> Self {
+        Saturation { amount: 50.0 }
+    }
+}
+
+impl Filter for Saturation {
+    fn name(&self) -> &str {
+        FilterKind::Saturation.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Saturation.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &SATURATION_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        Ok(Box::new(Saturation { amount: params.number("amount")? as f32 }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        let factor = 1.0 + self.amount / 100.0;
+        map_pixels(img, |_, _, pixel| saturate(pixel, factor))
+    }
+}
+
+// A 'smarter' saturation: dull colours change a lot, colours that are already strong hardly change at all
+// So it can liven up a photo without turning skin tones orange (or, below 0, mute it without going grey)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vibrance {
+    pub amount: f32,
+}
+
+impl Default for Vibrance {
+    fn default() -> Self {
+        Vibrance { amount: 50.0 }
+    }
+}
+
+impl Filter for Vibrance {
+    fn name(&self) -> &str {
+        FilterKind::Vibrance.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Vibrance.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &VIBRANCE_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        Ok(Box::new(Vibrance { amount: params.number("amount")? as f32 }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        let amount = self.amount / 100.0;
+        map_pixels(img, |_, _, pixel| {
+            // How colourful the pixel already is, from 0 (grey) to 1 (a pure colour)
+            let Rgba([r, g, b, _]) = pixel;
+            let saturation = (r.max(g).max(b) - r.min(g).min(b)) as f32 / 255.0;
+            saturate(pixel, 1.0 + amount * (1.0 - saturation))
+        })
+    }
+}
+
+// Moves the colour away from (factor above 1) or towards (below 1) its own grey value
+fn saturate(Rgba([r, g, b, a]): Rgba<u8>, factor: f32) -> Rgba<u8> {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    // The Rec. 709 grey, the same as Grayscale's default
+    let grey = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let channel = |value: f32| to_channel(grey + (value - grey) * factor);
+    Rgba([channel(r), channel(g), channel(b), a])
+}
+
+// The result for every possible channel value (0 - 255), rounded and clamped
+pub(crate) fn lookup_table(adjust: impl Fn(f32) -> f32) -> [u8; 256] {
+    std::array::from_fn(|value| to_channel(adjust(value as f32)))
+}
+
+// Looks up the new red, green and blue of every pixel in the table
+fn apply_lut(img: &RgbaImage, lut: &[u8; 256]) -> RgbaImage {
+    apply_luts(img, &[*lut; 3])
+}
+
+// The same with a different table for each of red, green and blue
+pub(crate) fn apply_luts(img: &RgbaImage, [red, green, blue]: &[[u8; 256]; 3]) -> RgbaImage {
+    map_pixels(img, |_, _, Rgba([r, g, b, a])| Rgba([red[r as usize], green[g as usize], blue[b as usize], a]))
+}
+
+// Image files store 'sRGB' values, which are spaced out the way our eyes see brightness rather than
+// by the actual amount of light (so 128 is about a fifth of the light of 255, not half)
+// These convert between the two (on a 0 - 1 scale), for maths that needs real amounts of light
+pub(crate) fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub(crate) fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every channel value from 0 to 255 along a row, with the colours mixed up and a different alpha on each row
+    fn ramp() -> RgbaImage {
+        RgbaImage::from_fn(256, 3, |x, y| Rgba([x as u8, 255 - x as u8, (x as u8).wrapping_mul(7), [255, 128, 0][y as usize]]))
+    }
+
+    // Every adjustment at a few settings, including the extremes
+    fn adjustments() -> Vec<Box<dyn Filter>> {
+        let mut filters: Vec<Box<dyn Filter>> = Vec::new();
+        for amount in [-100.0, -30.0, 45.0, 100.0] {
+            filters.push(Box::new(Brightness { amount }));
+            filters.push(Box::new(Contrast { amount }));
+            filters.push(Box::new(Saturation { amount }));
+            filters.push(Box::new(Vibrance { amount }));
+        }
+        for stops in [-5.0, -0.5, 2.0, 5.0] {
+            filters.push(Box::new(Exposure { stops }));
+        }
+        for gamma in [0.1, 0.7, 2.2, 10.0] {
+            filters.push(Box::new(Gamma { gamma }));
+        }
+        filters
+    }
+
+    #[test]
+    fn no_change() {
+        let img = ramp();
+        let filters: [Box<dyn Filter>; 6] = [
+            Box::new(Brightness { amount: 0.0 }),
+            Box::new(Contrast { amount: 0.0 }),
+            Box::new(Exposure { stops: 0.0 }),
+            Box::new(Gamma { gamma: 1.0 }),
+            Box::new(Saturation { amount: 0.0 }),
+            Box::new(Vibrance { amount: 0.0 }),
+        ];
+        for filter in filters {
+            assert_eq!(filter.apply(&img), img, "{}", filter.name());
+        }
+    }
+
+    #[test]
+    fn brightness_extremes() {
+        let img = ramp();
+        for (amount, value) in [(100.0, 255), (-100.0, 0)] {
+            let output = Brightness { amount }.apply(&img);
+            assert!(output.pixels().all(|px| px[0] == value && px[1] == value && px[2] == value), "{}", amount);
+        }
+    }
+
+    #[test]
+    fn no_contrast_is_mid_grey() {
+        let output = Contrast { amount: -100.0 }.apply(&ramp());
+        assert!(output.pixels().all(|px| px[0] == 128 && px[1] == 128 && px[2] == 128));
+        // And full contrast leaves only black and white
+        let output = Contrast { amount: 100.0 }.apply(&ramp());
+        assert!(output.pixels().all(|px| px.0[..3].iter().all(|&c| c == 0 || c == 255)));
+    }
+
+    // -100 saturation is the same as the default (Rec. 709) grayscale
+    #[test]
+    fn no_saturation_is_grey() {
+        let img = ramp();
+        let output = Saturation { amount: -100.0 }.apply(&img);
+        for (px, original) in output.pixels().zip(img.pixels()) {
+            let (r, g, b) = (original[0] as f32, original[1] as f32, original[2] as f32);
+            let grey = (0.2126 * r + 0.7152 * g + 0.0722 * b + 0.5) as u8;
+            assert_eq!(px.0[..3], [grey; 3], "{:?}", original);
+        }
+    }
+
+    // Vibrance mostly changes the dull colours, saturation changes everything by the same factor
+    #[test]
+    fn vibrance_favours_muted_colours() {
+        let muted = Rgba([140, 120, 110, 255]);
+        let strong = Rgba([240, 40, 20, 255]);
+        let img = RgbaImage::from_fn(2, 1, |x, _| if x == 0 { muted } else { strong });
+        // How far the red moved, as a fraction of how far it was from the pixel's grey to start with
+        let stretch = |before: Rgba<u8>, after: Rgba<u8>| {
+            let grey = 0.2126 * before[0] as f32 + 0.7152 * before[1] as f32 + 0.0722 * before[2] as f32;
+            (after[0] as f32 - grey) / (before[0] as f32 - grey)
+        };
+        for amount in [50.0, -50.0] {
+            let output = Vibrance { amount }.apply(&img);
+            let (muted_stretch, strong_stretch) = (stretch(muted, output[(0, 0)]), stretch(strong, output[(1, 0)]));
+            assert!((muted_stretch - 1.0).abs() > 2.0 * (strong_stretch - 1.0).abs(), "{}: {} vs {}", amount, muted_stretch, strong_stretch);
+        }
+    }
+
+    #[test]
+    fn alpha_is_unchanged() {
+        let img = ramp();
+        for filter in adjustments() {
+            let output = filter.apply(&img);
+            for (px, original) in output.pixels().zip(img.pixels()) {
+                assert_eq!(px[3], original[3], "{}", filter.name());
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use image::Rgba;
+
+use crate::error::FilterError;
+use crate::spec::{ParamInfo, Params};
+
+// How filters that mix neighbouring pixels together (blurs, convolutions) treat transparency
+//
+// The problem: a fully transparent pixel still has a colour (usually black), it just can't be seen
+// Mix it into its neighbours like any other pixel and that invisible black leaks out as dark fringes
+// around cut-out logos, and the alpha channel itself gets sharpened/embossed into a mess
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AlphaMode {
+    // Weight every colour by its alpha before mixing ('premultiplied alpha'), then undo it afterwards
+    // Transparent pixels contribute nothing to the colour, and alpha is filtered along with it
+    // (so a blur softens the edge of a cut-out, which is what you'd expect)
+    #[default]
+    Premultiplied,
+    // Mix the colours the same way as Premultiplied, but keep every pixel's original alpha
+    // Good for stickers: the outline stays exactly where it was
+    Preserve,
+    // Treat alpha as a fourth colour channel (how every filter used to work)
+    Straight,
+}
+
+impl AlphaMode {
+    // The names used by the "alpha" parameter
+    pub const NAMES: [&'static str; 3] = ["premultiplied", "preserve", "straight"];
+
+    // Reads the "alpha" parameter
+    pub fn from_params(params: &Params) -> Result<Self, FilterError> {
+        Ok(match params.choice("alpha")? {
+            "preserve" => AlphaMode::Preserve,
+            "straight" => AlphaMode::Straight,
+            // Params only ever hands back one of NAMES, so this is "premultiplied"
+            _ => AlphaMode::Premultiplied,
+        })
+    }
+
+    // A pixel as [r, g, b, a] floats, ready for mixing with its neighbours
+    // e.g. a half transparent white pixel => [127.5, 127.5, 127.5, 255 / 2] when premultiplying
+    #[inline]
+    pub(crate) fn load(self, px: Rgba<u8>) -> [f32; 4] {
+        let [r, g, b, a] = px.0.map(|value| value as f32);
+        match self {
+            AlphaMode::Straight => [r, g, b, a],
+            AlphaMode::Premultiplied | AlphaMode::Preserve => {
+                let scale = a / 255.0;
+                [r * scale, g * scale, b * scale, a]
+            }
+        }
+    }
+
+    // Turns a mixed [r, g, b, a] (from load()) back into a pixel
+    // 'original' is the pixel before filtering (Preserve keeps its alpha)
+    // 'bias' is added to the colour channels afterwards (see Kernel::with_bias)
+    pub(crate) fn store(self, mixed: [f32; 4], original: Rgba<u8>, bias: f32) -> Rgba<u8> {
+        let [r, g, b, a] = mixed;
+        let alpha = a.clamp(0.0, 255.0);
+        let colour = match self {
+            AlphaMode::Straight => [r, g, b],
+            // Divide the alpha back out of the colour
+            AlphaMode::Premultiplied | AlphaMode::Preserve if alpha > ALPHA_EPSILON => {
+                let scale = 255.0 / alpha;
+                [r * scale, g * scale, b * scale]
+            }
+            // Nothing is left to divide when the mix came out fully transparent (e.g. an emboss cancelling out)
+            // That's invisible with Premultiplied, but Preserve might keep the pixel visible, so fall back to its old colour
+            AlphaMode::Premultiplied => [0.0; 3],
+            AlphaMode::Preserve => [original[0], original[1], original[2]].map(|c| c as f32),
+        };
+        let alpha = match self {
+            AlphaMode::Preserve => original[3],
+            AlphaMode::Premultiplied | AlphaMode::Straight => to_channel(alpha),
+        };
+        let [r, g, b] = colour.map(|c| to_channel(c + bias));
+        Rgba([r, g, b, alpha])
+    }
+}
+
+// Anything less transparent than this counts as fully transparent when un-premultiplying
+// (dividing by a tiny alpha would blow rounding errors up into bright random colours)
+const ALPHA_EPSILON: f32 = 1e-3;
+
+// The "alpha" parameter shared by every filter that mixes pixels together
+pub(crate) const ALPHA_PARAM: ParamInfo = ParamInfo::choice("alpha", "Transparency", &AlphaMode::NAMES, "premultiplied");
+
+// Rounds and clamps a channel to 0 - 255
+// (+ 0.5 then truncating (what 'as' does) rounds to the nearest whole number, and is a lot quicker than round())
+pub(crate) fn to_channel(value: f32) -> u8 {
+    (value + 0.5).clamp(0.0, 255.0) as u8
+}
@@ -0,0 +1,610 @@
+use image::{Rgba, RgbaImage};
+
+use super::alpha::{to_channel, AlphaMode, ALPHA_PARAM};
+use crate::error::FilterError;
+use crate::filter::Filter;
+use crate::filter_kind::FilterKind;
+use crate::parallel::for_each_chunk;
+use crate::spec::{ParamInfo, Params};
+
+// The different ways of blurring, from most accurate to fastest
+// All of them treat the pixels past the edge as copies of the edge pixel (like BorderMode::Clamp)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlurMethod {
+    // A true Gaussian, done as a horizontal pass then a vertical pass ('separable')
+    // Cost grows with sigma (about 6 * sigma reads per pixel per pass)
+    Gaussian,
+    // Every pixel in a square gets the same weight. Cost doesn't depend on the size at all
+    Box,
+    // Three box blurs in a row, which looks almost exactly like a Gaussian
+    // Cost doesn't depend on sigma, so this is the one to use for big blurs on big photos
+    #[default]
+    FastGaussian,
+}
+
+impl BlurMethod {
+    // The names used by the "method" parameter
+    pub const NAMES: [&'static str; 3] = ["fast_gaussian", "gaussian", "box"];
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "gaussian" => BlurMethod::Gaussian,
+            "box" => BlurMethod::Box,
+            _ => BlurMethod::FastGaussian,
+        }
+    }
+}
+
+const DEFAULT_SIGMA: f32 = 5.0;
+const FAST_GAUSSIAN_MIN_SIGMA: f32 = 2.5;
+
+const BLUR_PARAMS: [ParamInfo; 3] = [
+    ParamInfo::number("sigma", "Amount", 0.1, 100.0, DEFAULT_SIGMA as f64, 0.1),
+    ParamInfo::choice("method", "Method", &BlurMethod::NAMES, "fast_gaussian"),
+    ALPHA_PARAM,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Blur {
+    // The amount of blur (the standard deviation of the Gaussian)
+    // The box method picks a box size with the same spread, so every method blurs by about the same amount
+    pub sigma: f32,
+    pub method: BlurMethod,
+    pub alpha: AlphaMode,
+}
+
+impl Default for Blur {
+    fn default() -> Self {
+        Blur { sigma: DEFAULT_SIGMA, method: BlurMethod::default(), alpha: AlphaMode::default() }
+    }
+}
+
+impl Filter for Blur {
+    fn name(&self) -> &str {
+        FilterKind::Blur.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Blur.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &BLUR_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        Ok(Box::new(Blur {
+            sigma: params.number("sigma")? as f32,
+            method: BlurMethod::from_name(params.choice("method")?),
+            alpha: AlphaMode::from_params(params)?,
+        }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        match self.method {
+            BlurMethod::Gaussian => gaussian_blur(img, self.sigma, self.alpha),
+            // A box of width w has a spread (variance) of (w² - 1) / 12, so pick the w that matches sigma
+            BlurMethod::Box => {
+                let width = (12.0 * self.sigma * self.sigma + 1.0).sqrt();
+                box_blur(img, (width / 2.0).round() as u32, self.alpha)
+            }
+            BlurMethod::FastGaussian => fast_gaussian_blur(img, self.sigma, self.alpha),
+        }
+    }
+}
+
+// A true Gaussian blur, done as two 1D passes (horizontal then vertical)
+// Doing it in two passes is the same as one big 2D kernel, but needs ~2 * 6σ reads per pixel instead of (6σ)²
+// 'alpha' decides how transparent pixels are mixed in (see AlphaMode), the same goes for the other blurs
+pub fn gaussian_blur(img: &RgbaImage, sigma: f32, alpha: AlphaMode) -> RgbaImage {
+    if sigma <= 0.0 {
+        return img.clone();
+    }
+    // 3 sigma either side covers 99.7% of the curve, anything further out is too small to matter
+    let radius = (3.0 * sigma).ceil() as usize;
+    let weights = gaussian_weights(radius, sigma);
+    separable_blur(img, alpha, &[Pass::Gaussian(&weights)])
+}
+
+// Every pixel becomes the average of the (2 * radius + 1) x (2 * radius + 1) square around it
+// Uses running sums, so the cost per pixel is the same whatever the radius
+pub fn box_blur(img: &RgbaImage, radius: u32, alpha: AlphaMode) -> RgbaImage {
+    if radius == 0 {
+        return img.clone();
+    }
+    separable_blur(img, alpha, &[Pass::Box(radius as usize)])
+}
+
+// Approximates a Gaussian blur with three box blurs in a row (each box blur smooths out the last one's corners)
+// Visually almost identical to gaussian_blur, but the cost per pixel is the same whatever sigma is
+pub fn fast_gaussian_blur(img: &RgbaImage, sigma: f32, alpha: AlphaMode) -> RgbaImage {
+    if sigma <= 0.0 {
+        return img.clone();
+    }
+    // Boxes only come in odd whole sizes, which is too coarse for a small sigma (0.5 would be no blur at all)
+    // The exact Gaussian only needs a few taps at this size anyway, so it's quicker too (up to about 2.5)
+    if sigma < FAST_GAUSSIAN_MIN_SIGMA {
+        return gaussian_blur(img, sigma, alpha);
+    }
+    let passes: Vec<Pass> = box_sizes_for_gaussian(sigma, 3)
+        .into_iter()
+        .map(|size| (size - 1) / 2)
+        .filter(|&radius| radius > 0)
+        .map(Pass::Box)
+        .collect();
+    separable_blur(img, alpha, &passes)
+}
+
+// The (odd) widths of 'passes' box blurs that together match a Gaussian with this sigma
+// Some boxes are one size smaller than the others so the total spread comes out right
+// (see "Fast Almost-Gaussian Filtering" by Peter Kovesi)
+fn box_sizes_for_gaussian(sigma: f32, passes: usize) -> Vec<usize> {
+    let n = passes as f32;
+    // The ideal box width if all of them were the same size
+    let ideal = (12.0 * sigma * sigma / n + 1.0).sqrt();
+    let mut lower = ideal.floor() as usize;
+    if lower.is_multiple_of(2) {
+        lower -= 1;
+    }
+    let upper = lower + 2;
+
+    // How many of the passes should use the smaller width
+    let lower_f = lower as f32;
+    let m = ((12.0 * sigma * sigma - n * lower_f * lower_f - 4.0 * n * lower_f - 3.0 * n) / (-4.0 * lower_f - 4.0)).round();
+    let m = m.clamp(0.0, n) as usize;
+
+    (0..passes).map(|i| if i < m { lower } else { upper }).collect()
+}
+
+fn gaussian_weights(radius: usize, sigma: f32) -> Vec<f32> {
+    let weights: Vec<f32> = (0..=2 * radius)
+        .map(|i| {
+            let d = i as f32 - radius as f32;
+            (-(d * d) / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    // Make the weights add up to 1 so the brightness doesn't change
+    let sum: f32 = weights.iter().sum();
+    weights.iter().map(|w| w / sum).collect()
+}
+
+// One 1D blur, run across every row and then down every column
+#[derive(Debug, Clone, Copy)]
+enum Pass<'a> {
+    // A Gaussian with these weights (2 * radius + 1 of them)
+    Gaussian(&'a [f32]),
+    // A box with this radius
+    Box(usize),
+}
+
+// Runs the passes across the rows, then the same passes down the columns
+// (a box or Gaussian blur in 2D is exactly a blur across then a blur down, and they can be done in any order)
+//
+// The passes across work on one row at a time, converting it to floats as they go, so it's still in the cache
+// for the next pass and the image is only read once. The last pass down turns the floats straight back into pixels
+// That means just two full size float buffers, rather than a separate conversion pass (and buffer) on each end
+fn separable_blur(img: &RgbaImage, alpha: AlphaMode, passes: &[Pass]) -> RgbaImage {
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    let Some((last, others)) = passes.split_last() else {
+        return img.clone();
+    };
+    if width == 0 || height == 0 {
+        return img.clone();
+    }
+    let alpha = alpha_for(img, alpha);
+    let stride = width * 4;
+
+    let mut buffer = vec![0.0f32; stride * height];
+    for_each_chunk(&mut buffer, stride, |y, out| {
+        let mut row = load_row(&img.as_raw()[y * stride..(y + 1) * stride], alpha);
+        let mut temp = vec![0.0f32; stride];
+        for pass in passes {
+            pass.across(&row, &mut temp, width);
+            std::mem::swap(&mut row, &mut temp);
+        }
+        out.copy_from_slice(&row);
+    });
+
+    let mut temp = vec![0.0f32; stride * height];
+    for pass in others {
+        pass.down(&buffer, &mut temp, width, height, |_, sums, out| out.copy_from_slice(sums));
+        std::mem::swap(&mut buffer, &mut temp);
+    }
+    let mut output = RgbaImage::new(width as u32, height as u32);
+    last.down(&buffer, &mut output, width, height, |y, sums, out| {
+        store_row(sums, &img.as_raw()[y * stride..(y + 1) * stride], alpha, out)
+    });
+    output
+}
+
+impl Pass<'_> {
+    // One row (RGBA floats) into 'out'
+    fn across(self, row: &[f32], out: &mut [f32], width: usize) {
+        match self {
+            Pass::Gaussian(weights) => gaussian_across(row, out, width, weights),
+            Pass::Box(radius) => box_across(row, out, width, radius),
+        }
+    }
+
+    // Every column of 'src' (width * height RGBA floats)
+    // Each blurred row goes through 'finish' (with its row number) on its way into 'dst',
+    // so the last pass can write pixels rather than floats
+    fn down<T: Send>(
+        self,
+        src: &[f32],
+        dst: &mut [T],
+        width: usize,
+        height: usize,
+        finish: impl Fn(usize, &[f32], &mut [T]) + Sync,
+    ) {
+        match self {
+            Pass::Gaussian(weights) => gaussian_down(src, dst, width, height, weights, finish),
+            Pass::Box(radius) => box_down(src, dst, width, height, radius, finish),
+        }
+    }
+}
+
+// One row of pixels as floats, 4 per pixel (R, G, B, A), so the passes don't lose precision in between
+// (premultiplied first, depending on the alpha mode)
+fn load_row(pixels: &[u8], alpha: AlphaMode) -> Vec<f32> {
+    let mut row = vec![0.0f32; pixels.len()];
+    match alpha {
+        // Nothing to weight, just the bytes as floats
+        AlphaMode::Straight => {
+            for (value, &byte) in row.iter_mut().zip(pixels) {
+                *value = byte as f32;
+            }
+        }
+        AlphaMode::Premultiplied | AlphaMode::Preserve => {
+            for (values, px) in row.chunks_exact_mut(4).zip(pixels.chunks_exact(4)) {
+                values.copy_from_slice(&alpha.load(Rgba([px[0], px[1], px[2], px[3]])));
+            }
+        }
+    }
+    row
+}
+
+// And back to pixels, 'original' is the row before blurring (for AlphaMode::Preserve)
+fn store_row(mixed: &[f32], original: &[u8], alpha: AlphaMode, out: &mut [u8]) {
+    match alpha {
+        // The same as alpha.store() on every channel, without the work per pixel
+        AlphaMode::Straight => {
+            for (byte, &value) in out.iter_mut().zip(mixed) {
+                *byte = to_channel(value);
+            }
+        }
+        AlphaMode::Premultiplied | AlphaMode::Preserve => {
+            for ((out, mixed), px) in out.chunks_exact_mut(4).zip(mixed.chunks_exact(4)).zip(original.chunks_exact(4)) {
+                let Rgba(result) = alpha.store([mixed[0], mixed[1], mixed[2], mixed[3]], Rgba([px[0], px[1], px[2], px[3]]), 0.0);
+                out.copy_from_slice(&result);
+            }
+        }
+    }
+}
+
+// Weighting by alpha makes no difference to a fully opaque image (every weight is 1), and most photos are,
+// so those skip premultiplying and dividing it back out afterwards
+// Alpha stays 255 whatever the blur, so every mode gives the same pixels as Straight
+fn alpha_for(img: &RgbaImage, alpha: AlphaMode) -> AlphaMode {
+    if img.pixels().all(|px| px[3] == 255) {
+        AlphaMode::Straight
+    } else {
+        alpha
+    }
+}
+
+// Index of a clamped position along a row/column of 'len' pixels
+fn clamp_index(i: isize, len: usize) -> usize {
+    i.clamp(0, len as isize - 1) as usize
+}
+
+// The row is first copied into a padded row with the edge pixels repeated 'radius' times on each side,
+// so the inner loop never has to check whether it has gone past the edge
+fn gaussian_across(row: &[f32], out: &mut [f32], width: usize, weights: &[f32]) {
+    let radius = weights.len() / 2;
+    let padded: Vec<[f32; 4]> = (0..width + 2 * radius)
+        .map(|i| {
+            let sx = clamp_index(i as isize - radius as isize, width);
+            [row[sx * 4], row[sx * 4 + 1], row[sx * 4 + 2], row[sx * 4 + 3]]
+        })
+        .collect();
+
+    // windows(n) => every run of n pixels in a row, one per output pixel
+    for (pixel, window) in out.chunks_exact_mut(4).zip(padded.windows(weights.len())) {
+        let mut sum = [0.0f32; 4];
+        for (weight, value) in weights.iter().zip(window) {
+            for c in 0..4 {
+                sum[c] += weight * value[c];
+            }
+        }
+        pixel.copy_from_slice(&sum);
+    }
+}
+
+// Goes row by row (adding whole weighted rows together) rather than column by column,
+// because walking along memory in order is much kinder to the CPU cache
+// Every output row is worked out on its own, so for_each_chunk can share them out across cores
+fn gaussian_down<T: Send>(
+    src: &[f32],
+    dst: &mut [T],
+    width: usize,
+    height: usize,
+    weights: &[f32],
+    finish: impl Fn(usize, &[f32], &mut [T]) + Sync,
+) {
+    let radius = (weights.len() / 2) as isize;
+    let stride = width * 4;
+    for_each_chunk(dst, stride, |y, out| {
+        let mut sums = vec![0.0f32; stride];
+        for (k, weight) in weights.iter().enumerate() {
+            let sy = clamp_index(y as isize + k as isize - radius, height);
+            let row = &src[sy * stride..(sy + 1) * stride];
+            for (sum, value) in sums.iter_mut().zip(row) {
+                *sum += weight * value;
+            }
+        }
+        finish(y, &sums, out);
+    });
+}
+
+// Slides a window of (2 * radius + 1) pixels along the row, keeping a running sum:
+// moving one pixel to the right adds the pixel coming in and subtracts the one going out
+// (padded like gaussian_across, with one more pixel each side for the last step's incoming and outgoing pixels)
+fn box_across(row: &[f32], out: &mut [f32], width: usize, radius: usize) {
+    let r = radius as isize;
+    let scale = 1.0 / (2 * radius + 1) as f32;
+    let padded: Vec<[f32; 4]> = (-r - 1..width as isize + r + 1)
+        .map(|x| {
+            let sx = clamp_index(x, width);
+            [row[sx * 4], row[sx * 4 + 1], row[sx * 4 + 2], row[sx * 4 + 3]]
+        })
+        .collect();
+
+    // The sum for the window around x = 0 (the part past the edge is copies of the first pixel)
+    let mut sum = [0.0f32; 4];
+    for value in &padded[1..2 * radius + 2] {
+        for c in 0..4 {
+            sum[c] += value[c];
+        }
+    }
+
+    // Each window runs from the pixel going out (x - radius) to the one coming in (x + radius + 1)
+    for (pixel, window) in out.chunks_exact_mut(4).zip(padded[1..].windows(2 * radius + 2)) {
+        for (o, total) in pixel.iter_mut().zip(sum) {
+            *o = total * scale;
+        }
+        let (outgoing, incoming) = (window[0], window[2 * radius + 1]);
+        for c in 0..4 {
+            sum[c] += incoming[c] - outgoing[c];
+        }
+    }
+}
+
+// How many rows box_down does with one set of running sums
+// Each band starts its sums from scratch, so the bands can be done in any order (or all at once)
+// It's a fixed number rather than 'rows per core' so the result never depends on how many cores there are
+const BOX_BAND_ROWS: usize = 64;
+
+// The same running sum, but down the columns
+// It keeps one sum per column and moves them all down a row at a time (again, kind to the cache)
+fn box_down<T: Send>(
+    src: &[f32],
+    dst: &mut [T],
+    width: usize,
+    height: usize,
+    radius: usize,
+    finish: impl Fn(usize, &[f32], &mut [T]) + Sync,
+) {
+    let r = radius as isize;
+    let stride = width * 4;
+    let scale = 1.0 / (2 * radius + 1) as f32;
+
+    for_each_chunk(dst, stride * BOX_BAND_ROWS, |band, out| {
+        let first_row = band * BOX_BAND_ROWS;
+
+        // The sums for the window around the first row of the band
+        let mut sums = vec![0.0f32; stride];
+        for i in -r..=r {
+            let sy = clamp_index(first_row as isize + i, height);
+            for (sum, value) in sums.iter_mut().zip(&src[sy * stride..(sy + 1) * stride]) {
+                *sum += value;
+            }
+        }
+
+        let mut scaled = vec![0.0f32; stride];
+        for (offset, out_row) in out.chunks_exact_mut(stride).enumerate() {
+            let y = (first_row + offset) as isize;
+            for (o, sum) in scaled.iter_mut().zip(&sums) {
+                *o = sum * scale;
+            }
+            finish(y as usize, &scaled, out_row);
+            let incoming = clamp_index(y + r + 1, height);
+            let outgoing = clamp_index(y - r, height);
+            let incoming_row = &src[incoming * stride..(incoming + 1) * stride];
+            let outgoing_row = &src[outgoing * stride..(outgoing + 1) * stride];
+            for ((sum, add), sub) in sums.iter_mut().zip(incoming_row).zip(outgoing_row) {
+                *sum += add - sub;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Something different in every channel of every pixel, all of it opaque
+    fn pattern(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| Rgba([(x * 37 + y * 11) as u8, (x * 5 + y * 71 + 40) as u8, (200 + x * y * 13) as u8, 255]))
+    }
+
+    // The image as f64s, so the reference blurs below don't round anything until the end
+    fn to_f64(img: &RgbaImage) -> Vec<[f64; 4]> {
+        img.pixels().map(|px| px.0.map(|value| value as f64)).collect()
+    }
+
+    fn to_image(width: u32, height: u32, pixels: &[[f64; 4]]) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| Rgba(pixels[(y * width + x) as usize].map(|value| (value + 0.5).clamp(0.0, 255.0) as u8)))
+    }
+
+    // The slow, obvious way: every output pixel is the weighted sum of the whole square around it,
+    // with weights[dx] * weights[dy] and the pixels past the edge clamped to the edge
+    fn reference(width: u32, height: u32, pixels: &[[f64; 4]], weights: &[f64]) -> Vec<[f64; 4]> {
+        let radius = (weights.len() / 2) as i64;
+        let at = |x: i64, y: i64| pixels[(y.clamp(0, height as i64 - 1) * width as i64 + x.clamp(0, width as i64 - 1)) as usize];
+        let mut output = Vec::with_capacity(pixels.len());
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                let mut sum = [0.0f64; 4];
+                for (ky, wy) in weights.iter().enumerate() {
+                    for (kx, wx) in weights.iter().enumerate() {
+                        let px = at(x + kx as i64 - radius, y + ky as i64 - radius);
+                        for c in 0..4 {
+                            sum[c] += wx * wy * px[c];
+                        }
+                    }
+                }
+                output.push(sum);
+            }
+        }
+        output
+    }
+
+    fn box_weights(radius: usize) -> Vec<f64> {
+        vec![1.0 / (2 * radius + 1) as f64; 2 * radius + 1]
+    }
+
+    fn gaussian_reference_weights(sigma: f64) -> Vec<f64> {
+        let radius = (3.0 * sigma).ceil() as i64;
+        let weights: Vec<f64> = (-radius..=radius).map(|d| (-(d * d) as f64 / (2.0 * sigma * sigma)).exp()).collect();
+        let sum: f64 = weights.iter().sum();
+        weights.iter().map(|w| w / sum).collect()
+    }
+
+    // The biggest difference between any two channels, float rounding can leave the odd one out by 1
+    fn max_difference(a: &RgbaImage, b: &RgbaImage) -> u8 {
+        assert_eq!(a.dimensions(), b.dimensions());
+        a.as_raw().iter().zip(b.as_raw()).map(|(&a, &b)| a.abs_diff(b)).max().unwrap_or(0)
+    }
+
+    // Small and odd sizes, plus one taller than BOX_BAND_ROWS so box_down needs more than one band
+    const SIZES: [(u32, u32); 6] = [(1, 7), (7, 1), (2, 3), (9, 5), (17, 13), (5, 70)];
+
+    #[test]
+    fn box_blur_matches_reference() {
+        for (width, height) in SIZES {
+            let img = pattern(width, height);
+            for radius in [1, 2, 6] {
+                let expected = to_image(width, height, &reference(width, height, &to_f64(&img), &box_weights(radius)));
+                let output = box_blur(&img, radius as u32, AlphaMode::Straight);
+                assert!(max_difference(&output, &expected) <= 1, "{}x{}, radius {}", width, height, radius);
+            }
+        }
+    }
+
+    #[test]
+    fn gaussian_blur_matches_reference() {
+        for (width, height) in SIZES {
+            let img = pattern(width, height);
+            for sigma in [0.5f32, 1.0, 2.2, 4.0] {
+                let weights = gaussian_reference_weights(sigma as f64);
+                let expected = to_image(width, height, &reference(width, height, &to_f64(&img), &weights));
+                let output = gaussian_blur(&img, sigma, AlphaMode::Straight);
+                assert!(max_difference(&output, &expected) <= 1, "{}x{}, sigma {}", width, height, sigma);
+            }
+        }
+    }
+
+    // Three box blurs one after the other, with the widths from box_sizes_for_gaussian
+    #[test]
+    fn fast_gaussian_blur_matches_reference() {
+        for (width, height) in SIZES {
+            let img = pattern(width, height);
+            for sigma in [FAST_GAUSSIAN_MIN_SIGMA, 3.0, 6.5] {
+                let mut expected = to_f64(&img);
+                for size in box_sizes_for_gaussian(sigma, 3) {
+                    expected = reference(width, height, &expected, &box_weights((size - 1) / 2));
+                }
+                let output = fast_gaussian_blur(&img, sigma, AlphaMode::Straight);
+                assert!(max_difference(&output, &to_image(width, height, &expected)) <= 1, "{}x{}, sigma {}", width, height, sigma);
+            }
+        }
+    }
+
+    // The boxes are only an approximation, but they should stay close to the real thing
+    #[test]
+    fn fast_gaussian_blur_is_close_to_gaussian() {
+        // A hard black to white edge, where the difference in shape shows up most
+        let img = RgbaImage::from_fn(40, 30, |x, _| if x < 20 { Rgba([0, 0, 0, 255]) } else { Rgba([255, 255, 255, 255]) });
+        for sigma in [FAST_GAUSSIAN_MIN_SIGMA, 3.0, 5.0, 8.0] {
+            let difference = max_difference(&fast_gaussian_blur(&img, sigma, AlphaMode::Straight), &gaussian_blur(&img, sigma, AlphaMode::Straight));
+            assert!(difference <= 3, "sigma {}: {}", sigma, difference);
+        }
+    }
+
+    // Just under the threshold it's the exact Gaussian, from the threshold on it's the boxes
+    #[test]
+    fn fast_gaussian_threshold() {
+        let img = pattern(23, 17);
+        let below = FAST_GAUSSIAN_MIN_SIGMA - 0.01;
+        assert_eq!(fast_gaussian_blur(&img, below, AlphaMode::default()), gaussian_blur(&img, below, AlphaMode::default()));
+
+        let passes: Vec<Pass> = box_sizes_for_gaussian(FAST_GAUSSIAN_MIN_SIGMA, 3).into_iter().map(|size| Pass::Box((size - 1) / 2)).collect();
+        assert_eq!(fast_gaussian_blur(&img, FAST_GAUSSIAN_MIN_SIGMA, AlphaMode::default()), separable_blur(&img, AlphaMode::default(), &passes));
+    }
+
+    // A single pixel only has itself to mix with, whatever the blur, the size or the alpha mode
+    #[test]
+    fn single_pixel() {
+        for px in [Rgba([200, 100, 50, 255]), Rgba([200, 100, 50, 128]), Rgba([10, 250, 90, 3])] {
+            let img = RgbaImage::from_pixel(1, 1, px);
+            for alpha in [AlphaMode::Premultiplied, AlphaMode::Preserve, AlphaMode::Straight] {
+                for output in [gaussian_blur(&img, 3.0, alpha), box_blur(&img, 5, alpha), fast_gaussian_blur(&img, 10.0, alpha)] {
+                    assert_eq!(output, img, "{:?}, {:?}", px, alpha);
+                }
+            }
+        }
+    }
+
+    // One of each kind of blur, each big enough to reach a few pixels
+    const BLURS: [fn(&RgbaImage, AlphaMode) -> RgbaImage; 3] =
+        [|img, alpha| gaussian_blur(img, 2.0, alpha), |img, alpha| box_blur(img, 2, alpha), |img, alpha| fast_gaussian_blur(img, 4.0, alpha)];
+
+    // Weighting by alpha changes nothing when everything is opaque, so every mode gives the same pixels
+    #[test]
+    fn opaque_alpha_modes_match() {
+        let img = pattern(12, 9);
+        for blur in BLURS {
+            let straight = blur(&img, AlphaMode::Straight);
+            assert!(straight.pixels().all(|px| px[3] == 255));
+            assert_eq!(blur(&img, AlphaMode::Premultiplied), straight);
+            assert_eq!(blur(&img, AlphaMode::Preserve), straight);
+        }
+    }
+
+    // A white shape on a transparent (black) background: premultiplying keeps the black out of the white's edge,
+    // Preserve also keeps the outline exactly where it was, and Straight lets the black bleed in
+    #[test]
+    fn translucent_edges() {
+        let img = RgbaImage::from_fn(12, 6, |x, _| if x < 6 { Rgba([255, 255, 255, 200]) } else { Rgba([0, 0, 0, 0]) });
+        for blur in BLURS {
+            let premultiplied = blur(&img, AlphaMode::Premultiplied);
+            let preserve = blur(&img, AlphaMode::Preserve);
+            let straight = blur(&img, AlphaMode::Straight);
+            for ((px, original), (kept, plain)) in premultiplied.pixels().zip(img.pixels()).zip(preserve.pixels().zip(straight.pixels())) {
+                if px[3] > 0 {
+                    assert_eq!(px.0[..3], [255, 255, 255], "premultiplied {:?}", px);
+                }
+                // The alpha still gets blurred, so the edge softens
+                assert_eq!(px[3], plain[3]);
+                assert_eq!(kept[3], original[3]);
+                if original[3] > 0 {
+                    assert_eq!(kept.0[..3], [255, 255, 255], "preserve {:?}", kept);
+                }
+            }
+            // Next to the edge the straight blur has mixed in the invisible black
+            assert!(straight.get_pixel(5, 3)[0] < 255);
+            assert!(premultiplied.get_pixel(6, 3)[3] > 0 && premultiplied.get_pixel(5, 3)[3] < 200);
+        }
+    }
+}
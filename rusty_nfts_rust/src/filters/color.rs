@@ -0,0 +1,305 @@
+use image::{RgbaImage, Rgba};
+
+use crate::error::FilterError;
+use crate::filter::Filter;
+use crate::filter_kind::FilterKind;
+use crate::kernels::{invert_row, sepia_row};
+use crate::parallel::{for_each_chunk, map_pixels};
+use crate::spec::{ParamInfo, Params};
+
+// The different ways of turning a colour into a single grey value
+// (each one gives a slightly different black and white 'look')
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum GrayscaleMethod {
+    // How bright each colour looks to us on a modern (HD / sRGB) screen: green looks brightest, blue darkest
+    #[default]
+    Rec709,
+    // The older (SD TV) version of the same idea, a bit heavier on red
+    Rec601,
+    // (r + g + b) / 3, every channel counts the same
+    Average,
+    // Halfway between the strongest and weakest channel (the 'L' in HSL)
+    Lightness,
+    // Just one channel, like shooting black and white film through a coloured filter
+    // 0 => red, 1 => green, 2 => blue
+    Channel(usize),
+    // Your own mix of red, green and blue, e.g. [1.0, 0.0, 0.0] is the same as Channel(0)
+    // The weights are relative, i.e. [2.0, 1.0, 1.0] means half red, a quarter each of green and blue
+    Custom([f32; 3]),
+}
+
+impl GrayscaleMethod {
+    // The names used by the "method" parameter
+    pub const NAMES: [&'static str; 8] = ["rec709", "rec601", "average", "lightness", "red", "green", "blue", "custom"];
+
+    // Reads the "method" parameter (and for 'custom', the three weights)
+    fn from_params(params: &Params) -> Result<Self, FilterError> {
+        Ok(match params.choice("method")? {
+            "rec601" => GrayscaleMethod::Rec601,
+            "average" => GrayscaleMethod::Average,
+            "lightness" => GrayscaleMethod::Lightness,
+            "red" => GrayscaleMethod::Channel(0),
+            "green" => GrayscaleMethod::Channel(1),
+            "blue" => GrayscaleMethod::Channel(2),
+            "custom" => {
+                let weights = [
+                    params.number("red_weight")? as f32,
+                    params.number("green_weight")? as f32,
+                    params.number("blue_weight")? as f32,
+                ];
+                // luma() divides by the total, so weights that cancel out (e.g. 1, -1, 0) can't be made relative
+                if weights.iter().sum::<f32>().abs() <= f32::EPSILON {
+                    return Err(FilterError::InvalidParameter {
+                        name: "red_weight".to_string(),
+                        reason: format!("the red, green and blue weights add up to 0 ({}, {}, {}), so there's nothing to divide by", weights[0], weights[1], weights[2]),
+                    });
+                }
+                GrayscaleMethod::Custom(weights)
+            }
+            // Params only ever hands back one of NAMES, so this is "rec709"
+            _ => GrayscaleMethod::Rec709,
+        })
+    }
+
+    // The grey value (0 - 255) for a colour
+    pub fn luma(self, r: u8, g: u8, b: u8) -> u8 {
+        let (r, g, b) = (r as f32, g as f32, b as f32);
+        let value = match self {
+            GrayscaleMethod::Rec709 => 0.2126 * r + 0.7152 * g + 0.0722 * b,
+            GrayscaleMethod::Rec601 => 0.299 * r + 0.587 * g + 0.114 * b,
+            GrayscaleMethod::Average => (r + g + b) / 3.0,
+            GrayscaleMethod::Lightness => (r.max(g).max(b) + r.min(g).min(b)) / 2.0,
+            GrayscaleMethod::Channel(channel) => [r, g, b][channel.min(2)],
+            GrayscaleMethod::Custom(weights) => {
+                // Divide by the total so the weights are relative
+                // (configure() rejects weights that add up to 0, but a Custom built in Rust could still have them, so leave those alone)
+                let total: f32 = weights.iter().sum();
+                let total = if total.abs() > f32::EPSILON { total } else { 1.0 };
+                (weights[0] * r + weights[1] * g + weights[2] * b) / total
+            }
+        };
+        (value + 0.5).clamp(0.0, 255.0) as u8
+    }
+}
+
+const GRAYSCALE_PARAMS: [ParamInfo; 4] = [
+    ParamInfo::choice("method", "Method", &GrayscaleMethod::NAMES, "rec709"),
+    // Only used by the 'custom' method
+    ParamInfo::number("red_weight", "Red Weight", -2.0, 2.0, 1.0, 0.01),
+    ParamInfo::number("green_weight", "Green Weight", -2.0, 2.0, 1.0, 0.01),
+    ParamInfo::number("blue_weight", "Blue Weight", -2.0, 2.0, 1.0, 0.01),
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Grayscale {
+    pub method: GrayscaleMethod,
+}
+
+impl Filter for Grayscale {
+    fn name(&self) -> &str {
+        FilterKind::Grayscale.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Grayscale.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &GRAYSCALE_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        Ok(Box::new(Grayscale { method: GrayscaleMethod::from_params(params)? }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        // Set the R, G, B values of every pixel to its grey value
+        // and keep the alpha value as it is (so transparent parts stay transparent)
+        // map_pixels => runs the closure on every pixel (spread over every core with the "parallel" feature)
+        map_pixels(img, |_, _, Rgba([r, g, b, a])| {
+            let luma = self.method.luma(r, g, b);
+            Rgba([luma, luma, luma, a])
+        })
+    }
+}
+
+const DEFAULT_ANGLE: i32 = 90;
+
+const HUE_ROTATE_PARAMS: [ParamInfo; 1] = [ParamInfo::integer("angle", "Angle", -360.0, 360.0, DEFAULT_ANGLE as f64)];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HueRotate {
+    // The angle (in degrees) by which the hue is 'rotated'
+    pub angle: i32,
+}
+
+impl Default for HueRotate {
+    fn default() -> Self {
+        HueRotate { angle: DEFAULT_ANGLE }
+    }
+}
+
+impl Filter for HueRotate {
+    fn name(&self) -> &str {
+        FilterKind::HueRotate.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::HueRotate.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &HUE_ROTATE_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        Ok(Box::new(HueRotate { angle: params.number("angle")? as i32 }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        apply_hue_rotate(img, self.angle)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Invert;
+
+impl Filter for Invert {
+    fn name(&self) -> &str {
+        FilterKind::Invert.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Invert.label()
+    }
+
+    fn configure(&self, _params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        Ok(Box::new(Invert))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        // Flip every colour channel (0 <=> 255), leaving alpha alone
+        // (a row at a time, see kernels for the SIMD version)
+        let mut inverted_img = img.clone();
+        for_each_chunk(&mut inverted_img, img.width() as usize * 4, |_, row| invert_row(row));
+        inverted_img
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Sepia;
+
+impl Filter for Sepia {
+    fn name(&self) -> &str {
+        FilterKind::Sepia.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Sepia.label()
+    }
+
+    fn configure(&self, _params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        Ok(Box::new(Sepia))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        apply_sepia(img)
+    }
+}
+
+// The same maths as image::imageops::huerotate, written out here so it can be split up by rows
+// Rotating the hue is a 3x3 matrix applied to every (r, g, b), built from the angle
+fn apply_hue_rotate(img: &RgbaImage, angle: i32) -> RgbaImage {
+    let angle = (angle as f64).to_radians();
+    let (cosv, sinv) = (angle.cos(), angle.sin());
+    let matrix: [[f64; 3]; 3] = [
+        // Reds
+        [0.213 + cosv * 0.787 - sinv * 0.213, 0.715 - cosv * 0.715 - sinv * 0.715, 0.072 - cosv * 0.072 + sinv * 0.928],
+        // Greens
+        [0.213 - cosv * 0.213 + sinv * 0.143, 0.715 + cosv * 0.285 + sinv * 0.140, 0.072 - cosv * 0.072 - sinv * 0.283],
+        // Blues
+        [0.213 - cosv * 0.213 - sinv * 0.787, 0.715 - cosv * 0.715 + sinv * 0.715, 0.072 + cosv * 0.928 + sinv * 0.072],
+    ];
+
+    map_pixels(img, |_, _, Rgba([r, g, b, a])| {
+        let (r, g, b) = (r as f64, g as f64, b as f64);
+        // 'as u8' cuts the decimals off, like imageops does
+        let channel = |row: [f64; 3]| (row[0] * r + row[1] * g + row[2] * b).clamp(0.0, 255.0) as u8;
+        Rgba([channel(matrix[0]), channel(matrix[1]), channel(matrix[2]), a])
+    })
+}
+
+fn apply_sepia(img: &RgbaImage) -> RgbaImage {
+    // Work out the new value of each pixel a row at a time (so the rows can run in parallel)
+    // sepia_row has the formula, and a SIMD version that does several pixels at once
+    let mut sepia_img = img.clone();
+    for_each_chunk(&mut sepia_img, img.width() as usize * 4, |_, row| sepia_row(row));
+    sepia_img
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spec::FilterSpec;
+
+    // Every method's grey value for one colour (worked out by hand from the formulas above)
+    #[test]
+    fn methods() {
+        let (r, g, b) = (200, 100, 50);
+        let expected = [
+            (GrayscaleMethod::Rec709, 118),    // 42.52 + 71.52 + 3.61 = 117.65
+            (GrayscaleMethod::Rec601, 124),    // 59.8 + 58.7 + 5.7 = 124.2
+            (GrayscaleMethod::Average, 117),   // 350 / 3 = 116.67
+            (GrayscaleMethod::Lightness, 125), // (200 + 50) / 2
+            (GrayscaleMethod::Channel(0), 200),
+            (GrayscaleMethod::Channel(1), 100),
+            (GrayscaleMethod::Channel(2), 50),
+            (GrayscaleMethod::Custom([0.0, 0.0, 1.0]), 50),
+        ];
+        for (method, luma) in expected {
+            assert_eq!(method.luma(r, g, b), luma, "{:?}", method);
+        }
+    }
+
+    // Only the ratio between the weights matters
+    #[test]
+    fn custom_weights_are_relative() {
+        for (r, g, b) in [(200, 100, 50), (0, 255, 7), (255, 255, 255)] {
+            let average = GrayscaleMethod::Average.luma(r, g, b);
+            assert_eq!(GrayscaleMethod::Custom([2.0, 2.0, 2.0]).luma(r, g, b), average);
+            assert_eq!(GrayscaleMethod::Custom([0.5, 0.5, 0.5]).luma(r, g, b), average);
+            assert_eq!(GrayscaleMethod::Custom([2.0, 0.0, 0.0]).luma(r, g, b), r);
+        }
+        // [2, 1, 1] => half red, a quarter each of green and blue
+        assert_eq!(GrayscaleMethod::Custom([2.0, 1.0, 1.0]).luma(200, 100, 40), 135);
+    }
+
+    #[test]
+    fn zero_weights_are_rejected() {
+        for weights in [[0.0, 0.0, 0.0], [1.0, -1.0, 0.0], [0.5, 1.5, -2.0]] {
+            let spec = FilterSpec::new("grayscale")
+                .with("method", "custom")
+                .with("red_weight", weights[0])
+                .with("green_weight", weights[1])
+                .with("blue_weight", weights[2]);
+            assert!(
+                matches!(spec.build(), Err(FilterError::InvalidParameter { name, .. }) if name == "red_weight"),
+                "{:?}",
+                weights
+            );
+        }
+    }
+
+    #[test]
+    fn alpha_is_unchanged() {
+        let img = RgbaImage::from_fn(5, 3, |x, y| Rgba([(x * 50) as u8, (y * 90) as u8, 77, (x * 60 + y) as u8]));
+        for method in [GrayscaleMethod::Rec709, GrayscaleMethod::Lightness, GrayscaleMethod::Channel(2), GrayscaleMethod::Custom([1.0, -0.5, 2.0])] {
+            let output = Grayscale { method }.apply(&img);
+            for (before, after) in img.pixels().zip(output.pixels()) {
+                assert_eq!(after[3], before[3], "{:?}", method);
+                assert_eq!(after[0], method.luma(before[0], before[1], before[2]), "{:?}", method);
+                assert!(after[0] == after[1] && after[1] == after[2], "{:?}", method);
+            }
+        }
+    }
+}
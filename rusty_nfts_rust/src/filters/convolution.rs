@@ -0,0 +1,747 @@
+use image::{RgbaImage, Rgba};
+
+use super::alpha::{AlphaMode, ALPHA_PARAM};
+use crate::error::FilterError;
+use crate::filter::Filter;
+use crate::filter_kind::FilterKind;
+use crate::kernels::convolve_3x3_row;
+use crate::parallel::{build_rows, for_each_chunk};
+use crate::spec::{ParamInfo, Params};
+
+// What to do when the kernel hangs off the edge of the image
+// (the pixels next to the edge don't have a full set of neighbours)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BorderMode {
+    // Repeat the nearest edge pixel, i.e. aaa|abcd|ddd
+    #[default]
+    Clamp,
+    // Reflect back into the image, i.e. cb|abcd|cb
+    Mirror,
+    // Carry on from the other side, i.e. cd|abcd|ab (good for tiling textures)
+    Wrap,
+    // Pretend everything outside the image is this colour
+    Constant(Rgba<u8>),
+    // Only keep the pixels where the whole kernel fits, so the result is smaller than the input
+    // (a side that is too small to crop is left at full size and clamped instead)
+    Crop,
+}
+
+impl BorderMode {
+    // The names used by the "border" parameter
+    pub const NAMES: [&'static str; 5] = ["clamp", "mirror", "wrap", "constant", "crop"];
+
+    // Reads the "border" (and for 'constant', "border_color") parameters
+    pub fn from_params(params: &Params) -> Result<Self, FilterError> {
+        Ok(match params.choice("border")? {
+            "mirror" => BorderMode::Mirror,
+            "wrap" => BorderMode::Wrap,
+            "constant" => BorderMode::Constant(Rgba(params.color("border_color")?)),
+            "crop" => BorderMode::Crop,
+            // Params only ever hands back one of NAMES, so this is "clamp"
+            _ => BorderMode::Clamp,
+        })
+    }
+
+    // Turns a coordinate that might be outside the image (e.g. -1) into one inside it
+    // None => use the constant colour instead
+    // i64 so that negative coordinates (and big ones) can't overflow
+    pub(crate) fn resolve(self, coord: i64, len: u32) -> Option<u32> {
+        let len = len as i64;
+        if (0..len).contains(&coord) {
+            return Some(coord as u32);
+        }
+        match self {
+            BorderMode::Clamp | BorderMode::Crop => Some(coord.clamp(0, len - 1) as u32),
+            BorderMode::Mirror => {
+                // A 1 pixel wide image has nothing to reflect, it's just that pixel
+                if len == 1 {
+                    return Some(0);
+                }
+                // Mirroring repeats every 2 * (len - 1) pixels: 0 1 2 3 2 1 | 0 1 2 3 2 1 | ...
+                let period = 2 * (len - 1);
+                // rem_euclid => always positive, even for negative coords (unlike %)
+                let offset = coord.rem_euclid(period);
+                Some(if offset < len { offset } else { period - offset } as u32)
+            }
+            BorderMode::Wrap => Some(coord.rem_euclid(len) as u32),
+            BorderMode::Constant(_) => None,
+        }
+    }
+}
+
+// The border parameters shared by every convolution based filter
+pub(crate) const BORDER_PARAMS: [ParamInfo; 2] = [
+    ParamInfo::choice("border", "Edges", &BorderMode::NAMES, "clamp"),
+    ParamInfo::color("border_color", "Edge Colour", "#00000000"),
+];
+
+// The 'identity' kernel, used by the "kernel" parameter and Convolve::default()
+const DEFAULT_KERNEL: &[&[f64]] = &[
+    &[0.0, 0.0, 0.0],
+    &[0.0, 1.0, 0.0],
+    &[0.0, 0.0, 0.0],
+];
+
+// The parameters of the fixed kernel filters (emboss, sharpen)
+const CONVOLUTION_PARAMS: [ParamInfo; 3] = [BORDER_PARAMS[0], BORDER_PARAMS[1], ALPHA_PARAM];
+
+const CONVOLVE_PARAMS: [ParamInfo; 6] = [
+    ParamInfo::matrix("kernel", "Kernel", DEFAULT_KERNEL),
+    // 0 => automatic, i.e. the sum of the weights (or 1 if they add up to 0)
+    ParamInfo::number("divisor", "Divisor", -1000.0, 1000.0, 0.0, 0.1),
+    ParamInfo::number("bias", "Bias", -255.0, 255.0, 0.0, 1.0),
+    BORDER_PARAMS[0],
+    BORDER_PARAMS[1],
+    ALPHA_PARAM,
+];
+
+// Runs a user supplied kernel over the image, for power users to build their own effects
+// The default kernel is the 'identity' (1 in the middle), which leaves the image as it is
+#[derive(Debug, Clone, PartialEq)]
+pub struct Convolve {
+    pub kernel: Kernel,
+    pub border: BorderMode,
+    pub alpha: AlphaMode,
+}
+
+impl Default for Convolve {
+    fn default() -> Self {
+        Convolve {
+            // The same as configure() with the default parameters (the divisor is automatic)
+            kernel: kernel_from_matrix(DEFAULT_KERNEL).expect("identity kernel is valid").normalized(),
+            border: BorderMode::default(),
+            alpha: AlphaMode::default(),
+        }
+    }
+}
+
+impl Filter for Convolve {
+    fn name(&self) -> &str {
+        FilterKind::Convolve.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Convolve.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &CONVOLVE_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        let kernel = kernel_from_matrix(&params.matrix("kernel")?)?;
+
+        let divisor = params.number("divisor")? as f32;
+        let kernel = if divisor == 0.0 { kernel.normalized() } else { kernel.with_divisor(divisor)? };
+        let kernel = kernel.with_bias(params.number("bias")? as f32);
+
+        Ok(Box::new(Convolve { kernel, border: BorderMode::from_params(params)?, alpha: AlphaMode::from_params(params)? }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        apply_convolution(img, &self.kernel, self.border, self.alpha)
+    }
+}
+
+// The rows of the "kernel" parameter (f64s, like every number in JSON) as a Kernel
+fn kernel_from_matrix<R: AsRef<[f64]>>(rows: &[R]) -> Result<Kernel, FilterError> {
+    let rows: Vec<Vec<f32>> = rows.iter().map(|row| row.as_ref().iter().map(|&weight| weight as f32).collect()).collect();
+    Kernel::from_rows(&rows)
+}
+
+// kernel is a small grid or matrix that is used in image processing to apply effects and filters
+// for each filter a different kernel is created
+// f32 is a 32-bit floating point number
+// e.g. a 3 x 3 matrix => middle pixel is the target pixel and the surrounding pixels are multiplied by the surrounding values
+//
+// A Kernel can be any odd width and height (odd, so there is always a middle pixel)
+// Each result is (sum of weight * pixel) / divisor + bias
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kernel {
+    width: usize,
+    height: usize,
+    // The weights row by row, i.e. weights[y * width + x]
+    weights: Vec<f32>,
+    divisor: f32,
+    // Added to the colour channels (not alpha) after dividing, e.g. 128 to lift an emboss to mid grey
+    bias: f32,
+}
+
+impl Kernel {
+    // The biggest kernel we accept (anything bigger is painfully slow done this way)
+    pub const MAX_SIZE: usize = 31;
+
+    // Makes a kernel from its weights, row by row
+    pub fn new(width: usize, height: usize, weights: Vec<f32>) -> Result<Self, FilterError> {
+        if width.is_multiple_of(2) || height.is_multiple_of(2) {
+            return Err(invalid_kernel(format!("the size must be odd (so there is a middle pixel), got {}x{}", width, height)));
+        }
+        if width > Kernel::MAX_SIZE || height > Kernel::MAX_SIZE {
+            return Err(invalid_kernel(format!("{}x{} is bigger than the maximum of {}x{}", width, height, Kernel::MAX_SIZE, Kernel::MAX_SIZE)));
+        }
+        if weights.len() != width * height {
+            return Err(invalid_kernel(format!("a {}x{} kernel needs {} weights, got {}", width, height, width * height, weights.len())));
+        }
+        if weights.iter().any(|weight| !weight.is_finite()) {
+            return Err(invalid_kernel("every weight must be a finite number".to_string()));
+        }
+        Ok(Kernel { width, height, weights, divisor: 1.0, bias: 0.0 })
+    }
+
+    // Makes a kernel from a list of rows, e.g. Kernel::from_rows(&[[0.0, -1.0, 0.0], [-1.0, 5.0, -1.0], [0.0, -1.0, 0.0]])
+    // AsRef<[f32]> => each row can be an array, a Vec or a slice
+    pub fn from_rows<R: AsRef<[f32]>>(rows: &[R]) -> Result<Self, FilterError> {
+        let width = rows.first().map_or(0, |row| row.as_ref().len());
+        if rows.iter().any(|row| row.as_ref().len() != width) {
+            return Err(invalid_kernel("every row must have the same number of weights".to_string()));
+        }
+        let weights = rows.iter().flat_map(|row| row.as_ref().iter().copied()).collect();
+        Kernel::new(width, rows.len(), weights)
+    }
+
+    // A width x width kernel of 1s, divided by the number of weights (i.e. the average of the area)
+    // e.g. Kernel::box_blur(3) is a 7x7 box blur
+    pub fn box_blur(radius: usize) -> Result<Self, FilterError> {
+        let size = radius * 2 + 1;
+        Kernel::new(size, size, vec![1.0; size * size]).map(Kernel::normalized)
+    }
+
+    // A Gaussian blur kernel, e.g. Kernel::gaussian(2, 1.0) is a 5x5 Gaussian
+    pub fn gaussian(radius: usize, sigma: f32) -> Result<Self, FilterError> {
+        if sigma.is_nan() || sigma <= 0.0 {
+            return Err(invalid_kernel(format!("sigma must be above 0, got {}", sigma)));
+        }
+        let size = radius * 2 + 1;
+        let mut weights = Vec::with_capacity(size * size);
+        for y in 0..size {
+            for x in 0..size {
+                // How far this weight is from the middle
+                let dx = x as f32 - radius as f32;
+                let dy = y as f32 - radius as f32;
+                weights.push((-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp());
+            }
+        }
+        Kernel::new(size, size, weights).map(Kernel::normalized)
+    }
+
+    // Divides the result by this (e.g. 9 for a 3x3 of 1s), can't be 0
+    pub fn with_divisor(mut self, divisor: f32) -> Result<Self, FilterError> {
+        if divisor == 0.0 || !divisor.is_finite() {
+            return Err(invalid_kernel(format!("the divisor must be a non-zero number, got {}", divisor)));
+        }
+        self.divisor = divisor;
+        Ok(self)
+    }
+
+    pub fn with_bias(mut self, bias: f32) -> Self {
+        self.bias = bias;
+        self
+    }
+
+    // Sets the divisor to the sum of the weights, so the kernel doesn't change the overall brightness
+    // (kernels that add up to 0, like edge detectors, are left dividing by 1)
+    pub fn normalized(mut self) -> Self {
+        let sum: f32 = self.weights.iter().sum();
+        self.divisor = if sum.abs() > f32::EPSILON { sum } else { 1.0 };
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn weights(&self) -> &[f32] {
+        &self.weights
+    }
+
+    pub fn divisor(&self) -> f32 {
+        self.divisor
+    }
+
+    pub fn bias(&self) -> f32 {
+        self.bias
+    }
+
+    // How far the kernel reaches out from its middle, e.g. (1, 1) for a 3x3 kernel
+    pub fn radius(&self) -> (u32, u32) {
+        ((self.width / 2) as u32, (self.height / 2) as u32)
+    }
+}
+
+fn invalid_kernel(reason: String) -> FilterError {
+    FilterError::InvalidParameter { name: "kernel".to_string(), reason }
+}
+
+// Raises the edges so the image looks 'stamped' into metal
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Emboss {
+    pub border: BorderMode,
+    pub alpha: AlphaMode,
+}
+
+impl Filter for Emboss {
+    fn name(&self) -> &str {
+        FilterKind::Emboss.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Emboss.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &CONVOLUTION_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        Ok(Box::new(Emboss { border: BorderMode::from_params(params)?, alpha: AlphaMode::from_params(params)? }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        apply_emboss(img, self.border, self.alpha)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Sharpen {
+    pub border: BorderMode,
+    pub alpha: AlphaMode,
+}
+
+impl Filter for Sharpen {
+    fn name(&self) -> &str {
+        FilterKind::Sharpen.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Sharpen.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &CONVOLUTION_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        Ok(Box::new(Sharpen { border: BorderMode::from_params(params)?, alpha: AlphaMode::from_params(params)? }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        apply_sharpen(img, self.border, self.alpha)
+    }
+}
+
+fn apply_emboss(img: &RgbaImage, border: BorderMode, alpha: AlphaMode) -> RgbaImage {
+    let kernel = Kernel::from_rows(&[
+        [-2.0, -1.0, 0.0],
+        [-1.0,  1.0, 1.0],
+        [ 0.0,  1.0, 2.0],
+    ]).expect("emboss kernel is valid");
+    apply_convolution(img, &kernel, border, alpha)
+}
+
+fn apply_sharpen(img: &RgbaImage, border: BorderMode, alpha: AlphaMode) -> RgbaImage {
+    let kernel = Kernel::from_rows(&[
+        [ 0.0, -1.0,  0.0],
+        [-1.0,  5.0, -1.0],
+        [ 0.0, -1.0,  0.0],
+    ]).expect("sharpen kernel is valid");
+    apply_convolution(img, &kernel, border, alpha)
+}
+
+// 'alpha' decides how transparent pixels are mixed in (see AlphaMode)
+pub fn apply_convolution(img: &RgbaImage, kernel: &Kernel, border: BorderMode, alpha: AlphaMode) -> RgbaImage {
+    // Get the dimensions (width and height) of the input image
+    let (width, height) = img.dimensions();
+
+    // How far the kernel reaches out from its centre (1 for a 3x3 kernel)
+    let (radius_x, radius_y) = kernel.radius();
+
+    // With Crop we only produce the pixels where the whole kernel fits inside the image
+    // i.e. skip 'radius' pixels on each side (unless the image is too small for that)
+    let (skip_x, skip_y) = match border {
+        BorderMode::Crop => (
+            if width > 2 * radius_x { radius_x } else { 0 },
+            if height > 2 * radius_y { radius_y } else { 0 },
+        ),
+        _ => (0, 0),
+    };
+
+    // The colour to use for pixels outside the image (only used by BorderMode::Constant)
+    let outside = match border {
+        BorderMode::Constant(color) => color,
+        _ => Rgba([0, 0, 0, 0]),
+    };
+
+    // 3x3 kernels (emboss, sharpen, most hand made ones) have a quicker version, with exactly the same result
+    if kernel.width() == 3 && kernel.height() == 3 {
+        return apply_convolution_3x3(img, kernel, border, alpha, (skip_x, skip_y), outside);
+    }
+    apply_convolution_any(img, kernel, border, alpha, (skip_x, skip_y), outside)
+}
+
+// Any size of kernel, one weight at a time
+fn apply_convolution_any(
+    img: &RgbaImage,
+    kernel: &Kernel,
+    border: BorderMode,
+    alpha: AlphaMode,
+    (skip_x, skip_y): (u32, u32),
+    outside: Rgba<u8>,
+) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    let (radius_x, radius_y) = kernel.radius();
+
+    // Create a new image (output buffer) for the result, a row at a time
+    // Same dimensions as the original image, unless we're cropping
+    // (build_rows => every row is worked out on its own, so they can be shared out across cores)
+    let output = build_rows(width - 2 * skip_x, height - 2 * skip_y, |out_y, row| {
+        // Loop over each output pixel in the row, including the edge pixels
+        // (for those, border.resolve() decides what the 'missing' neighbours are)
+        for (out_x, out_pixel) in row.chunks_exact_mut(4).enumerate() {
+            // Where this output pixel is in the input image
+            let x = out_x as i64 + skip_x as i64;
+            let y = (out_y + skip_y) as i64;
+
+            // Initialize channel values
+            // These will store the sum of the products of the kernel and the surrounding pixel values
+            let mut sum = [0.0f32; 4];
+
+            // Nested loop to go through each value in the kernel
+            // chunks() splits the weights back up into rows, enumerate() gives us the index (ky/kx) alongside each value
+            for (ky, kernel_row) in kernel.weights().chunks(kernel.width()).enumerate() { // Loop over the kernel rows
+                for (kx, weight) in kernel_row.iter().enumerate() { // Loop over the kernel columns
+
+                    // Get the pixel value from the original image at the corresponding position
+                    // The position is offset by the current kernel position (kx and ky)
+                    // and might be outside the image, in which case the border mode picks a pixel for us
+                    let sample_x = border.resolve(x + kx as i64 - radius_x as i64, width);
+                    let sample_y = border.resolve(y + ky as i64 - radius_y as i64, height);
+                    let px = match (sample_x, sample_y) {
+                        (Some(sx), Some(sy)) => *img.get_pixel(sx, sy),
+                        _ => outside,
+                    };
+
+                    // Multiply each channel (red, green, blue, alpha) of the pixel by the corresponding kernel value
+                    // and add the result to the respective accumulator
+                    // (alpha.load() weights the colour by its alpha first, unless the mode is Straight)
+                    for (total, value) in sum.iter_mut().zip(alpha.load(px)) {
+                        *total += weight * value;
+                    }
+                }
+            }
+
+            // Scale the sums by the divisor
+            let divisor = kernel.divisor();
+            let mixed = sum.map(|total| total / divisor);
+
+            // After processing all the surrounding pixels, turn the sums back into a pixel:
+            // alpha.store() undoes the premultiplying, adds the bias to the colour channels,
+            // then rounds and clamps every value to the valid range for image data (0 to 255)
+            // (rounding rather than just cutting off the decimals, otherwise a blur turns 255 into 254)
+            let Rgba(result) = alpha.store(mixed, *img.get_pixel(x as u32, y as u32), kernel.bias());
+            out_pixel.copy_from_slice(&result);
+        }
+    });
+
+    // Return the processed image stored in the output buffer
+    output
+}
+
+// The same as apply_convolution for a 3x3 kernel, but quicker:
+// each row works out the 3 input rows it needs once (as floats, with the border pixels filled in),
+// then convolve_3x3_row does the multiplying and adding, several pixels at a time if SIMD is available
+// The sums are added up in the same order as above, so the output is identical
+fn apply_convolution_3x3(
+    img: &RgbaImage,
+    kernel: &Kernel,
+    border: BorderMode,
+    alpha: AlphaMode,
+    (skip_x, skip_y): (u32, u32),
+    outside: Rgba<u8>,
+) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    let (out_width, out_height) = (width - 2 * skip_x, height - 2 * skip_y);
+    let weights: [f32; 9] = kernel.weights().try_into().expect("a 3x3 kernel has 9 weights");
+
+    build_rows(out_width, out_height, |out_y, row| {
+        let y = (out_y + skip_y) as i64;
+
+        // The rows above, on and below this one, each with an extra pixel on both ends for the kernel to hang over
+        let input_rows: [Vec<f32>; 3] = std::array::from_fn(|ky| {
+            let sample_y = border.resolve(y + ky as i64 - 1, height);
+            let mut input_row = Vec::with_capacity((out_width as usize + 2) * 4);
+            for padded_x in 0..out_width as i64 + 2 {
+                let sample_x = border.resolve(padded_x + skip_x as i64 - 1, width);
+                let px = match (sample_x, sample_y) {
+                    (Some(sx), Some(sy)) => *img.get_pixel(sx, sy),
+                    _ => outside,
+                };
+                input_row.extend_from_slice(&alpha.load(px));
+            }
+            input_row
+        });
+
+        let mut sums = vec![0.0f32; out_width as usize * 4];
+        convolve_3x3_row([&input_rows[0], &input_rows[1], &input_rows[2]], &weights, &mut sums);
+
+        // Then the same as apply_convolution: divide, and turn the sums back into a pixel
+        let divisor = kernel.divisor();
+        for (out_x, (out_pixel, sum)) in row.chunks_exact_mut(4).zip(sums.chunks_exact(4)).enumerate() {
+            let mixed = [sum[0] / divisor, sum[1] / divisor, sum[2] / divisor, sum[3] / divisor];
+            let Rgba(result) = alpha.store(mixed, *img.get_pixel(out_x as u32 + skip_x, y as u32), kernel.bias());
+            out_pixel.copy_from_slice(&result);
+        }
+    })
+}
+
+// The same as apply_convolution, but on a single channel of floats (e.g. the brightness of every pixel, row by row)
+// and without rounding or clamping, so negative results survive (the edge detectors need those)
+// Constant borders count everything outside as 0, and Crop is treated like Clamp (the result is always full size)
+pub(crate) fn convolve_plane(plane: &[f32], width: u32, height: u32, kernel: &Kernel, border: BorderMode) -> Vec<f32> {
+    let (radius_x, radius_y) = kernel.radius();
+    let mut output = vec![0.0f32; plane.len()];
+    for_each_chunk(&mut output, width as usize, |y, row| {
+        for (x, out) in row.iter_mut().enumerate() {
+            let mut sum = 0.0f32;
+            for (ky, kernel_row) in kernel.weights().chunks(kernel.width()).enumerate() {
+                let Some(sample_y) = border.resolve(y as i64 + ky as i64 - radius_y as i64, height) else {
+                    continue;
+                };
+                for (kx, weight) in kernel_row.iter().enumerate() {
+                    if let Some(sample_x) = border.resolve(x as i64 + kx as i64 - radius_x as i64, width) {
+                        sum += weight * plane[sample_y as usize * width as usize + sample_x as usize];
+                    }
+                }
+            }
+            *out = sum / kernel.divisor() + kernel.bias();
+        }
+    });
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [BorderMode; 5] =
+        [BorderMode::Clamp, BorderMode::Mirror, BorderMode::Wrap, BorderMode::Constant(Rgba([0, 0, 0, 255])), BorderMode::Crop];
+
+    // An opaque grey image with the given values, one per pixel, row by row
+    fn grey(width: u32, height: u32, values: &[u8]) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let value = values[(y * width + x) as usize];
+            Rgba([value, value, value, 255])
+        })
+    }
+
+    fn values(img: &RgbaImage) -> Vec<u8> {
+        img.pixels().map(|px| px[0]).collect()
+    }
+
+    // Something different in every channel of every pixel, including some (fully and half) transparent ones
+    fn pattern(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let alpha = [255, 0, 128, 255, 30][((x + 2 * y) % 5) as usize];
+            Rgba([(x * 37 + y * 11) as u8, (x * 5 + y * 71 + 40) as u8, (200 + x * y * 13) as u8, alpha])
+        })
+    }
+
+    #[test]
+    fn resolve_one_pixel() {
+        // Whatever the mode, a 1 pixel side only has that pixel to offer (or the constant colour)
+        for border in MODES {
+            for coord in -3..=3 {
+                let expected = match border {
+                    BorderMode::Constant(_) if coord != 0 => None,
+                    _ => Some(0),
+                };
+                assert_eq!(border.resolve(coord, 1), expected, "{:?} at {}", border, coord);
+            }
+        }
+    }
+
+    #[test]
+    fn resolve_outside() {
+        let resolve = |border: BorderMode| (-5..=8).map(|coord| border.resolve(coord, 4)).collect::<Vec<_>>();
+        let some = |coords: &[u32]| coords.iter().map(|&coord| Some(coord)).collect::<Vec<_>>();
+        assert_eq!(resolve(BorderMode::Clamp), some(&[0, 0, 0, 0, 0, 0, 1, 2, 3, 3, 3, 3, 3, 3]));
+        assert_eq!(resolve(BorderMode::Crop), resolve(BorderMode::Clamp));
+        assert_eq!(resolve(BorderMode::Mirror), some(&[1, 2, 3, 2, 1, 0, 1, 2, 3, 2, 1, 0, 1, 2]));
+        assert_eq!(resolve(BorderMode::Wrap), some(&[3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0]));
+        let constant = resolve(BorderMode::Constant(Rgba([0; 4])));
+        assert_eq!(constant[5..9], some(&[0, 1, 2, 3]));
+        assert!(constant[..5].iter().chain(&constant[9..]).all(Option::is_none));
+    }
+
+    // Averages 3 pixels in a row (or column) on a 5x1 (or 1x5) image, which needs the border on both ends
+    // Done both with a 3x1 kernel (the general version) and a 3x3 one with 0s around it (the 3x3 version)
+    #[test]
+    fn one_pixel_high_and_wide() {
+        let row = [10, 20, 30, 40, 50];
+        let expected: [&[u8]; 5] = [
+            &[13, 20, 30, 40, 47], // clamp: 10 10 20 ... 40 50 50
+            &[17, 20, 30, 40, 43], // mirror: 20 10 20 ... 40 50 40
+            &[27, 20, 30, 40, 33], // wrap: 50 10 20 ... 40 50 10
+            &[10, 20, 30, 40, 30], // constant black: 0 10 20 ... 40 50 0
+            &[20, 30, 40],         // crop: only the 3 pixels with both neighbours
+        ];
+        let across = [
+            Kernel::from_rows(&[[1.0, 1.0, 1.0]]).unwrap().normalized(),
+            Kernel::from_rows(&[[0.0, 0.0, 0.0], [1.0, 1.0, 1.0], [0.0, 0.0, 0.0]]).unwrap().normalized(),
+        ];
+        let down = [
+            Kernel::from_rows(&[[1.0], [1.0], [1.0]]).unwrap().normalized(),
+            Kernel::from_rows(&[[0.0, 1.0, 0.0], [0.0, 1.0, 0.0], [0.0, 1.0, 0.0]]).unwrap().normalized(),
+        ];
+        for (border, expected) in MODES.into_iter().zip(expected) {
+            for kernel in &across {
+                let output = apply_convolution(&grey(5, 1, &row), kernel, border, AlphaMode::Straight);
+                assert_eq!(output.dimensions(), (expected.len() as u32, 1), "{:?}", border);
+                assert_eq!(values(&output), expected, "{:?}, {}x{} kernel", border, kernel.width(), kernel.height());
+            }
+            for kernel in &down {
+                let output = apply_convolution(&grey(1, 5, &row), kernel, border, AlphaMode::Straight);
+                assert_eq!(output.dimensions(), (1, expected.len() as u32), "{:?}", border);
+                assert_eq!(values(&output), expected, "{:?}, {}x{} kernel", border, kernel.width(), kernel.height());
+            }
+        }
+    }
+
+    // A box blur of a single pixel: every mode but Constant only has that pixel to average
+    #[test]
+    fn single_pixel() {
+        for radius in [1, 2] {
+            let kernel = Kernel::box_blur(radius).unwrap();
+            for border in MODES {
+                let output = apply_convolution(&grey(1, 1, &[90]), &kernel, border, AlphaMode::Premultiplied);
+                let expected = match border {
+                    // 90 and 8 (or 24) black pixels
+                    BorderMode::Constant(_) if radius == 1 => 10,
+                    BorderMode::Constant(_) => 4,
+                    _ => 90,
+                };
+                assert_eq!(output.dimensions(), (1, 1), "{:?}", border);
+                assert_eq!(*output.get_pixel(0, 0), Rgba([expected, expected, expected, 255]), "{:?}, radius {}", border, radius);
+            }
+        }
+    }
+
+    // The 3x3 version has to give exactly the same bytes as the general one
+    #[test]
+    fn fast_3x3_matches_general() {
+        let kernels = [
+            Kernel::from_rows(&[[-2.0, -1.0, 0.0], [-1.0, 1.0, 1.0], [0.0, 1.0, 2.0]]).unwrap(),
+            Kernel::from_rows(&[[0.0, -1.0, 0.0], [-1.0, 5.0, -1.0], [0.0, -1.0, 0.0]]).unwrap(),
+            Kernel::from_rows(&[[1.0, 2.0, 1.0], [2.0, 4.0, 2.0], [1.0, 2.0, 1.0]]).unwrap().normalized(),
+            Kernel::from_rows(&[[0.13, -7.5, 1e-3], [3.3, 0.7, -0.25], [9.0, -1.0, 0.5]]).unwrap().with_divisor(3.0).unwrap().with_bias(64.0),
+        ];
+        let mut modes = MODES.to_vec();
+        modes.push(BorderMode::Constant(Rgba([200, 10, 60, 128])));
+        for (width, height) in [(1, 1), (1, 5), (5, 1), (2, 2), (7, 5), (19, 3)] {
+            let img = pattern(width, height);
+            for kernel in &kernels {
+                for &border in &modes {
+                    for alpha in [AlphaMode::Premultiplied, AlphaMode::Preserve, AlphaMode::Straight] {
+                        let skip = match border {
+                            BorderMode::Crop => ((width > 2) as u32, (height > 2) as u32),
+                            _ => (0, 0),
+                        };
+                        let outside = match border {
+                            BorderMode::Constant(color) => color,
+                            _ => Rgba([0, 0, 0, 0]),
+                        };
+                        assert_eq!(
+                            apply_convolution_3x3(&img, kernel, border, alpha, skip, outside),
+                            apply_convolution_any(&img, kernel, border, alpha, skip, outside),
+                            "{}x{}, {:?}, {:?}, {:?}",
+                            width,
+                            height,
+                            kernel,
+                            border,
+                            alpha
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    // Emboss, sharpen and a blur with a bias (convolve), all with the given alpha mode
+    fn alpha_filters(alpha: AlphaMode) -> [Box<dyn Filter>; 3] {
+        let blur = Kernel::from_rows(&[[1.0, 2.0, 1.0], [2.0, 4.0, 2.0], [1.0, 2.0, 1.0]]).unwrap().normalized().with_bias(3.0);
+        [
+            Box::new(Emboss { border: BorderMode::Clamp, alpha }),
+            Box::new(Sharpen { border: BorderMode::Clamp, alpha }),
+            Box::new(Convolve { kernel: blur, border: BorderMode::Clamp, alpha }),
+        ]
+    }
+
+    // An opaque white square in the middle of transparent red
+    // The red can't be seen, so none of it should end up in the white
+    fn cut_out() -> RgbaImage {
+        RgbaImage::from_fn(8, 8, |x, y| {
+            if (2..6).contains(&x) && (2..6).contains(&y) { Rgba([255, 255, 255, 255]) } else { Rgba([255, 0, 0, 0]) }
+        })
+    }
+
+    #[test]
+    fn premultiplied_has_no_fringe() {
+        for filter in alpha_filters(AlphaMode::Premultiplied) {
+            let output = filter.apply(&cut_out());
+            for (x, y, px) in output.enumerate_pixels() {
+                if px[3] > 0 {
+                    assert_eq!(px[1], px[0], "{} at {},{}: {:?}", filter.name(), x, y, px);
+                    assert_eq!(px[2], px[0], "{} at {},{}: {:?}", filter.name(), x, y, px);
+                }
+            }
+        }
+        // Which isn't true of Straight: the blur pulls the red into the edge of the square
+        let [_, _, blur] = alpha_filters(AlphaMode::Straight);
+        let px = *blur.apply(&cut_out()).get_pixel(2, 3);
+        assert!(px[3] > 0 && px[1] < px[0], "{:?}", px);
+    }
+
+    #[test]
+    fn preserve_keeps_alpha() {
+        let img = pattern(9, 7);
+        for filter in alpha_filters(AlphaMode::Preserve) {
+            let output = filter.apply(&img);
+            for (before, after) in img.pixels().zip(output.pixels()) {
+                assert_eq!(after[3], before[3], "{}", filter.name());
+            }
+        }
+    }
+
+    // Straight treats alpha as just another channel, the way every filter used to:
+    // the same as convolving each channel of the image on its own (with the bias on the colour channels only)
+    #[test]
+    fn straight_convolves_every_channel() {
+        let img = pattern(9, 7);
+        let kernels = [
+            Kernel::from_rows(&[[-2.0, -1.0, 0.0], [-1.0, 1.0, 1.0], [0.0, 1.0, 2.0]]).unwrap(),
+            Kernel::from_rows(&[[0.0, -1.0, 0.0], [-1.0, 5.0, -1.0], [0.0, -1.0, 0.0]]).unwrap(),
+            Kernel::from_rows(&[[1.0, 2.0, 1.0], [2.0, 4.0, 2.0], [1.0, 2.0, 1.0]]).unwrap().normalized().with_bias(3.0),
+        ];
+        for (filter, kernel) in alpha_filters(AlphaMode::Straight).iter().zip(&kernels) {
+            let output = filter.apply(&img);
+            for (x, y, px) in output.enumerate_pixels() {
+                for channel in 0..4 {
+                    let mut sum = 0.0;
+                    for ky in 0..3 {
+                        for kx in 0..3 {
+                            let sx = BorderMode::Clamp.resolve(x as i64 + kx - 1, 9).unwrap();
+                            let sy = BorderMode::Clamp.resolve(y as i64 + ky - 1, 7).unwrap();
+                            sum += kernel.weights()[(ky * 3 + kx) as usize] * img.get_pixel(sx, sy)[channel] as f32;
+                        }
+                    }
+                    let bias = if channel < 3 { kernel.bias() } else { 0.0 };
+                    let expected = (sum / kernel.divisor() + bias).round().clamp(0.0, 255.0) as u8;
+                    assert_eq!(px[channel], expected, "{} at {},{} channel {}", filter.name(), x, y, channel);
+                }
+            }
+        }
+    }
+}
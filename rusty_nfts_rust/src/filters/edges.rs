@@ -0,0 +1,538 @@
+use image::{Rgba, RgbaImage};
+
+use super::convolution::{convolve_plane, BorderMode, Kernel};
+use crate::error::FilterError;
+use crate::filter::Filter;
+use crate::filter_kind::FilterKind;
+use crate::parallel::map_pixels;
+use crate::spec::{ParamInfo, Params};
+
+// Edge detection: finding the places where the brightness changes sharply (the outlines of things)
+// All three filters work on the brightness of the image (colour edges of the same brightness don't count),
+// with transparent pixels counting as black so the outline of a cut-out is an edge too
+//   edges     => Sobel, Prewitt or Scharr: how steep the change in brightness is at every pixel (soft, thick edges)
+//   laplacian => Laplacian of Gaussian: where the change is steepest, after smoothing (thin edges)
+//   canny     => Canny: the classic, thin connected edges with the noise cleaned out
+// They're built on the convolution engine (Kernel + convolve_plane), on floats so the sign of a change isn't lost
+
+// What the edges are drawn as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EdgeOutput {
+    // White edges on black
+    #[default]
+    Map,
+    // The edges drawn in 'color' on top of the original image
+    Overlay,
+    // The edges coloured by which way they run (the hue is the direction the brightness increases in)
+    Direction,
+}
+
+impl EdgeOutput {
+    // The names used by the "output" parameter
+    pub const NAMES: [&'static str; 3] = ["map", "overlay", "direction"];
+    // For the filters that don't know the direction of their edges
+    pub const NAMES_WITHOUT_DIRECTION: [&'static str; 2] = ["map", "overlay"];
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "overlay" => EdgeOutput::Overlay,
+            "direction" => EdgeOutput::Direction,
+            _ => EdgeOutput::Map,
+        }
+    }
+}
+
+// The operators for working out how steep the brightness change is (the 'gradient')
+// Each one is a pair of 3x3 kernels, one for left to right changes and one (the same, turned) for top to bottom
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GradientOperator {
+    // [1 2 1] across: a good all rounder
+    #[default]
+    Sobel,
+    // [1 1 1] across: the simplest, a little more sensitive to noise
+    Prewitt,
+    // [3 10 3] across: the most accurate direction for diagonal edges
+    Scharr,
+}
+
+impl GradientOperator {
+    // The names used by the "operator" parameter
+    pub const NAMES: [&'static str; 3] = ["sobel", "prewitt", "scharr"];
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "prewitt" => GradientOperator::Prewitt,
+            "scharr" => GradientOperator::Scharr,
+            _ => GradientOperator::Sobel,
+        }
+    }
+
+    // The left to right and top to bottom kernels
+    // Divided by the sum of the weights on one side, so a sudden jump from black to white measures 255
+    fn kernels(self) -> (Kernel, Kernel) {
+        let (side, middle) = match self {
+            GradientOperator::Sobel => (1.0, 2.0),
+            GradientOperator::Prewitt => (1.0, 1.0),
+            GradientOperator::Scharr => (3.0, 10.0),
+        };
+        let divisor = 2.0 * side + middle;
+        let across = Kernel::from_rows(&[[-side, 0.0, side], [-middle, 0.0, middle], [-side, 0.0, side]]);
+        let down = Kernel::from_rows(&[[-side, -middle, -side], [0.0, 0.0, 0.0], [side, middle, side]]);
+        let finish = |kernel: Result<Kernel, FilterError>| kernel.and_then(|kernel| kernel.with_divisor(divisor)).expect("gradient kernels are valid");
+        (finish(across), finish(down))
+    }
+}
+
+const OUTPUT_PARAM: ParamInfo = ParamInfo::choice("output", "Output", &EdgeOutput::NAMES, "map");
+const OUTPUT_WITHOUT_DIRECTION_PARAM: ParamInfo = ParamInfo::choice("output", "Output", &EdgeOutput::NAMES_WITHOUT_DIRECTION, "map");
+const COLOR_PARAM: ParamInfo = ParamInfo::color("color", "Edge Colour", "#ff0000ff");
+// How much the image is smoothed first, so noise and fine texture aren't picked up as edges
+const SIGMA_PARAM: ParamInfo = ParamInfo::number("sigma", "Smoothing", 0.5, 5.0, 1.4, 0.1);
+
+const EDGE_DETECT_PARAMS: [ParamInfo; 5] = [
+    ParamInfo::choice("operator", "Operator", &GradientOperator::NAMES, "sobel"),
+    // Multiplies the edge strength, to bring out faint edges
+    ParamInfo::number("strength", "Strength", 0.1, 10.0, 1.0, 0.1),
+    // Edges weaker than this (0 - 255, before 'strength') are dropped
+    ParamInfo::integer("threshold", "Threshold", 0.0, 255.0, 0.0),
+    OUTPUT_PARAM,
+    COLOR_PARAM,
+];
+
+// Gradient edge detection: the edge strength of every pixel is how steep the brightness change is there
+// (the 'gradient magnitude', sqrt(across^2 + down^2))
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EdgeDetect {
+    pub operator: GradientOperator,
+    pub strength: f32,
+    pub threshold: f32,
+    pub output: EdgeOutput,
+    pub color: Rgba<u8>,
+}
+
+impl Default for EdgeDetect {
+    fn default() -> Self {
+        EdgeDetect { operator: GradientOperator::default(), strength: 1.0, threshold: 0.0, output: EdgeOutput::default(), color: Rgba([255, 0, 0, 255]) }
+    }
+}
+
+impl Filter for EdgeDetect {
+    fn name(&self) -> &str {
+        FilterKind::EdgeDetect.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::EdgeDetect.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &EDGE_DETECT_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        Ok(Box::new(EdgeDetect {
+            operator: GradientOperator::from_name(params.choice("operator")?),
+            strength: params.number("strength")? as f32,
+            threshold: params.number("threshold")? as f32,
+            output: EdgeOutput::from_name(params.choice("output")?),
+            color: Rgba(params.color("color")?),
+        }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        let gradients = Gradients::new(&luminance(img), img.width(), img.height(), self.operator);
+        let edges: Vec<f32> = gradients
+            .magnitudes()
+            .map(|magnitude| if magnitude < self.threshold { 0.0 } else { magnitude * self.strength })
+            .collect();
+        draw_edges(img, &edges, Some(&gradients), self.output, self.color)
+    }
+}
+
+const LAPLACIAN_PARAMS: [ParamInfo; 4] = [
+    SIGMA_PARAM,
+    // How big the brightness change across an edge has to be (0 - 255) for it to count
+    ParamInfo::integer("threshold", "Threshold", 0.0, 255.0, 8.0),
+    OUTPUT_WITHOUT_DIRECTION_PARAM,
+    COLOR_PARAM,
+];
+
+// Laplacian of Gaussian (the 'Marr-Hildreth' edge detector): smooths the image, then measures how the steepness
+// itself is changing (the 'Laplacian'). That crosses zero right in the middle of every edge, where the change is
+// steepest, so the edges are the pixels where it changes sign (the 'zero crossings')
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LaplacianOfGaussian {
+    pub sigma: f32,
+    pub threshold: f32,
+    pub output: EdgeOutput,
+    pub color: Rgba<u8>,
+}
+
+impl Default for LaplacianOfGaussian {
+    fn default() -> Self {
+        LaplacianOfGaussian { sigma: 1.4, threshold: 8.0, output: EdgeOutput::default(), color: Rgba([255, 0, 0, 255]) }
+    }
+}
+
+impl Filter for LaplacianOfGaussian {
+    fn name(&self) -> &str {
+        FilterKind::Laplacian.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Laplacian.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &LAPLACIAN_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        Ok(Box::new(LaplacianOfGaussian {
+            sigma: params.number("sigma")? as f32,
+            threshold: params.number("threshold")? as f32,
+            output: EdgeOutput::from_name(params.choice("output")?),
+            color: Rgba(params.color("color")?),
+        }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        let (width, height) = img.dimensions();
+        let laplacian = laplacian_of_gaussian(&luminance(img), width, height, self.sigma);
+
+        // Wherever a pixel and the one to its right (or below it) have opposite signs and are far enough apart,
+        // the edge runs between them, and the one closer to 0 is marked (so the lines are 1 pixel wide)
+        let (w, h) = (width as usize, height as usize);
+        let mut edges = vec![0.0f32; laplacian.len()];
+        for y in 0..h {
+            for x in 0..w {
+                let index = y * w + x;
+                let here = laplacian[index];
+                let right = (x + 1 < w).then(|| index + 1);
+                let below = (y + 1 < h).then(|| index + w);
+                for other in [right, below].into_iter().flatten() {
+                    let there = laplacian[other];
+                    if here * there < 0.0 && (here - there).abs() > self.threshold {
+                        edges[if here.abs() <= there.abs() { index } else { other }] = 255.0;
+                    }
+                }
+            }
+        }
+        draw_edges(img, &edges, None, self.output, self.color)
+    }
+}
+
+const CANNY_PARAMS: [ParamInfo; 5] = [
+    SIGMA_PARAM,
+    // Edges (0 - 255) weaker than low_threshold are dropped, ones stronger than high_threshold are kept,
+    // and the ones in between are only kept if they join up with a kept one
+    ParamInfo::integer("low_threshold", "Low Threshold", 0.0, 255.0, 20.0),
+    ParamInfo::integer("high_threshold", "High Threshold", 0.0, 255.0, 50.0),
+    OUTPUT_PARAM,
+    COLOR_PARAM,
+];
+
+// Canny edge detection, in four steps:
+//   1. smooth the image (a Gaussian blur of 'sigma'), so noise isn't picked up
+//   2. work out the gradient (Sobel) of every pixel
+//   3. thin the edges to 1 pixel: only keep the pixels that are stronger than both their neighbours across the edge
+//      (the 'non-maximum suppression')
+//   4. keep the strong edges, plus the weaker ones that are connected to a strong one (the 'hysteresis')
+//      so an edge that fades in and out is kept whole, but lone specks of noise aren't
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Canny {
+    pub sigma: f32,
+    pub low_threshold: f32,
+    // Must be at least low_threshold
+    pub high_threshold: f32,
+    pub output: EdgeOutput,
+    pub color: Rgba<u8>,
+}
+
+impl Default for Canny {
+    fn default() -> Self {
+        Canny { sigma: 1.4, low_threshold: 20.0, high_threshold: 50.0, output: EdgeOutput::default(), color: Rgba([255, 0, 0, 255]) }
+    }
+}
+
+impl Filter for Canny {
+    fn name(&self) -> &str {
+        FilterKind::Canny.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Canny.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &CANNY_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        let canny = Canny {
+            sigma: params.number("sigma")? as f32,
+            low_threshold: params.number("low_threshold")? as f32,
+            high_threshold: params.number("high_threshold")? as f32,
+            output: EdgeOutput::from_name(params.choice("output")?),
+            color: Rgba(params.color("color")?),
+        };
+        // The ranges alone can't stop these crossing over
+        if canny.high_threshold < canny.low_threshold {
+            return Err(FilterError::InvalidParameter {
+                name: "high_threshold".to_string(),
+                reason: format!("{} must be at least the low threshold ({})", canny.high_threshold, canny.low_threshold),
+            });
+        }
+        Ok(Box::new(canny))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        let (width, height) = img.dimensions();
+        let (w, h) = (width as usize, height as usize);
+
+        // 1. and 2.
+        let smoothed = gaussian_plane(&luminance(img), width, height, self.sigma);
+        let gradients = Gradients::new(&smoothed, width, height, GradientOperator::Sobel);
+        let magnitudes: Vec<f32> = gradients.magnitudes().collect();
+
+        // 3. compare each pixel with its two neighbours across the edge, i.e. along the gradient
+        // (rounded to the nearest of 4 directions: left/right, up/down and the two diagonals)
+        let magnitude = |x: usize, y: usize, dx: i64, dy: i64| {
+            let (x, y) = (x as i64 + dx, y as i64 + dy);
+            if x < 0 || y < 0 || x >= w as i64 || y >= h as i64 { 0.0 } else { magnitudes[y as usize * w + x as usize] }
+        };
+        let mut thin = vec![0.0f32; magnitudes.len()];
+        for y in 0..h {
+            for x in 0..w {
+                let index = y * w + x;
+                let value = magnitudes[index];
+                if value < self.low_threshold || value == 0.0 {
+                    continue;
+                }
+                // The gradient's angle, folded into 0 - 180 (the two neighbours are the same either way round)
+                let angle = gradients.direction(index).rem_euclid(180.0);
+                let (dx, dy) = match angle {
+                    a if !(22.5..157.5).contains(&a) => (1, 0),
+                    a if a < 67.5 => (1, 1),
+                    a if a < 112.5 => (0, 1),
+                    _ => (-1, 1),
+                };
+                // >= on one side and > on the other, so a flat topped ridge 2 pixels wide keeps exactly one of them
+                if value >= magnitude(x, y, dx, dy) && value > magnitude(x, y, -dx, -dy) {
+                    thin[index] = value;
+                }
+            }
+        }
+
+        // 4. start from every strong pixel and follow its weaker neighbours (in all 8 directions)
+        let mut edges = vec![0.0f32; thin.len()];
+        let mut to_visit: Vec<usize> = (0..thin.len()).filter(|&index| thin[index] >= self.high_threshold).collect();
+        for &index in &to_visit {
+            edges[index] = 255.0;
+        }
+        while let Some(index) = to_visit.pop() {
+            let (x, y) = ((index % w) as i64, (index / w) as i64);
+            for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= w as i64 || ny >= h as i64 {
+                    continue;
+                }
+                let neighbour = ny as usize * w + nx as usize;
+                // thin only has pixels above low_threshold in it
+                if thin[neighbour] > 0.0 && edges[neighbour] == 0.0 {
+                    edges[neighbour] = 255.0;
+                    to_visit.push(neighbour);
+                }
+            }
+        }
+        draw_edges(img, &edges, Some(&gradients), self.output, self.color)
+    }
+}
+
+// How the brightness changes at every pixel, left to right and top to bottom
+struct Gradients {
+    across: Vec<f32>,
+    down: Vec<f32>,
+}
+
+impl Gradients {
+    fn new(plane: &[f32], width: u32, height: u32, operator: GradientOperator) -> Self {
+        let (across, down) = operator.kernels();
+        Gradients {
+            across: convolve_plane(plane, width, height, &across, BorderMode::Clamp),
+            down: convolve_plane(plane, width, height, &down, BorderMode::Clamp),
+        }
+    }
+
+    // How steep the change is, whichever way it goes
+    fn magnitudes(&self) -> impl Iterator<Item = f32> + '_ {
+        self.across.iter().zip(&self.down).map(|(across, down)| across.hypot(*down))
+    }
+
+    // Which way the brightness increases, in degrees (0 => to the right, 90 => down)
+    fn direction(&self, index: usize) -> f32 {
+        self.down[index].atan2(self.across[index]).to_degrees().rem_euclid(360.0)
+    }
+}
+
+// The brightness of every pixel (0 - 255, row by row), with transparent pixels counting as black
+fn luminance(img: &RgbaImage) -> Vec<f32> {
+    img.pixels()
+        .map(|&Rgba([r, g, b, a])| (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32) * a as f32 / 255.0)
+        .collect()
+}
+
+// How far a kernel for 'sigma' needs to reach (3 sigma covers 99.7% of a Gaussian), within Kernel::MAX_SIZE
+fn kernel_radius(sigma: f32) -> usize {
+    ((sigma * 3.0).ceil() as usize).clamp(1, Kernel::MAX_SIZE / 2)
+}
+
+// 1D kernels, as a row (size x 1) and as a column (1 x size)
+// Blurs and the Laplacian of Gaussian can be split into a pass across and a pass down,
+// which is much quicker than one big square kernel
+fn row_and_column(weights: Vec<f32>, divisor: f32) -> (Kernel, Kernel) {
+    let size = weights.len();
+    let kernel = |width, height| Kernel::new(width, height, weights.clone()).and_then(|kernel| kernel.with_divisor(divisor));
+    (kernel(size, 1).expect("1D kernels are valid"), kernel(1, size).expect("1D kernels are valid"))
+}
+
+// The 1D Gaussian, not yet divided by its sum
+fn gaussian_weights(sigma: f32) -> Vec<f32> {
+    let radius = kernel_radius(sigma) as i64;
+    (-radius..=radius).map(|x| (-(x * x) as f32 / (2.0 * sigma * sigma)).exp()).collect()
+}
+
+fn gaussian_plane(plane: &[f32], width: u32, height: u32, sigma: f32) -> Vec<f32> {
+    let weights = gaussian_weights(sigma);
+    let sum = weights.iter().sum();
+    let (across, down) = row_and_column(weights, sum);
+    let blurred = convolve_plane(plane, width, height, &across, BorderMode::Clamp);
+    convolve_plane(&blurred, width, height, &down, BorderMode::Clamp)
+}
+
+// The Laplacian of Gaussian, as (second derivative of the Gaussian across, then the Gaussian down)
+// plus (the Gaussian across, then its second derivative down)
+// Scaled by sigma^2 so a black to white edge gives about the same size result whatever the smoothing
+fn laplacian_of_gaussian(plane: &[f32], width: u32, height: u32, sigma: f32) -> Vec<f32> {
+    let gaussian = gaussian_weights(sigma);
+    let sum: f32 = gaussian.iter().sum();
+    let radius = (gaussian.len() / 2) as f32;
+    // The second derivative, (x^2 / sigma^2 - 1) * gaussian (times sigma^2 / sigma^2 for the scaling above),
+    // with its average taken off so it adds up to exactly 0 (a flat area gives exactly 0)
+    let mut second: Vec<f32> = gaussian.iter().enumerate().map(|(i, g)| ((i as f32 - radius).powi(2) / (sigma * sigma) - 1.0) * g).collect();
+    let average = second.iter().sum::<f32>() / second.len() as f32;
+    second.iter_mut().for_each(|weight| *weight -= average);
+
+    let (smooth_across, smooth_down) = row_and_column(gaussian, sum);
+    let (second_across, second_down) = row_and_column(second, sum);
+    let convolve = |plane: &[f32], kernel: &Kernel| convolve_plane(plane, width, height, kernel, BorderMode::Clamp);
+    let across = convolve(&convolve(plane, &second_across), &smooth_down);
+    let down = convolve(&convolve(plane, &smooth_across), &second_down);
+    across.iter().zip(&down).map(|(a, b)| a + b).collect()
+}
+
+// Turns the edge strengths (0 - 255, anything higher counts as 255) into the chosen output
+// gradients => the directions, for EdgeOutput::Direction (filters without them draw a map instead)
+fn draw_edges(img: &RgbaImage, edges: &[f32], gradients: Option<&Gradients>, output: EdgeOutput, color: Rgba<u8>) -> RgbaImage {
+    let width = img.width() as usize;
+    map_pixels(img, |x, y, original| {
+        let index = y as usize * width + x as usize;
+        let strength = (edges[index] / 255.0).clamp(0.0, 1.0);
+        let level = (strength * 255.0 + 0.5) as u8;
+        match (output, gradients) {
+            (EdgeOutput::Direction, Some(gradients)) => {
+                let [r, g, b] = hue_to_rgb(gradients.direction(index));
+                let scale = |value: f32| (value * strength * 255.0 + 0.5) as u8;
+                Rgba([scale(r), scale(g), scale(b), 255])
+            }
+            (EdgeOutput::Overlay, _) => over(color, strength, original),
+            _ => Rgba([level, level, level, 255]),
+        }
+    })
+}
+
+// The edge colour laid over the original pixel, 'strength' (0 - 1) of the way
+// (the usual 'over' blending, so it shows up on transparent areas too)
+fn over(Rgba(color): Rgba<u8>, strength: f32, Rgba(original): Rgba<u8>) -> Rgba<u8> {
+    let top = strength * color[3] as f32 / 255.0;
+    let bottom = original[3] as f32 / 255.0 * (1.0 - top);
+    let alpha = top + bottom;
+    if alpha <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let channel = |c: usize| ((color[c] as f32 * top + original[c] as f32 * bottom) / alpha + 0.5).min(255.0) as u8;
+    Rgba([channel(0), channel(1), channel(2), (alpha * 255.0 + 0.5) as u8])
+}
+
+// A fully saturated colour for an angle around the colour wheel (0 => red, 120 => green, 240 => blue), each 0 - 1
+fn hue_to_rgb(degrees: f32) -> [f32; 3] {
+    let hue = degrees.rem_euclid(360.0) / 60.0;
+    let rising = hue.fract();
+    let falling = 1.0 - rising;
+    match hue as u32 {
+        0 => [1.0, rising, 0.0],
+        1 => [falling, 1.0, 0.0],
+        2 => [0.0, 1.0, rising],
+        3 => [0.0, falling, 1.0],
+        4 => [rising, 0.0, 1.0],
+        _ => [1.0, 0.0, falling],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spec::FilterSpec;
+
+    // Black on the left half, white on the right
+    fn vertical_step() -> RgbaImage {
+        RgbaImage::from_fn(20, 12, |x, _| if x < 10 { Rgba([0, 0, 0, 255]) } else { Rgba([255, 255, 255, 255]) })
+    }
+
+    // The x of every edge pixel in each row of an edge map
+    fn edge_columns(edges: &RgbaImage) -> Vec<Vec<u32>> {
+        (0..edges.height()).map(|y| (0..edges.width()).filter(|&x| edges.get_pixel(x, y)[0] > 0).collect()).collect()
+    }
+
+    #[test]
+    fn canny_step_is_one_pixel_wide() {
+        let edges = FilterSpec::new("canny").build().unwrap().apply(&vertical_step());
+        let columns = edge_columns(&edges);
+        // The same single pixel, right next to the step, in every row (including the ones at the top and bottom border)
+        assert!(columns[0] == [9] || columns[0] == [10], "{:?}", columns[0]);
+        assert!(columns.iter().all(|row| *row == columns[0]), "{:?}", columns);
+        assert_eq!(edges.get_pixel(columns[0][0], 0).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn laplacian_step_is_one_pixel_wide() {
+        let edges = FilterSpec::new("laplacian").build().unwrap().apply(&vertical_step());
+        let columns = edge_columns(&edges);
+        assert!(columns[0] == [9] || columns[0] == [10], "{:?}", columns[0]);
+        assert!(columns.iter().all(|row| *row == columns[0]), "{:?}", columns);
+    }
+
+    #[test]
+    fn flat_image_has_no_edges() {
+        for img in [RgbaImage::from_pixel(15, 9, Rgba([90, 160, 30, 255])), RgbaImage::from_pixel(15, 9, Rgba([90, 160, 30, 0]))] {
+            for filter in ["edges", "laplacian", "canny"] {
+                let edges = FilterSpec::new(filter).build().unwrap().apply(&img);
+                assert!(edges.pixels().all(|pixel| pixel.0 == [0, 0, 0, 255]), "{} found edges in a flat image", filter);
+            }
+            let edges = FilterSpec::new("edges").with("operator", "scharr").with("strength", 10.0).build().unwrap().apply(&img);
+            assert!(edges.pixels().all(|pixel| pixel.0 == [0, 0, 0, 255]));
+        }
+    }
+
+    #[test]
+    fn swapped_thresholds() {
+        let spec = FilterSpec::new("canny").with("low_threshold", 60).with("high_threshold", 30);
+        match spec.build() {
+            Err(FilterError::InvalidParameter { name, .. }) => assert_eq!(name, "high_threshold"),
+            Err(error) => panic!("wrong error: {}", error),
+            Ok(_) => panic!("low_threshold above high_threshold was accepted"),
+        }
+        // Equal is fine: only the hysteresis step goes (every edge above the threshold is kept)
+        assert!(FilterSpec::new("canny").with("low_threshold", 40).with("high_threshold", 40).build().is_ok());
+    }
+}
@@ -0,0 +1,344 @@
+use image::{Rgba, RgbaImage};
+
+use super::adjust::{apply_luts, lookup_table};
+use super::alpha::to_channel;
+use super::color::GrayscaleMethod;
+use crate::error::FilterError;
+use crate::filter::Filter;
+use crate::filter_kind::FilterKind;
+use crate::histogram::Histogram;
+use crate::parallel::map_pixels;
+use crate::spec::{ParamInfo, Params};
+
+// Histogram equalization: spreads the tones out so they use the whole range from black to white evenly
+// Great for dark or foggy photos where everything is squashed into a few values
+// (see histogram.rs for what a histogram is)
+//
+// Both filters work on the luminance (brightness) by default and then move red, green and blue by the same amount,
+// so the colours don't shift (equalizing each channel on its own would turn a blue sky grey, for example)
+// Fully transparent pixels are left out of the histograms, like in Histogram
+
+// What Equalize evens out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EqualizeMode {
+    // The brightness, keeping the colours
+    #[default]
+    Luminance,
+    // Each channel on its own: stronger, but changes the colours (the classic look)
+    Rgb,
+}
+
+impl EqualizeMode {
+    // The names used by the "mode" parameter
+    pub const NAMES: [&'static str; 2] = ["luminance", "rgb"];
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "rgb" => EqualizeMode::Rgb,
+            _ => EqualizeMode::Luminance,
+        }
+    }
+}
+
+const EQUALIZE_PARAMS: [ParamInfo; 1] = [ParamInfo::choice("mode", "Mode", &EqualizeMode::NAMES, "luminance")];
+
+// Global histogram equalization: one mapping for the whole image, built from its histogram
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Equalize {
+    pub mode: EqualizeMode,
+}
+
+impl Filter for Equalize {
+    fn name(&self) -> &str {
+        FilterKind::Equalize.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Equalize.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &EQUALIZE_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        Ok(Box::new(Equalize { mode: EqualizeMode::from_name(params.choice("mode")?) }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        let histogram = Histogram::from_image(img);
+        match self.mode {
+            EqualizeMode::Luminance => {
+                let lut = equalize_table(&histogram.luminance.bins);
+                shift_luminance(img, |_, _, luma| lut[luma as usize] as f32)
+            }
+            EqualizeMode::Rgb => {
+                let luts = [&histogram.red, &histogram.green, &histogram.blue].map(|channel| equalize_table(&channel.bins));
+                apply_luts(img, &luts)
+            }
+        }
+    }
+}
+
+// The classic mapping: each value moves to where it sits in the running total of the histogram
+// (the 'cumulative distribution'), stretched so the darkest value present becomes black and all of them fill 0 - 255
+// e.g. if half the pixels are at or below 40, then 40 becomes ~128
+fn equalize_table(bins: &[u32]) -> [u8; 256] {
+    let total: u64 = bins.iter().map(|&n| n as u64).sum();
+    // The count of the darkest value present (everything at it becomes black)
+    let first = bins.iter().find(|&&n| n > 0).copied().unwrap_or(0) as u64;
+    // One value (or no pixels at all) can't be spread out, so leave it alone
+    if total == first {
+        return lookup_table(|value| value);
+    }
+    let mut running = 0u64;
+    let cumulative: [u64; 256] = std::array::from_fn(|value| {
+        running += bins[value] as u64;
+        running
+    });
+    lookup_table(|value| {
+        let below = cumulative[value as usize].saturating_sub(first);
+        below as f32 / (total - first) as f32 * 255.0
+    })
+}
+
+// CLAHE ('Contrast Limited Adaptive Histogram Equalization'), which gets a lot more detail out of
+// photos that are dark in some places and bright in others:
+//   1. split the image into tiles of about tile_size x tile_size pixels, and equalize each tile on its own
+//   2. but first cut every bar of each tile's histogram down to clip_limit times the average bar
+//      (and share what was cut off out between all the bars), so flat areas (a clear sky) don't turn into noise
+//   3. blend between the 4 nearest tiles' mappings for every pixel, so there are no seams between tiles
+const CLAHE_PARAMS: [ParamInfo; 2] = [
+    ParamInfo::integer("tile_size", "Tile Size", 8.0, 1024.0, 64.0),
+    // 1 => the gentlest, higher => more contrast (and more noise)
+    ParamInfo::number("clip_limit", "Clip Limit", 1.0, 40.0, 2.0, 0.1),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Clahe {
+    // In pixels, the tiles are stretched a little so a whole number of them fits each way
+    pub tile_size: u32,
+    pub clip_limit: f32,
+}
+
+impl Default for Clahe {
+    fn default() -> Self {
+        Clahe { tile_size: 64, clip_limit: 2.0 }
+    }
+}
+
+impl Filter for Clahe {
+    fn name(&self) -> &str {
+        FilterKind::Clahe.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Clahe.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &CLAHE_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        Ok(Box::new(Clahe { tile_size: params.number("tile_size")? as u32, clip_limit: params.number("clip_limit")? as f32 }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        let (width, height) = img.dimensions();
+        if width == 0 || height == 0 {
+            return img.clone();
+        }
+        // How many tiles fit each way (at least 1), and how big they really are
+        let tile_size = self.tile_size.max(1) as f32;
+        let tiles_x = ((width as f32 / tile_size).round() as usize).max(1);
+        let tiles_y = ((height as f32 / tile_size).round() as usize).max(1);
+        let (tile_width, tile_height) = (width as f32 / tiles_x as f32, height as f32 / tiles_y as f32);
+        let tile_of = |position: u32, size: f32, tiles: usize| ((position as f32 / size) as usize).min(tiles - 1);
+
+        // 1. every tile's luminance histogram
+        let mut histograms = vec![[0u32; 256]; tiles_x * tiles_y];
+        for (x, y, &Rgba([r, g, b, a])) in img.enumerate_pixels() {
+            if a > 0 {
+                let tile = tile_of(y, tile_height, tiles_y) * tiles_x + tile_of(x, tile_width, tiles_x);
+                histograms[tile][GrayscaleMethod::Rec709.luma(r, g, b) as usize] += 1;
+            }
+        }
+        // 2. clipped and turned into mappings
+        let luts: Vec<[f32; 256]> = histograms.iter().map(|bins| clipped_table(bins, self.clip_limit)).collect();
+
+        // 3. blended between the tiles whose centres are around each pixel
+        // (along the edges there's only one tile on the outside, so that one is used on its own)
+        let neighbours = |position: u32, size: f32, tiles: usize| {
+            let from_centre = ((position as f32 + 0.5) / size - 0.5).max(0.0);
+            let before = (from_centre as usize).min(tiles - 1);
+            let after = (before + 1).min(tiles - 1);
+            (before, after, (from_centre - before as f32).min(1.0))
+        };
+        shift_luminance(img, |x, y, luma| {
+            let (left, right, across) = neighbours(x, tile_width, tiles_x);
+            let (top, bottom, down) = neighbours(y, tile_height, tiles_y);
+            let value = |tile_x: usize, tile_y: usize| luts[tile_y * tiles_x + tile_x][luma as usize];
+            let upper = value(left, top) + (value(right, top) - value(left, top)) * across;
+            let lower = value(left, bottom) + (value(right, bottom) - value(left, bottom)) * across;
+            upper + (lower - upper) * down
+        })
+    }
+}
+
+// A tile's mapping (not rounded yet, that happens after blending), with the histogram clipped first
+fn clipped_table(bins: &[u32; 256], clip_limit: f32) -> [f32; 256] {
+    let total: u32 = bins.iter().sum();
+    if total == 0 {
+        return std::array::from_fn(|value| value as f32);
+    }
+    // The most pixels any one value can have, and what's cut off above that
+    // (as fractions of a pixel: rounding these to whole pixels gave tiles with slightly different pixel counts
+    // different mappings, so a flat area came out blotchy)
+    let limit = clip_limit as f64 * total as f64 / 256.0;
+    let clipped = bins.map(|n| (n as f64).min(limit));
+    let excess = total as f64 - clipped.iter().sum::<f64>();
+    // Shared out evenly between all the values
+    let share = excess / 256.0;
+    // Unlike Equalize, the darkest value isn't pulled down to black: a tile that's all mid grey should stay (about) mid grey
+    let mut running = 0.0;
+    std::array::from_fn(|value| {
+        running += clipped[value] + share;
+        (running / total as f64 * 255.0) as f32
+    })
+}
+
+// Works out each pixel's new luminance (from its position and current luminance) and moves red, green and blue
+// by the same amount, which changes the brightness but keeps the colour
+fn shift_luminance(img: &RgbaImage, new_luma: impl Fn(u32, u32, u8) -> f32 + Sync) -> RgbaImage {
+    map_pixels(img, |x, y, Rgba([r, g, b, a])| {
+        let luma = GrayscaleMethod::Rec709.luma(r, g, b);
+        let shift = new_luma(x, y, luma) - luma as f32;
+        Rgba([to_channel(r as f32 + shift), to_channel(g as f32 + shift), to_channel(b as f32 + shift), a])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [EqualizeMode; 2] = [EqualizeMode::Luminance, EqualizeMode::Rgb];
+
+    // A muddy, low contrast photo-like image: colours between 60 and 160, every pixel a little different
+    fn muddy() -> RgbaImage {
+        RgbaImage::from_fn(40, 30, |x, y| Rgba([(60 + x * 2 + y) as u8, (80 + (x * y) % 50) as u8, (100 + y * 2) as u8, 255]))
+    }
+
+    // One value can't be spread out, so nothing changes
+    #[test]
+    fn single_value() {
+        for color in [Rgba([0, 0, 0, 255]), Rgba([90, 140, 200, 255]), Rgba([255, 255, 255, 128])] {
+            let img = RgbaImage::from_pixel(7, 5, color);
+            for mode in MODES {
+                assert_eq!(Equalize { mode }.apply(&img), img, "{:?}, {:?}", color, mode);
+            }
+        }
+        assert_eq!(equalize_table(&[0; 256]), lookup_table(|value| value));
+    }
+
+    // Two values become black and white
+    #[test]
+    fn two_values() {
+        let img = RgbaImage::from_fn(6, 4, |x, y| if (x + y) % 3 == 0 { Rgba([50, 50, 50, 255]) } else { Rgba([150, 150, 150, 255]) });
+        for mode in MODES {
+            let output = Equalize { mode }.apply(&img);
+            for (px, original) in output.pixels().zip(img.pixels()) {
+                let expected = if original[0] == 50 { 0 } else { 255 };
+                assert_eq!(*px, Rgba([expected, expected, expected, 255]), "{:?}", mode);
+            }
+        }
+    }
+
+    // Luminance mode moves red, green and blue by the same amount, so the differences between them (the hue) stay put
+    // (apart from rounding, and the pixels pushed past black or white)
+    #[test]
+    fn luminance_keeps_hue() {
+        let img = muddy();
+        let outputs = [Equalize { mode: EqualizeMode::Luminance }.apply(&img), Clahe { tile_size: 16, clip_limit: 3.0 }.apply(&img)];
+        for output in outputs {
+            assert_ne!(output, img);
+            let mut checked = 0;
+            for (px, original) in output.pixels().zip(img.pixels()) {
+                if px.0[..3].iter().any(|&c| c == 0 || c == 255) {
+                    continue;
+                }
+                let differences = |p: &Rgba<u8>| [p[0] as i32 - p[1] as i32, p[1] as i32 - p[2] as i32];
+                for (after, before) in differences(px).into_iter().zip(differences(original)) {
+                    assert!((after - before).abs() <= 1, "{:?} => {:?}", original, px);
+                }
+                checked += 1;
+            }
+            assert!(checked > 100);
+        }
+    }
+
+    // Whatever is cut off the tall bars is shared out again, so the bars still add up to the same total
+    // (i.e. the table always ends at white) and no step is bigger than the clip limit allows
+    #[test]
+    fn clipped_excess_is_shared_out() {
+        let mut spike = [0u32; 256];
+        spike[40] = 1000;
+        let mut uneven = [0u32; 256];
+        for (value, n) in uneven.iter_mut().enumerate() {
+            *n = ((value * 7919) % 13) as u32 + if value > 200 { 300 } else { 0 };
+        }
+        let mut odd = [0u32; 256];
+        odd[3] = 1;
+        odd[250] = 2;
+        for bins in [spike, uneven, odd] {
+            let total: u32 = bins.iter().sum();
+            for clip_limit in [1.0, 2.0, 3.7, 40.0] {
+                let table = clipped_table(&bins, clip_limit);
+                assert!((table[255] - 255.0).abs() < 1e-3, "clip {}: {}", clip_limit, table[255]);
+                // A bar can be at most the limit, plus its share of what was cut off the others
+                let limit = clip_limit * total as f32 / 256.0;
+                let most = (limit + total as f32 / 256.0) / total as f32 * 255.0;
+                for value in 1..256 {
+                    let step = table[value] - table[value - 1];
+                    assert!(step >= 0.0 && step <= most + 1e-3, "clip {}, step {} at {}", clip_limit, step, value);
+                }
+            }
+        }
+    }
+
+    // Every tile of a flat image gets the same mapping, so it stays flat (and there are no seams)
+    #[test]
+    fn flat_image_stays_flat() {
+        for color in [Rgba([128, 128, 128, 255]), Rgba([30, 60, 90, 255])] {
+            let img = RgbaImage::from_pixel(100, 70, color);
+            for (tile_size, clip_limit) in [(8, 1.0), (16, 2.0), (64, 40.0)] {
+                let output = Clahe { tile_size, clip_limit }.apply(&img);
+                let first = *output.get_pixel(0, 0);
+                assert!(output.pixels().all(|px| *px == first), "{:?}, tile {}, clip {}", color, tile_size, clip_limit);
+            }
+        }
+    }
+
+    // The hidden colour of fully transparent pixels doesn't change what the visible ones turn into
+    #[test]
+    fn transparent_pixels_are_ignored() {
+        let cut_out = |hidden: Rgba<u8>| {
+            let img = muddy();
+            RgbaImage::from_fn(img.width(), img.height(), |x, y| if (x / 5 + y / 4) % 2 == 0 { *img.get_pixel(x, y) } else { hidden })
+        };
+        let (black, white) = (cut_out(Rgba([0, 0, 0, 0])), cut_out(Rgba([255, 255, 255, 0])));
+        let filters: [Box<dyn Filter>; 3] = [
+            Box::new(Equalize { mode: EqualizeMode::Luminance }),
+            Box::new(Equalize { mode: EqualizeMode::Rgb }),
+            Box::new(Clahe { tile_size: 16, clip_limit: 2.0 }),
+        ];
+        for filter in filters {
+            let (from_black, from_white) = (filter.apply(&black), filter.apply(&white));
+            for ((px, other), original) in from_black.pixels().zip(from_white.pixels()).zip(black.pixels()) {
+                if original[3] > 0 {
+                    assert_eq!(px, other, "{}", filter.name());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,54 @@
+// The built-in filters, each implemented on top of the Filter trait
+mod adjust;
+mod alpha;
+mod blur;
+mod color;
+mod convolution;
+mod edges;
+mod equalize;
+mod morphology;
+mod pixelate;
+mod posterize;
+mod tone;
+
+pub use adjust::{Brightness, Contrast, Exposure, Gamma, Saturation, Vibrance};
+pub use alpha::AlphaMode;
+pub use blur::{box_blur, fast_gaussian_blur, gaussian_blur, Blur, BlurMethod};
+pub use color::{Grayscale, GrayscaleMethod, HueRotate, Invert, Sepia};
+pub use convolution::{apply_convolution, BorderMode, Convolve, Emboss, Kernel, Sharpen};
+pub use edges::{Canny, EdgeDetect, EdgeOutput, GradientOperator, LaplacianOfGaussian};
+pub use equalize::{Clahe, Equalize, EqualizeMode};
+pub use morphology::{Morphology, MorphMode, MorphOperation, StructuringElement, ELEMENT_SHAPES};
+pub use pixelate::Pixelate;
+pub use posterize::{Dither, Posterize};
+pub use tone::{Curve, Curves, Levels, LevelsChannel};
+
+use crate::registry::FilterRegistry;
+
+// Adds the built-in filters, in the order the frontend shows them
+pub(crate) fn register_builtins(registry: &mut FilterRegistry) {
+    registry.register(Grayscale::default());
+    registry.register(Blur::default());
+    registry.register(HueRotate::default());
+    registry.register(Invert);
+    registry.register(Sepia);
+    registry.register(Pixelate::default());
+    registry.register(Emboss::default());
+    registry.register(Sharpen::default());
+    registry.register(Posterize::default());
+    registry.register(Convolve::default());
+    registry.register(Brightness::default());
+    registry.register(Contrast::default());
+    registry.register(Exposure::default());
+    registry.register(Gamma::default());
+    registry.register(Saturation::default());
+    registry.register(Vibrance::default());
+    registry.register(Levels::default());
+    registry.register(Curves::default());
+    registry.register(Equalize::default());
+    registry.register(Clahe::default());
+    registry.register(EdgeDetect::default());
+    registry.register(LaplacianOfGaussian::default());
+    registry.register(Canny::default());
+    registry.register(Morphology::default());
+}
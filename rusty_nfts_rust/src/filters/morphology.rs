@@ -0,0 +1,538 @@
+use image::{Rgba, RgbaImage};
+
+use super::color::GrayscaleMethod;
+use super::convolution::{BorderMode, BORDER_PARAMS};
+use crate::error::FilterError;
+use crate::filter::Filter;
+use crate::filter_kind::FilterKind;
+use crate::parallel::{build_rows, map_pixels};
+use crate::spec::{ParamInfo, Params};
+
+// Morphology: growing and shrinking the light and dark parts of an image, for cleaning up masks
+// (filling pin holes, removing specks) and for outline effects
+// Instead of a weighted sum like a convolution, every pixel becomes the darkest ('erode') or lightest ('dilate')
+// of its neighbours, where the neighbours are the cells of a 'structuring element' (e.g. a disk) around it
+// Everything else is built from those two:
+//   open      => erode then dilate: removes light specks smaller than the element, keeps the rest the same size
+//   close     => dilate then erode: fills dark holes and gaps smaller than the element
+//   gradient  => dilate minus erode: the outlines of things
+//   top_hat   => the image minus its opening: just the small light details
+//   black_hat => the closing minus the image: just the small dark details
+// Neighbours past the edge of the image come from the "border" mode, the same as the convolutions
+// The default, clamp, repeats the edge pixels, so the edges don't grow or shrink on their own
+// (for the square, disk and cross that's the same as only counting the neighbours inside the image)
+
+// Which of the above to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MorphOperation {
+    #[default]
+    Erode,
+    Dilate,
+    Open,
+    Close,
+    Gradient,
+    TopHat,
+    BlackHat,
+}
+
+impl MorphOperation {
+    // The names used by the "operation" parameter
+    pub const NAMES: [&'static str; 7] = ["erode", "dilate", "open", "close", "gradient", "top_hat", "black_hat"];
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "dilate" => MorphOperation::Dilate,
+            "open" => MorphOperation::Open,
+            "close" => MorphOperation::Close,
+            "gradient" => MorphOperation::Gradient,
+            "top_hat" => MorphOperation::TopHat,
+            "black_hat" => MorphOperation::BlackHat,
+            _ => MorphOperation::Erode,
+        }
+    }
+}
+
+// What 'darkest' and 'lightest' mean
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MorphMode {
+    // Each of red, green, blue and alpha on its own (so alpha masks can be cleaned up too)
+    // Can make colours that weren't in the image, e.g. red next to blue dilates to magenta
+    #[default]
+    Channels,
+    // Whole pixels, by their brightness: only colours already in the image, moved around
+    Luminance,
+    // Black and white: pixels at least 'threshold' bright (transparent counts as black) are white, the rest black
+    // The result is an opaque black and white mask
+    Binary,
+}
+
+impl MorphMode {
+    // The names used by the "mode" parameter
+    pub const NAMES: [&'static str; 3] = ["channels", "luminance", "binary"];
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "luminance" => MorphMode::Luminance,
+            "binary" => MorphMode::Binary,
+            _ => MorphMode::Channels,
+        }
+    }
+}
+
+// The names used by the "shape" parameter
+pub const ELEMENT_SHAPES: [&str; 4] = ["square", "disk", "cross", "custom"];
+
+// The neighbourhood each pixel looks at, centred on the pixel
+// Any odd width and height up to StructuringElement::MAX_SIZE (odd, so there's a middle)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructuringElement {
+    width: usize,
+    height: usize,
+    // Row by row, true => that neighbour counts
+    cells: Vec<bool>,
+}
+
+impl StructuringElement {
+    // The same limit as Kernel
+    pub const MAX_SIZE: usize = 31;
+
+    // A (2 * radius + 1) square
+    pub fn square(radius: usize) -> Self {
+        let size = radius * 2 + 1;
+        StructuringElement { width: size, height: size, cells: vec![true; size * size] }
+    }
+
+    // Every cell within 'radius' of the middle (radius 1 is the same as the cross)
+    pub fn disk(radius: usize) -> Self {
+        StructuringElement::from_fn(radius, |dx, dy| dx * dx + dy * dy <= (radius * radius) as i64)
+    }
+
+    // A plus sign, 'radius' cells along each arm
+    pub fn cross(radius: usize) -> Self {
+        StructuringElement::from_fn(radius, |dx, dy| dx == 0 || dy == 0)
+    }
+
+    fn from_fn(radius: usize, f: impl Fn(i64, i64) -> bool) -> Self {
+        let size = radius * 2 + 1;
+        let r = radius as i64;
+        let cells = (0..size * size).map(|i| f((i % size) as i64 - r, (i / size) as i64 - r)).collect();
+        StructuringElement { width: size, height: size, cells }
+    }
+
+    // Makes an element from rows of numbers, where anything but 0 is part of it
+    // e.g. [[0, 1, 0], [1, 1, 1], [0, 1, 0]] is a small cross
+    pub fn from_rows<R: AsRef<[f64]>>(rows: &[R]) -> Result<Self, FilterError> {
+        let (width, height) = (rows.first().map_or(0, |row| row.as_ref().len()), rows.len());
+        if rows.iter().any(|row| row.as_ref().len() != width) {
+            return Err(invalid_element("every row must have the same number of cells".to_string()));
+        }
+        if width.is_multiple_of(2) || height.is_multiple_of(2) {
+            return Err(invalid_element(format!("the size must be odd (so there is a middle cell), got {}x{}", width, height)));
+        }
+        let max = StructuringElement::MAX_SIZE;
+        if width > max || height > max {
+            return Err(invalid_element(format!("{}x{} is bigger than the maximum of {}x{}", width, height, max, max)));
+        }
+        let cells: Vec<bool> = rows.iter().flat_map(|row| row.as_ref().iter().map(|&cell| cell != 0.0)).collect();
+        if !cells.contains(&true) {
+            return Err(invalid_element("at least one cell must be non-zero".to_string()));
+        }
+        Ok(StructuringElement { width, height, cells })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        self.cells[y * self.width + x]
+    }
+
+    // Where each cell is, relative to the middle
+    fn offsets(&self) -> Vec<(i64, i64)> {
+        let (radius_x, radius_y) = ((self.width / 2) as i64, (self.height / 2) as i64);
+        (0..self.cells.len())
+            .filter(|&i| self.cells[i])
+            .map(|i| ((i % self.width) as i64 - radius_x, (i / self.width) as i64 - radius_y))
+            .collect()
+    }
+
+    // A completely filled in rectangle can be done as a row then a column, which is a lot quicker
+    // (the darkest pixel in a rectangle is the darkest of the darkest in each of its rows)
+    // Returns those two, or just the element itself if it isn't a full rectangle
+    fn passes(&self) -> Vec<Vec<(i64, i64)>> {
+        if self.cells.iter().all(|&cell| cell) && self.width > 1 && self.height > 1 {
+            let row = StructuringElement { width: self.width, height: 1, cells: vec![true; self.width] };
+            let column = StructuringElement { width: 1, height: self.height, cells: vec![true; self.height] };
+            vec![row.offsets(), column.offsets()]
+        } else {
+            vec![self.offsets()]
+        }
+    }
+}
+
+fn invalid_element(reason: String) -> FilterError {
+    FilterError::InvalidParameter { name: "element".to_string(), reason }
+}
+
+const DEFAULT_ELEMENT: &[&[f64]] = &[
+    &[0.0, 1.0, 0.0],
+    &[1.0, 1.0, 1.0],
+    &[0.0, 1.0, 0.0],
+];
+
+const MORPHOLOGY_PARAMS: [ParamInfo; 9] = [
+    ParamInfo::choice("operation", "Operation", &MorphOperation::NAMES, "erode"),
+    ParamInfo::choice("shape", "Shape", &ELEMENT_SHAPES, "disk"),
+    // How far the square, disk or cross reaches from the middle (not used by custom)
+    ParamInfo::integer("radius", "Radius", 1.0, 15.0, 1.0),
+    // The custom shape: anything but 0 is part of it
+    ParamInfo::matrix("element", "Custom Shape", DEFAULT_ELEMENT),
+    // Repeats the erodes and dilates, e.g. 3 iterations of a radius 1 disk is about a radius 3 disk (but quicker)
+    ParamInfo::integer("iterations", "Iterations", 1.0, 10.0, 1.0),
+    ParamInfo::choice("mode", "Mode", &MorphMode::NAMES, "channels"),
+    // Only used by binary mode
+    ParamInfo::integer("threshold", "Threshold", 0.0, 255.0, 128.0),
+    // Crop keeps only the pixels the edge of the image never reached (through every erode and dilate)
+    BORDER_PARAMS[0],
+    BORDER_PARAMS[1],
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Morphology {
+    pub operation: MorphOperation,
+    pub element: StructuringElement,
+    pub iterations: u32,
+    pub mode: MorphMode,
+    pub threshold: u8,
+    pub border: BorderMode,
+}
+
+impl Default for Morphology {
+    fn default() -> Self {
+        Morphology {
+            operation: MorphOperation::default(),
+            element: StructuringElement::disk(1),
+            iterations: 1,
+            mode: MorphMode::default(),
+            threshold: 128,
+            border: BorderMode::default(),
+        }
+    }
+}
+
+impl Filter for Morphology {
+    fn name(&self) -> &str {
+        FilterKind::Morphology.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Morphology.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &MORPHOLOGY_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        let radius = params.number("radius")? as usize;
+        let element = match params.choice("shape")? {
+            "square" => StructuringElement::square(radius),
+            "cross" => StructuringElement::cross(radius),
+            "custom" => StructuringElement::from_rows(&params.matrix("element")?)?,
+            // Params only ever hands back one of ELEMENT_SHAPES, so this is "disk"
+            _ => StructuringElement::disk(radius),
+        };
+        Ok(Box::new(Morphology {
+            operation: MorphOperation::from_name(params.choice("operation")?),
+            element,
+            iterations: params.number("iterations")? as u32,
+            mode: MorphMode::from_name(params.choice("mode")?),
+            threshold: params.number("threshold")? as u8,
+            border: BorderMode::from_params(params)?,
+        }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        let original = match self.mode {
+            MorphMode::Binary => map_pixels(img, |_, _, pixel| self.binary(pixel)),
+            _ => img.clone(),
+        };
+        // A binary image's border colour is black or white too, and Crop works like Clamp until the end
+        let border = match (self.border, self.mode) {
+            (BorderMode::Constant(color), MorphMode::Binary) => BorderMode::Constant(self.binary(color)),
+            (BorderMode::Crop, _) => BorderMode::Clamp,
+            (border, _) => border,
+        };
+        let erode = |img: &RgbaImage| self.repeat(img, Extreme::Darkest, border);
+        let dilate = |img: &RgbaImage| self.repeat(img, Extreme::Lightest, border);
+
+        let result = match self.operation {
+            MorphOperation::Erode => erode(&original),
+            MorphOperation::Dilate => dilate(&original),
+            MorphOperation::Open => dilate(&erode(&original)),
+            MorphOperation::Close => erode(&dilate(&original)),
+            MorphOperation::Gradient => difference(&dilate(&original), &erode(&original), &original),
+            MorphOperation::TopHat => difference(&original, &dilate(&erode(&original)), &original),
+            MorphOperation::BlackHat => difference(&erode(&dilate(&original)), &original, &original),
+        };
+        match self.border {
+            BorderMode::Crop => self.crop(result),
+            _ => result,
+        }
+    }
+}
+
+// Erode => Darkest, dilate => Lightest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Extreme {
+    Darkest,
+    Lightest,
+}
+
+impl Morphology {
+    // One erode or dilate, 'iterations' times
+    fn repeat(&self, img: &RgbaImage, extreme: Extreme, border: BorderMode) -> RgbaImage {
+        let passes = self.element.passes();
+        let mut result = img.clone();
+        for _ in 0..self.iterations.max(1) {
+            for offsets in &passes {
+                result = morph_pass(&result, offsets, extreme, self.mode, border);
+            }
+        }
+        result
+    }
+
+    // Binary mode: pixels at least 'threshold' bright are white, the rest black
+    fn binary(&self, Rgba([r, g, b, a]): Rgba<u8>) -> Rgba<u8> {
+        // Transparent counts as black, the same as the edge detectors
+        let luma = GrayscaleMethod::Rec709.luma(r, g, b) as u32 * a as u32 / 255;
+        let value = if luma >= self.threshold as u32 { 255 } else { 0 };
+        Rgba([value, value, value, 255])
+    }
+
+    // For BorderMode::Crop: cuts off the pixels that anything past the edge could have reached
+    // i.e. the element's reach, times the iterations, times the erodes and dilates in a row (2 for open, close and the hats)
+    // (a side that is too small to crop is left at full size, like the convolutions)
+    fn crop(&self, img: RgbaImage) -> RgbaImage {
+        let stages = match self.operation {
+            MorphOperation::Erode | MorphOperation::Dilate | MorphOperation::Gradient => 1,
+            MorphOperation::Open | MorphOperation::Close | MorphOperation::TopHat | MorphOperation::BlackHat => 2,
+        };
+        let reach = |radius: usize| radius as u32 * self.iterations.max(1) * stages;
+        let (reach_x, reach_y) = (reach(self.element.width / 2), reach(self.element.height / 2));
+        let (width, height) = img.dimensions();
+        let skip_x = if width > 2 * reach_x { reach_x } else { 0 };
+        let skip_y = if height > 2 * reach_y { reach_y } else { 0 };
+        image::imageops::crop_imm(&img, skip_x, skip_y, width - 2 * skip_x, height - 2 * skip_y).to_image()
+    }
+}
+
+// Every pixel becomes the darkest / lightest of its neighbours at 'offsets'
+// (the ones past the edge of the image come from 'border', which mustn't be Crop)
+fn morph_pass(img: &RgbaImage, offsets: &[(i64, i64)], extreme: Extreme, mode: MorphMode, border: BorderMode) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    // Where each neighbour is in the image, None => past the edge with a Constant border
+    let neighbours = |x: u32, y: u32| {
+        offsets.iter().map(move |&(dx, dy)| {
+            let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+            if (0..width as i64).contains(&nx) && (0..height as i64).contains(&ny) {
+                return Some((nx as u32, ny as u32));
+            }
+            border.resolve(nx, width).zip(border.resolve(ny, height))
+        })
+    };
+    let outside = match border {
+        BorderMode::Constant(color) => color,
+        _ => Rgba([0; 4]),
+    };
+    let pixel_at = |neighbour: Option<(u32, u32)>| neighbour.map_or(outside, |(nx, ny)| *img.get_pixel(nx, ny));
+
+    match mode {
+        // Whole pixels: the one with the lowest / highest brightness (the first one found, if there's a tie)
+        // A custom element doesn't have to include its middle, so the pixel itself only counts if it's in there
+        MorphMode::Luminance => {
+            let luma_of = |Rgba([r, g, b, _]): Rgba<u8>| GrayscaleMethod::Rec709.luma(r, g, b);
+            let luma: Vec<u8> = img.pixels().map(|&pixel| luma_of(pixel)).collect();
+            let outside_luma = luma_of(outside);
+            let luma_at = |neighbour: Option<(u32, u32)>| neighbour.map_or(outside_luma, |(x, y)| luma[y as usize * width as usize + x as usize]);
+            build_rows(width, height, |y, row| {
+                for (x, out) in row.chunks_exact_mut(4).enumerate() {
+                    let mut best: Option<(Option<(u32, u32)>, u8)> = None;
+                    for neighbour in neighbours(x as u32, y) {
+                        let brightness = luma_at(neighbour);
+                        let better = best.is_none_or(|(_, best)| match extreme {
+                            Extreme::Darkest => brightness < best,
+                            Extreme::Lightest => brightness > best,
+                        });
+                        if better {
+                            best = Some((neighbour, brightness));
+                        }
+                    }
+                    let (best, _) = best.expect("a structuring element has at least one cell");
+                    out.copy_from_slice(&pixel_at(best).0);
+                }
+            })
+        }
+        // Each channel on its own (a binary image is just a black and white one by now)
+        MorphMode::Channels | MorphMode::Binary => build_rows(width, height, |y, row| {
+            for (x, out) in row.chunks_exact_mut(4).enumerate() {
+                // Start from white for the darkest and black for the lightest, so only the neighbours count
+                let start = match extreme {
+                    Extreme::Darkest => 255,
+                    Extreme::Lightest => 0,
+                };
+                let mut result = [start; 4];
+                for neighbour in neighbours(x as u32, y) {
+                    let Rgba(pixel) = pixel_at(neighbour);
+                    for (value, neighbour) in result.iter_mut().zip(pixel) {
+                        *value = match extreme {
+                            Extreme::Darkest => (*value).min(neighbour),
+                            Extreme::Lightest => (*value).max(neighbour),
+                        };
+                    }
+                }
+                out.copy_from_slice(&result);
+            }
+        }),
+    }
+}
+
+// 'a' minus 'b' for red, green and blue (anything below 0 is 0), keeping the alpha of 'alpha_from'
+// (a difference of alphas would make most of the image transparent)
+fn difference(a: &RgbaImage, b: &RgbaImage, alpha_from: &RgbaImage) -> RgbaImage {
+    map_pixels(a, |x, y, Rgba([r, g, bl, _])| {
+        let Rgba([r2, g2, b2, _]) = *b.get_pixel(x, y);
+        Rgba([r.saturating_sub(r2), g.saturating_sub(g2), bl.saturating_sub(b2), alpha_from.get_pixel(x, y)[3]])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spec::FilterSpec;
+
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+    // A black 24x20 image with a white 12x10 block, a 1 pixel speck, a 1 pixel hole in the block
+    // and some white touching the right hand edge
+    fn binary_image() -> RgbaImage {
+        RgbaImage::from_fn(24, 20, |x, y| {
+            let block = (3..15).contains(&x) && (4..14).contains(&y) && (x, y) != (8, 8);
+            let speck = (x, y) == (19, 3);
+            let edge = x == 23 && (10..16).contains(&y);
+            if block || speck || edge { WHITE } else { BLACK }
+        })
+    }
+
+    fn morph(img: &RgbaImage, operation: &str) -> RgbaImage {
+        FilterSpec::new("morphology").with("operation", operation).with("radius", 2).build().unwrap().apply(img)
+    }
+
+    #[test]
+    fn close_is_dilate_then_erode() {
+        let img = binary_image();
+        assert_eq!(morph(&img, "close"), morph(&morph(&img, "dilate"), "erode"));
+        assert_eq!(morph(&img, "open"), morph(&morph(&img, "erode"), "dilate"));
+        // And closing fills the hole
+        assert_eq!(*morph(&img, "close").get_pixel(8, 8), WHITE);
+    }
+
+    #[test]
+    fn flat_gradient_is_zero() {
+        let img = RgbaImage::from_pixel(9, 7, Rgba([90, 160, 30, 200]));
+        for mode in MorphMode::NAMES {
+            let spec = FilterSpec::new("morphology").with("operation", "gradient").with("mode", mode);
+            let gradient = spec.build().unwrap().apply(&img);
+            // Binary mode makes the image opaque, the others keep its alpha
+            let alpha = if mode == "binary" { 255 } else { 200 };
+            assert!(gradient.pixels().all(|pixel| pixel.0 == [0, 0, 0, alpha]), "{}", mode);
+        }
+    }
+
+    #[test]
+    fn top_hat_removes_large_structures() {
+        // A big block and a speck
+        let img = RgbaImage::from_fn(24, 20, |x, y| if ((3..15).contains(&x) && (4..14).contains(&y)) || (x, y) == (19, 3) { WHITE } else { BLACK });
+        // A square, so the opening keeps the block's corners (a disk would round them off)
+        let spec = FilterSpec::new("morphology").with("operation", "top_hat").with("shape", "square").with("radius", 2);
+        let top_hat = spec.build().unwrap().apply(&img);
+        // Only the speck is smaller than the square, so it's all that's left
+        for (x, y, pixel) in top_hat.enumerate_pixels() {
+            let expected = if (x, y) == (19, 3) { WHITE } else { BLACK };
+            assert_eq!(*pixel, expected, "at {}, {}", x, y);
+        }
+    }
+
+    // Every pixel the darkest / lightest of its neighbours, resolving them one at a time
+    fn reference(img: &RgbaImage, element: &StructuringElement, extreme: Extreme, border: BorderMode) -> RgbaImage {
+        let (width, height) = img.dimensions();
+        RgbaImage::from_fn(width, height, |x, y| {
+            let values = element.offsets().into_iter().map(|(dx, dy)| {
+                match (border.resolve(x as i64 + dx, width), border.resolve(y as i64 + dy, height)) {
+                    (Some(nx), Some(ny)) => *img.get_pixel(nx, ny),
+                    _ => match border {
+                        BorderMode::Constant(color) => color,
+                        _ => unreachable!("only Constant leaves the image"),
+                    },
+                }
+            });
+            let channel = |c: usize| {
+                let channel = values.clone().map(|Rgba(pixel)| pixel[c]);
+                match extreme {
+                    Extreme::Darkest => channel.min().unwrap(),
+                    Extreme::Lightest => channel.max().unwrap(),
+                }
+            };
+            Rgba([channel(0), channel(1), channel(2), channel(3)])
+        })
+    }
+
+    #[test]
+    fn borders() {
+        let img = RgbaImage::from_fn(11, 8, |x, y| Rgba([(x * 23 + y * 7) as u8, (x * y * 19) as u8, (250 - x * 9) as u8, (60 + y * 20) as u8]));
+        let elements = [
+            StructuringElement::square(2),
+            StructuringElement::disk(2),
+            // Lopsided and without its middle, so it's clear which side each neighbour comes from
+            StructuringElement::from_rows(&[[1.0, 1.0, 0.0, 0.0, 0.0], [0.0, 0.0, 0.0, 0.0, 0.0], [0.0, 0.0, 0.0, 0.0, 1.0]]).unwrap(),
+        ];
+        let borders = [BorderMode::Clamp, BorderMode::Mirror, BorderMode::Wrap, BorderMode::Constant(Rgba([128, 0, 255, 0]))];
+        for element in &elements {
+            for (operation, extreme) in [(MorphOperation::Erode, Extreme::Darkest), (MorphOperation::Dilate, Extreme::Lightest)] {
+                let filter = |border| Morphology { operation, element: element.clone(), border, ..Morphology::default() };
+                for border in borders {
+                    let expected = reference(&img, element, extreme, border);
+                    assert_eq!(filter(border).apply(&img), expected, "{:?} {:?} {:?}", element, operation, border);
+                }
+                // Crop is the clamped result without the pixels the border reached (2 across and 2 (or 1) down)
+                let clamped = filter(BorderMode::Clamp).apply(&img);
+                let (skip_x, skip_y) = ((element.width() / 2) as u32, (element.height() / 2) as u32);
+                let cropped = image::imageops::crop_imm(&clamped, skip_x, skip_y, 11 - 2 * skip_x, 8 - 2 * skip_y).to_image();
+                assert_eq!(filter(BorderMode::Crop).apply(&img), cropped, "{:?} {:?} crop", element, operation);
+            }
+        }
+    }
+
+    #[test]
+    fn constant_border() {
+        let img = RgbaImage::from_pixel(7, 6, WHITE);
+        let erode = |border: &str| FilterSpec::new("morphology").with("border", border).with("border_color", "#000000ff").build().unwrap().apply(&img);
+        // Clamp (and the others that stay inside the image) only see white, a black border eats into the edge
+        assert_eq!(erode("clamp"), img);
+        assert_eq!(erode("mirror"), img);
+        let eroded = erode("constant");
+        for (x, y, pixel) in eroded.enumerate_pixels() {
+            let on_edge = x == 0 || y == 0 || x == 6 || y == 5;
+            assert_eq!(*pixel, if on_edge { BLACK } else { WHITE }, "at {}, {}", x, y);
+        }
+        // Crop with the open's 2 stages of a radius 1 disk: 2 pixels off every side
+        let spec = FilterSpec::new("morphology").with("operation", "open").with("border", "crop");
+        assert_eq!(spec.build().unwrap().apply(&img).dimensions(), (3, 2));
+    }
+}
@@ -0,0 +1,159 @@
+use image::{Rgba, RgbaImage};
+
+use super::alpha::AlphaMode;
+use crate::error::FilterError;
+use crate::filter::Filter;
+use crate::filter_kind::FilterKind;
+use crate::parallel::for_each_chunk;
+use crate::spec::{ParamInfo, Params};
+
+const DEFAULT_BLOCK_SIZE: u32 = 10;
+
+const PIXELATE_PARAMS: [ParamInfo; 1] = [ParamInfo::integer("block_size", "Block Size", 1.0, 512.0, DEFAULT_BLOCK_SIZE as f64)];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pixelate {
+    // How many pixels wide (and high) each 'block' is
+    // The blocks start at the top left, so the ones along the right and bottom edges may be smaller
+    pub block_size: u32,
+}
+
+impl Default for Pixelate {
+    fn default() -> Self {
+        Pixelate { block_size: DEFAULT_BLOCK_SIZE }
+    }
+}
+
+impl Filter for Pixelate {
+    fn name(&self) -> &str {
+        FilterKind::Pixelate.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Pixelate.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &PIXELATE_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        Ok(Box::new(Pixelate { block_size: params.number("block_size")? as u32 }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        apply_pixelate(img, self.block_size)
+    }
+}
+
+// Fills every block_size x block_size block with the average colour of the pixels in it
+// Works for any image size (a block bigger than the image just averages the whole image)
+fn apply_pixelate(img: &RgbaImage, block_size: u32) -> RgbaImage {
+    // Blocks of 1 pixel (or 0, which makes no sense) leave the image as it is
+    if block_size <= 1 {
+        return img.clone();
+    }
+    let mut pixelated_img = img.clone();
+    let (width, height) = img.dimensions();
+    let stride = width as usize * 4;
+
+    // One chunk per band of blocks (block_size rows of the image), so the bands can be shared out across cores
+    for_each_chunk(&mut pixelated_img, stride * block_size as usize, |band, out| {
+        let block_y = band as u32 * block_size;
+        // .min() => the blocks along the right and bottom edges stop at the edge of the image
+        let block_height = block_size.min(height - block_y);
+
+        // step_by => the left edge of every block in the band
+        for block_x in (0..width).step_by(block_size as usize) {
+            let block_width = block_size.min(width - block_x);
+
+            // Average in premultiplied alpha (see AlphaMode), so transparent pixels don't darken the block
+            let mut sum = [0.0f32; 4];
+            for y in block_y..block_y + block_height {
+                for x in block_x..block_x + block_width {
+                    for (total, value) in sum.iter_mut().zip(AlphaMode::Premultiplied.load(*img.get_pixel(x, y))) {
+                        *total += value;
+                    }
+                }
+            }
+            let count = (block_width * block_height) as f32;
+            let Rgba(average) = AlphaMode::Premultiplied.store(sum.map(|total| total / count), *img.get_pixel(block_x, block_y), 0.0);
+
+            for row in out.chunks_exact_mut(stride) {
+                for pixel in row[block_x as usize * 4..(block_x + block_width) as usize * 4].chunks_exact_mut(4) {
+                    pixel.copy_from_slice(&average);
+                }
+            }
+        }
+    });
+
+    pixelated_img
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Something different in every pixel, all of it opaque (so the premultiplied average is just the average)
+    fn pattern(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| Rgba([(x * 37 + y * 11) as u8, (x * 5 + y * 71 + 40) as u8, (200 + x * y * 13) as u8, 255]))
+    }
+
+    // The average of the pixels in x_range x y_range, worked out the slow way
+    fn average(img: &RgbaImage, x_range: std::ops::Range<u32>, y_range: std::ops::Range<u32>) -> Rgba<u8> {
+        let mut sum = [0u32; 4];
+        let mut count = 0;
+        for y in y_range {
+            for x in x_range.clone() {
+                for (total, value) in sum.iter_mut().zip(img.get_pixel(x, y).0) {
+                    *total += value as u32;
+                }
+                count += 1;
+            }
+        }
+        Rgba(sum.map(|total| (total as f64 / count as f64 + 0.5) as u8))
+    }
+
+    // Sizes that aren't a multiple of the block size leave smaller blocks along the right and bottom,
+    // and those only average their own pixels (not the ones a full block would have covered)
+    #[test]
+    fn partial_blocks() {
+        for (width, height, block_size) in [(5, 3, 2), (7, 7, 3), (10, 4, 4), (1, 9, 4), (9, 1, 4)] {
+            let img = pattern(width, height);
+            let output = apply_pixelate(&img, block_size);
+            for y in 0..height {
+                for x in 0..width {
+                    let (left, top) = (x / block_size * block_size, y / block_size * block_size);
+                    let expected = average(&img, left..(left + block_size).min(width), top..(top + block_size).min(height));
+                    assert_eq!(*output.get_pixel(x, y), expected, "{}x{}, block {}, at {},{}", width, height, block_size, x, y);
+                }
+            }
+        }
+    }
+
+    // A block bigger than the image covers all of it, so every pixel gets the average of the whole image
+    #[test]
+    fn block_bigger_than_image() {
+        for (width, height) in [(1, 1), (3, 2), (6, 11)] {
+            let img = pattern(width, height);
+            let expected = average(&img, 0..width, 0..height);
+            for block_size in [12, 512] {
+                assert!(apply_pixelate(&img, block_size).pixels().all(|px| *px == expected), "{}x{}, block {}", width, height, block_size);
+            }
+        }
+    }
+
+    #[test]
+    fn one_pixel_blocks() {
+        let img = pattern(5, 4);
+        assert_eq!(apply_pixelate(&img, 0), img);
+        assert_eq!(apply_pixelate(&img, 1), img);
+    }
+
+    // Transparent pixels don't count towards the colour, so they don't darken the block
+    #[test]
+    fn transparent_pixels() {
+        let img = RgbaImage::from_fn(2, 2, |x, _| if x == 0 { Rgba([200, 100, 50, 255]) } else { Rgba([0, 0, 0, 0]) });
+        assert!(apply_pixelate(&img, 2).pixels().all(|px| *px == Rgba([200, 100, 50, 128])));
+    }
+}
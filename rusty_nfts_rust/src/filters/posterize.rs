@@ -0,0 +1,280 @@
+use image::RgbaImage;
+
+use crate::error::FilterError;
+use crate::filter::Filter;
+use crate::filter_kind::FilterKind;
+use crate::kernels::posterize_row;
+use crate::kernels::scalar::quantize;
+use crate::parallel::for_each_chunk;
+use crate::spec::{ParamInfo, Params};
+
+// How to hide the 'banding' you get from using only a few levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dither {
+    // Every pixel just snaps to its nearest level
+    #[default]
+    None,
+    // Nudges each pixel up or down by a repeating 4x4 pattern before snapping (a regular crosshatch look)
+    Ordered,
+    // Floyd-Steinberg: passes each pixel's rounding error on to the neighbours it hasn't done yet
+    // (a grainy, more natural look)
+    FloydSteinberg,
+}
+
+impl Dither {
+    // The names used by the "dither" parameter
+    pub const NAMES: [&'static str; 3] = ["none", "ordered", "floyd_steinberg"];
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "ordered" => Dither::Ordered,
+            "floyd_steinberg" => Dither::FloydSteinberg,
+            _ => Dither::None,
+        }
+    }
+}
+
+const DEFAULT_LEVELS: u16 = 4;
+
+const POSTERIZE_PARAMS: [ParamInfo; 5] = [
+    // Up to 256 => one level for every value a channel can have, which leaves the image as it is
+    ParamInfo::integer("levels", "Levels", 2.0, 256.0, DEFAULT_LEVELS as f64),
+    // Per channel overrides, 0 => use "levels"
+    ParamInfo::integer("red_levels", "Red Levels", 0.0, 256.0, 0.0),
+    ParamInfo::integer("green_levels", "Green Levels", 0.0, 256.0, 0.0),
+    ParamInfo::integer("blue_levels", "Blue Levels", 0.0, 256.0, 0.0),
+    ParamInfo::choice("dither", "Dither", &Dither::NAMES, "none"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Posterize {
+    // The number of levels (i.e. the number of shades) for red, green and blue
+    // e.g. 2 => each channel is either 0 or 255
+    // Anything under 2 is treated as 2 (1 level can't show anything), and anything over 256 as 256
+    pub levels: [u16; 3],
+    pub dither: Dither,
+}
+
+impl Default for Posterize {
+    fn default() -> Self {
+        Posterize { levels: [DEFAULT_LEVELS; 3], dither: Dither::default() }
+    }
+}
+
+impl Filter for Posterize {
+    fn name(&self) -> &str {
+        FilterKind::Posterize.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Posterize.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &POSTERIZE_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        let levels = params.number("levels")? as u16;
+        // 0 => fall back to the shared "levels"
+        let channel_levels = |name: &str| -> Result<u16, FilterError> {
+            Ok(match params.number(name)? as u16 {
+                0 => levels,
+                channel => channel,
+            })
+        };
+        Ok(Box::new(Posterize {
+            levels: [channel_levels("red_levels")?, channel_levels("green_levels")?, channel_levels("blue_levels")?],
+            dither: Dither::from_name(params.choice("dither")?),
+        }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        apply_posterize(img, self.levels, self.dither)
+    }
+}
+
+// The 4x4 Bayer matrix: the order in which to 'switch on' the pixels of a 4x4 tile
+// Spreading the thresholds out like this gives the even crosshatch of ordered dithering
+const BAYER_4X4: [[f32; 4]; 4] = [
+    [0.0, 8.0, 2.0, 10.0],
+    [12.0, 4.0, 14.0, 6.0],
+    [3.0, 11.0, 1.0, 9.0],
+    [15.0, 7.0, 13.0, 5.0],
+];
+
+fn apply_posterize(img: &RgbaImage, levels: [u16; 3], dither: Dither) -> RgbaImage {
+    // The gap between two neighbouring levels, e.g. 4 levels => 0, 85, 170, 255 => a step of 85
+    // .clamp() => 0 or 1 levels would divide by zero, and more than 256 can't make any difference
+    let steps = levels.map(|n| 255.0 / (n.clamp(2, 256) - 1) as f32);
+
+    let mut posterized_img = img.clone();
+    match dither {
+        // Take each colour to the nearest level (the alpha channel is left unchanged)
+        // A row at a time, see posterize_row (which has a SIMD version) for the maths
+        Dither::None => {
+            for_each_chunk(&mut posterized_img, img.width() as usize * 4, |_, row| posterize_row(row, steps, [0.0; 4]));
+        }
+        Dither::Ordered => {
+            for_each_chunk(&mut posterized_img, img.width() as usize * 4, |y, row| {
+                // A nudge between -0.5 and +0.5 of a step, depending on where the pixel sits in its 4x4 tile
+                let nudges = BAYER_4X4[y % 4].map(|threshold| (threshold + 0.5) / 16.0 - 0.5);
+                posterize_row(row, steps, nudges);
+            });
+        }
+        // Every pixel depends on the ones before it, so this one can't be split up and always runs on one core
+        Dither::FloydSteinberg => floyd_steinberg(&mut posterized_img, steps),
+    }
+    posterized_img
+}
+
+// Goes through the pixels left to right, top to bottom, snapping each to its nearest level
+// The difference (the 'error') is shared out to the pixels not done yet:
+//         *   7/16
+//   3/16 5/16 1/16
+// so on average the colours come out right, even though each pixel only has a few to choose from
+// Fully transparent pixels are left out of it (like histogram and equalize do): they still get snapped,
+// but they don't pass their error on, and the share meant for them is dropped (same as off the edge of the image)
+// Otherwise the invisible colour behind a cut-out would show up as a speckled fringe around it
+fn floyd_steinberg(img: &mut RgbaImage, steps: [f32; 3]) {
+    let (width, height) = (img.width() as usize, img.height() as usize);
+
+    // The errors waiting to be added, for this row and the next one (3 channels per pixel)
+    let mut current = vec![0.0f32; width * 3];
+    let mut next = vec![0.0f32; width * 3];
+
+    // Whether the pixel at x, y can be seen
+    let visible = |img: &RgbaImage, x: usize, y: usize| img.get_pixel(x as u32, y as u32)[3] > 0;
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut pixel = *img.get_pixel(x as u32, y as u32);
+            for c in 0..3 {
+                let wanted = pixel[c] as f32 + current[x * 3 + c];
+                let chosen = quantize(wanted, steps[c]);
+                pixel[c] = chosen;
+
+                if pixel[3] == 0 {
+                    continue;
+                }
+                let error = wanted - chosen as f32;
+                if x + 1 < width && visible(img, x + 1, y) {
+                    current[(x + 1) * 3 + c] += error * 7.0 / 16.0;
+                }
+                if y + 1 < height {
+                    if x + 1 < width && visible(img, x + 1, y + 1) {
+                        next[(x + 1) * 3 + c] += error / 16.0;
+                    }
+                    if x > 0 && visible(img, x - 1, y + 1) {
+                        next[(x - 1) * 3 + c] += error * 3.0 / 16.0;
+                    }
+                    if visible(img, x, y + 1) {
+                        next[x * 3 + c] += error * 5.0 / 16.0;
+                    }
+                }
+            }
+            img.put_pixel(x as u32, y as u32, pixel);
+        }
+        // Move down a row: the 'next' errors become the current ones, and start a fresh 'next'
+        std::mem::swap(&mut current, &mut next);
+        next.fill(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spec::FilterSpec;
+    use image::Rgba;
+
+    // Every channel value from 0 to 255 along a row (at a few different alphas), so every rounding case is covered
+    fn ramp() -> RgbaImage {
+        RgbaImage::from_fn(256, 3, |x, y| Rgba([x as u8, 255 - x as u8, (x as u8).wrapping_mul(7), [255, 128, 0][y as usize]]))
+    }
+
+    const DITHERS: [Dither; 3] = [Dither::None, Dither::Ordered, Dither::FloydSteinberg];
+
+    // 2 levels => every colour channel ends up black or white, and alpha is never touched
+    #[test]
+    fn two_levels() {
+        let img = ramp();
+        for dither in DITHERS {
+            let output = apply_posterize(&img, [2; 3], dither);
+            for (px, original) in output.pixels().zip(img.pixels()) {
+                assert!(px.0[..3].iter().all(|&c| c == 0 || c == 255), "{:?}: {:?}", dither, px);
+                assert_eq!(px[3], original[3]);
+            }
+        }
+        // Without dithering it's just a threshold half way up
+        let output = apply_posterize(&img, [2; 3], Dither::None);
+        for x in 0..256 {
+            assert_eq!(output.get_pixel(x, 0)[0], if x < 128 { 0 } else { 255 }, "{}", x);
+        }
+    }
+
+    // 0 and 1 levels can't show anything, so they work like 2 rather than dividing by zero
+    #[test]
+    fn fewer_than_two_levels() {
+        let img = ramp();
+        for levels in [0, 1] {
+            for dither in DITHERS {
+                assert_eq!(apply_posterize(&img, [levels; 3], dither), apply_posterize(&img, [2; 3], dither), "{}", levels);
+            }
+        }
+    }
+
+    // 256 levels is a step of exactly 1, so nothing changes (and there's no rounding error left to dither)
+    // 255 is the most that still changes anything, two of the values have to share a level
+    #[test]
+    fn most_levels() {
+        let img = ramp();
+        for dither in DITHERS {
+            assert_eq!(apply_posterize(&img, [256; 3], dither), img, "{:?}", dither);
+            assert_eq!(apply_posterize(&img, [1000; 3], dither), img, "{:?}", dither);
+        }
+        let output = apply_posterize(&img, [255; 3], Dither::None);
+        let changed = output.pixels().zip(img.pixels()).filter(|(px, original)| px[0] != original[0]).count();
+        assert_eq!(changed, 3, "one value on each row");
+
+        for name in ["levels", "red_levels"] {
+            assert!(FilterSpec::new("posterize").with(name, 256).build().is_ok());
+            assert!(matches!(
+                FilterSpec::new("posterize").with(name, 257).build(),
+                Err(FilterError::InvalidParameter { name: param, .. }) if param == name
+            ));
+        }
+    }
+
+    // Each channel can have its own number of levels
+    #[test]
+    fn levels_per_channel() {
+        let img = ramp();
+        let output = apply_posterize(&img, [2, 256, 3], Dither::None);
+        for (px, original) in output.pixels().zip(img.pixels()) {
+            assert!(px[0] == 0 || px[0] == 255);
+            assert_eq!(px[1], original[1]);
+            assert!([0, 128, 255].contains(&px[2]), "{:?}", px);
+        }
+    }
+
+    // Transparent pixels don't take part in the dithering: a cut-out dithers exactly the same whatever colour is hidden behind it
+    #[test]
+    fn transparent_pixels_are_skipped() {
+        let cut_out = |hidden: Rgba<u8>| {
+            RgbaImage::from_fn(16, 16, |x, y| {
+                if (4..12).contains(&x) && (4..12).contains(&y) { Rgba([90, 140, 200, 255]) } else { hidden }
+            })
+        };
+        // (colours between the levels, so they'd have an error to pass on)
+        let dark = apply_posterize(&cut_out(Rgba([40, 40, 40, 0])), [4; 3], Dither::FloydSteinberg);
+        let bright = apply_posterize(&cut_out(Rgba([120, 200, 10, 0])), [4; 3], Dither::FloydSteinberg);
+        for (x, y, px) in dark.enumerate_pixels() {
+            if px[3] > 0 {
+                assert_eq!(px, bright.get_pixel(x, y), "at {},{}", x, y);
+            }
+        }
+        // And on their own they're snapped to the nearest level, without picking up anyone's error
+        let hidden = apply_posterize(&cut_out(Rgba([100, 100, 100, 0])), [4; 3], Dither::FloydSteinberg);
+        assert!(hidden.enumerate_pixels().filter(|(_, _, px)| px[3] == 0).all(|(_, _, px)| *px == Rgba([85, 85, 85, 0])));
+    }
+}
@@ -0,0 +1,389 @@
+use image::RgbaImage;
+
+use super::adjust::{apply_luts, lookup_table};
+use crate::error::FilterError;
+use crate::filter::Filter;
+use crate::filter_kind::FilterKind;
+use crate::spec::{ParamInfo, Params};
+
+// Photoshop style Levels and Curves, for precise control over the tones of an image
+// Like the other tone adjustments (see adjust.rs) they come down to a lookup table per channel
+
+// Which channels Levels changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LevelsChannel {
+    // All three together
+    #[default]
+    Rgb,
+    // Just one, e.g. to warm up an image by lifting red. 0 => red, 1 => green, 2 => blue
+    Channel(usize),
+}
+
+impl LevelsChannel {
+    // The names used by the "channel" parameter
+    pub const NAMES: [&'static str; 4] = ["rgb", "red", "green", "blue"];
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "red" => LevelsChannel::Channel(0),
+            "green" => LevelsChannel::Channel(1),
+            "blue" => LevelsChannel::Channel(2),
+            _ => LevelsChannel::Rgb,
+        }
+    }
+}
+
+const LEVELS_PARAMS: [ParamInfo; 6] = [
+    ParamInfo::choice("channel", "Channel", &LevelsChannel::NAMES, "rgb"),
+    // The defaults clip a little off each end, a quick way to add some punch to a flat photo
+    ParamInfo::integer("black_point", "Black Point", 0.0, 254.0, 16.0),
+    ParamInfo::integer("white_point", "White Point", 1.0, 255.0, 240.0),
+    ParamInfo::number("gamma", "Midtones", 0.1, 10.0, 1.0, 0.05),
+    ParamInfo::integer("output_black", "Output Black", 0.0, 255.0, 0.0),
+    ParamInfo::integer("output_white", "Output White", 0.0, 255.0, 255.0),
+];
+
+// Levels, in three steps:
+//   1. stretch the input so black_point becomes black and white_point white (anything outside is clipped)
+//   2. bend the midtones by gamma (above 1 => lighter, below 1 => darker, black and white don't move)
+//   3. squash the result into output_black - output_white (e.g. 20 - 235 for a faded look)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Levels {
+    pub channel: LevelsChannel,
+    pub black_point: u8,
+    // Must be above black_point
+    pub white_point: u8,
+    pub gamma: f32,
+    // Can be above output_white, which inverts the image
+    pub output_black: u8,
+    pub output_white: u8,
+}
+
+impl Default for Levels {
+    fn default() -> Self {
+        Levels { channel: LevelsChannel::default(), black_point: 16, white_point: 240, gamma: 1.0, output_black: 0, output_white: 255 }
+    }
+}
+
+impl Filter for Levels {
+    fn name(&self) -> &str {
+        FilterKind::Levels.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Levels.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &LEVELS_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        let levels = Levels {
+            channel: LevelsChannel::from_name(params.choice("channel")?),
+            black_point: params.number("black_point")? as u8,
+            white_point: params.number("white_point")? as u8,
+            gamma: params.number("gamma")? as f32,
+            output_black: params.number("output_black")? as u8,
+            output_white: params.number("output_white")? as u8,
+        };
+        // The ranges alone can't stop these crossing over
+        if levels.white_point <= levels.black_point {
+            return Err(FilterError::InvalidParameter {
+                name: "white_point".to_string(),
+                reason: format!("{} must be above the black point ({})", levels.white_point, levels.black_point),
+            });
+        }
+        Ok(Box::new(levels))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        // .max() => the same point for both (only possible from Rust) would divide by zero
+        let (black, range) = (self.black_point as f32, (self.white_point as f32 - self.black_point as f32).max(1.0));
+        let exponent = 1.0 / self.gamma.max(1e-3);
+        let (output_black, output_range) = (self.output_black as f32, self.output_white as f32 - self.output_black as f32);
+
+        let lut = lookup_table(|value| {
+            let stretched = ((value - black) / range).clamp(0.0, 1.0);
+            output_black + stretched.powf(exponent) * output_range
+        });
+        // Only the chosen channel gets the table, the others look themselves up (i.e. stay the same)
+        let mut luts = [lut; 3];
+        if let LevelsChannel::Channel(channel) = self.channel {
+            let unchanged = lookup_table(|value| value);
+            for (index, table) in luts.iter_mut().enumerate() {
+                if index != channel {
+                    *table = unchanged;
+                }
+            }
+        }
+        apply_luts(img, &luts)
+    }
+}
+
+// A smooth curve through a few points, mapping each input value (x) to an output value (y), both 0 - 255
+// e.g. [[0, 0], [255, 255]] leaves everything as it is, [[0, 255], [255, 0]] inverts,
+// and [[0, 0], [64, 48], [192, 208], [255, 255]] is a gentle 'S', which adds contrast
+// The curve is a natural cubic spline (the smoothest curve through every point, like Photoshop's),
+// and is flat before the first point and after the last one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve {
+    // Sorted by x, no two with the same x
+    points: Vec<[f32; 2]>,
+}
+
+impl Curve {
+    // Makes a curve from its points, in any order
+    // There must be at least 2, all within 0 - 255, and no two can have the same x
+    pub fn new(mut points: Vec<[f32; 2]>) -> Result<Self, FilterError> {
+        if points.len() < 2 {
+            return Err(invalid_curve(format!("a curve needs at least 2 points, got {}", points.len())));
+        }
+        if let Some([x, y]) = points.iter().find(|[x, y]| !(0.0..=255.0).contains(x) || !(0.0..=255.0).contains(y)) {
+            return Err(invalid_curve(format!("[{}, {}] is outside 0 to 255", x, y)));
+        }
+        points.sort_by(|a, b| a[0].total_cmp(&b[0]));
+        if let Some(pair) = points.windows(2).find(|pair| pair[0][0] == pair[1][0]) {
+            return Err(invalid_curve(format!("two points have the same x ({})", pair[0][0])));
+        }
+        Ok(Curve { points })
+    }
+
+    // The straight line that leaves everything as it is
+    pub fn identity() -> Self {
+        Curve { points: vec![[0.0, 0.0], [255.0, 255.0]] }
+    }
+
+    pub fn points(&self) -> &[[f32; 2]] {
+        &self.points
+    }
+
+    // The curve's output for every input value, 0 - 255
+    pub fn lookup_table(&self) -> [u8; 256] {
+        let slopes = self.second_derivatives();
+        lookup_table(|x| self.value_at(x, &slopes) as f32)
+    }
+
+    // How much the curve bends at each point, which is all a spline needs on top of the points
+    // Found by solving the 'tridiagonal' system that makes the curve smooth where its pieces join,
+    // with no bend at the two end points (that's the 'natural' part)
+    fn second_derivatives(&self) -> Vec<f64> {
+        let n = self.points.len();
+        let x = |i: usize| self.points[i][0] as f64;
+        let y = |i: usize| self.points[i][1] as f64;
+
+        let mut bends = vec![0.0; n];
+        // The Thomas algorithm: one pass down to eliminate, one pass back up to fill in
+        let mut upper = vec![0.0; n];
+        let mut right = vec![0.0; n];
+        for i in 1..n - 1 {
+            let (before, after) = (x(i) - x(i - 1), x(i + 1) - x(i));
+            let diagonal = 2.0 * (before + after) - before * upper[i - 1];
+            upper[i] = after / diagonal;
+            let slope_change = 6.0 * ((y(i + 1) - y(i)) / after - (y(i) - y(i - 1)) / before);
+            right[i] = (slope_change - before * right[i - 1]) / diagonal;
+        }
+        for i in (1..n - 1).rev() {
+            bends[i] = right[i] - upper[i] * bends[i + 1];
+        }
+        bends
+    }
+
+    fn value_at(&self, x: f32, bends: &[f64]) -> f64 {
+        let points = &self.points;
+        let (first, last) = (points[0], points[points.len() - 1]);
+        if x <= first[0] {
+            return first[1] as f64;
+        }
+        if x >= last[0] {
+            return last[1] as f64;
+        }
+
+        // The piece of the curve x is on, i.e. between points i and i + 1
+        let i = points.windows(2).position(|pair| x < pair[1][0]).unwrap_or(points.len() - 2);
+        let (x0, y0, x1, y1) = (points[i][0] as f64, points[i][1] as f64, points[i + 1][0] as f64, points[i + 1][1] as f64);
+        let width = x1 - x0;
+        // How far along the piece x is (0 => point i, 1 => point i + 1), and the rest of the way
+        let t = (x as f64 - x0) / width;
+        let u = 1.0 - t;
+        // A straight line between the points, plus the bend
+        u * y0 + t * y1 + ((u * u * u - u) * bends[i] + (t * t * t - t) * bends[i + 1]) * width * width / 6.0
+    }
+}
+
+fn invalid_curve(reason: String) -> FilterError {
+    FilterError::InvalidParameter { name: "curve".to_string(), reason }
+}
+
+const IDENTITY_POINTS: &[&[f64]] = &[&[0.0, 0.0], &[255.0, 255.0]];
+
+// Each curve is a list of [x, y] points, so in a pipeline it's e.g.
+//   { "filter": "curves", "params": { "master": [[0, 0], [64, 48], [192, 208], [255, 255]], "blue": [[0, 20], [255, 235]] } }
+const CURVES_PARAMS: [ParamInfo; 4] = [
+    ParamInfo::matrix("master", "RGB Curve", &[&[0.0, 0.0], &[64.0, 48.0], &[192.0, 208.0], &[255.0, 255.0]]),
+    ParamInfo::matrix("red", "Red Curve", IDENTITY_POINTS),
+    ParamInfo::matrix("green", "Green Curve", IDENTITY_POINTS),
+    ParamInfo::matrix("blue", "Blue Curve", IDENTITY_POINTS),
+];
+
+// Curves: each channel goes through its own curve, then all three through the master curve
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curves {
+    pub master: Curve,
+    // red, green, blue
+    pub channels: [Curve; 3],
+}
+
+impl Default for Curves {
+    fn default() -> Self {
+        Curves {
+            master: Curve::new(vec![[0.0, 0.0], [64.0, 48.0], [192.0, 208.0], [255.0, 255.0]]).expect("default curve is valid"),
+            channels: [Curve::identity(), Curve::identity(), Curve::identity()],
+        }
+    }
+}
+
+impl Filter for Curves {
+    fn name(&self) -> &str {
+        FilterKind::Curves.name()
+    }
+
+    fn label(&self) -> &str {
+        FilterKind::Curves.label()
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &CURVES_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        Ok(Box::new(Curves {
+            master: read_curve(params, "master")?,
+            channels: [read_curve(params, "red")?, read_curve(params, "green")?, read_curve(params, "blue")?],
+        }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        let master = self.master.lookup_table();
+        let luts = self.channels.each_ref().map(|curve| curve.lookup_table().map(|value| master[value as usize]));
+        apply_luts(img, &luts)
+    }
+}
+
+// Reads a curve parameter, with any error about it under the parameter's own name
+fn read_curve(params: &Params, name: &str) -> Result<Curve, FilterError> {
+    let with_name = |reason: String| FilterError::InvalidParameter { name: name.to_string(), reason };
+    let points = params
+        .matrix(name)?
+        .iter()
+        .map(|point| match point[..] {
+            [x, y] => Ok([x as f32, y as f32]),
+            _ => Err(with_name(format!("every point must be [x, y], got {:?}", point))),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Curve::new(points).map_err(|e| match e {
+        FilterError::InvalidParameter { reason, .. } => with_name(reason),
+        e => e,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spec::FilterSpec;
+    use image::Rgba;
+
+    // Every channel value from 0 to 255 along a row, with the colours mixed up and a different alpha on each row
+    fn ramp() -> RgbaImage {
+        RgbaImage::from_fn(256, 3, |x, y| Rgba([x as u8, 255 - x as u8, (x as u8).wrapping_mul(7), [255, 128, 0][y as usize]]))
+    }
+
+    fn curves(master: Curve, channels: [Curve; 3]) -> Curves {
+        Curves { master, channels }
+    }
+
+    #[test]
+    fn no_change() {
+        let img = ramp();
+        let levels = Levels { black_point: 0, white_point: 255, ..Levels::default() };
+        assert_eq!(levels.apply(&img), img);
+        let spec = FilterSpec::new("levels").with("black_point", 0).with("white_point", 255).with("gamma", 1.0);
+        assert_eq!(spec.build().unwrap().apply(&img), img);
+
+        let identity = curves(Curve::identity(), [Curve::identity(), Curve::identity(), Curve::identity()]);
+        assert_eq!(identity.apply(&img), img);
+        // A straight line through more points is still the identity
+        let straight = Curve::new(vec![[0.0, 0.0], [100.0, 100.0], [30.0, 30.0], [255.0, 255.0]]).unwrap();
+        assert_eq!(straight.lookup_table(), std::array::from_fn(|value| value as u8));
+    }
+
+    #[test]
+    fn inverted_curve() {
+        let img = ramp();
+        let invert = Curve::new(vec![[0.0, 255.0], [255.0, 0.0]]).unwrap();
+        let output = curves(invert, [Curve::identity(), Curve::identity(), Curve::identity()]).apply(&img);
+        for (px, original) in output.pixels().zip(img.pixels()) {
+            assert_eq!(*px, Rgba([255 - original[0], 255 - original[1], 255 - original[2], original[3]]));
+        }
+    }
+
+    // The spline has to go exactly through every point it was given (and stay flat past the ends)
+    #[test]
+    fn passes_through_every_point() {
+        let point_sets = [
+            vec![[0.0, 0.0], [64.0, 48.0], [192.0, 208.0], [255.0, 255.0]],
+            vec![[10.0, 200.0], [60.0, 20.0], [61.0, 90.0], [140.0, 255.0], [200.0, 0.0], [230.0, 130.0]],
+            vec![[0.0, 255.0], [128.0, 0.0], [255.0, 255.0]],
+            vec![[40.0, 70.0], [210.0, 90.0]],
+        ];
+        for points in point_sets {
+            let curve = Curve::new(points.clone()).unwrap();
+            let bends = curve.second_derivatives();
+            for [x, y] in points {
+                assert!((curve.value_at(x, &bends) - y as f64).abs() < 1e-9, "{:?} at {}", curve, x);
+                assert_eq!(curve.lookup_table()[x as usize], y as u8, "{:?} at {}", curve, x);
+            }
+            let (first, last) = (curve.points()[0], curve.points()[curve.points().len() - 1]);
+            assert_eq!(curve.value_at(0.0, &bends), first[1] as f64);
+            assert_eq!(curve.value_at(255.0, &bends), last[1] as f64);
+            // 'Natural': no bend at either end
+            assert_eq!((bends[0], bends[bends.len() - 1]), (0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn white_point_above_black_point() {
+        for (black, white) in [(100, 100), (200, 50), (254, 1)] {
+            let spec = FilterSpec::new("levels").with("black_point", black).with("white_point", white);
+            assert!(
+                matches!(spec.build(), Err(FilterError::InvalidParameter { name, .. }) if name == "white_point"),
+                "{} - {}",
+                black,
+                white
+            );
+        }
+        assert!(FilterSpec::new("levels").with("black_point", 100).with("white_point", 101).build().is_ok());
+    }
+
+    // A single channel's Levels or Curve leaves the other two (and alpha) alone
+    #[test]
+    fn one_channel() {
+        let img = ramp();
+        let lift = Curve::new(vec![[0.0, 40.0], [128.0, 200.0], [255.0, 255.0]]).unwrap();
+        for channel in 0..3 {
+            let levels = Levels { channel: LevelsChannel::Channel(channel), black_point: 30, white_point: 200, gamma: 1.7, ..Levels::default() };
+            let mut curve_channels = [Curve::identity(), Curve::identity(), Curve::identity()];
+            curve_channels[channel] = lift.clone();
+            let filters: [Box<dyn Filter>; 2] = [Box::new(levels), Box::new(curves(Curve::identity(), curve_channels))];
+            for filter in filters {
+                let output = filter.apply(&img);
+                assert_ne!(output, img, "{} on channel {}", filter.name(), channel);
+                for (px, original) in output.pixels().zip(img.pixels()) {
+                    for other in (0..4).filter(|&other| other != channel) {
+                        assert_eq!(px[other], original[other], "{} on channel {}", filter.name(), channel);
+                    }
+                }
+            }
+        }
+    }
+}
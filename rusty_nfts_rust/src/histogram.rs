@@ -0,0 +1,358 @@
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::error::FilterError;
+use crate::filters::GrayscaleMethod;
+use crate::output::{self, EncodedImage, OutputOptions};
+
+// How the tones of an image are spread out: for each channel, how many pixels have each value (0 - 255)
+// e.g. a dark photo has most of its pixels in the low bins, a washed out one has nothing in the lowest or highest
+//
+// From JS:
+//   const stats = JSON.parse(image_histogram(bytes));   // { red: { bins: [...256], min, max, mean, stddev }, ... }
+//   const png = render_histogram(bytes, '{ "width": 256, "height": 100, "channels": "rgb" }');
+// ImageSession has the same two (histogram() and render_histogram()) for the image it holds
+
+// One channel's counts plus a summary of them
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChannelHistogram {
+    // How many pixels have each value, always 256 of them
+    pub bins: Vec<u32>,
+    // The darkest and lightest values that appear (both 0 if there are no pixels)
+    pub min: u8,
+    pub max: u8,
+    pub mean: f64,
+    // The 'standard deviation': how far values are from the mean on average (low => flat, high => contrasty)
+    pub stddev: f64,
+}
+
+impl ChannelHistogram {
+    fn from_bins(bins: [u32; 256]) -> Self {
+        let count: f64 = bins.iter().map(|&n| n as f64).sum();
+        let min = bins.iter().position(|&n| n > 0).unwrap_or(0) as u8;
+        let max = bins.iter().rposition(|&n| n > 0).unwrap_or(0) as u8;
+        let (mut mean, mut stddev) = (0.0, 0.0);
+        if count > 0.0 {
+            mean = bins.iter().enumerate().map(|(value, &n)| value as f64 * n as f64).sum::<f64>() / count;
+            let variance = bins.iter().enumerate().map(|(value, &n)| (value as f64 - mean).powi(2) * n as f64).sum::<f64>() / count;
+            stddev = variance.sqrt();
+        }
+        ChannelHistogram { bins: bins.to_vec(), min, max, mean, stddev }
+    }
+}
+
+// Every channel's histogram
+// Fully transparent pixels aren't counted: their colour can't be seen (and is often just black),
+// so counting them would make a cut-out look much darker than it is
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Histogram {
+    pub red: ChannelHistogram,
+    pub green: ChannelHistogram,
+    pub blue: ChannelHistogram,
+    // The Rec. 709 grey of each pixel, the same as Grayscale's default
+    pub luminance: ChannelHistogram,
+    // How many pixels were counted
+    pub pixels: u64,
+}
+
+impl Histogram {
+    pub fn from_image(img: &RgbaImage) -> Self {
+        let mut bins = [[0u32; 256]; 4];
+        let mut pixels = 0;
+        for &Rgba([r, g, b, a]) in img.pixels() {
+            if a == 0 {
+                continue;
+            }
+            bins[0][r as usize] += 1;
+            bins[1][g as usize] += 1;
+            bins[2][b as usize] += 1;
+            bins[3][GrayscaleMethod::Rec709.luma(r, g, b) as usize] += 1;
+            pixels += 1;
+        }
+        let [red, green, blue, luminance] = bins.map(ChannelHistogram::from_bins);
+        Histogram { red, green, blue, luminance, pixels }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("a histogram is always valid JSON")
+    }
+
+    // Draws the histogram as a bar chart, one bar per value from black (left) to white (right),
+    // scaled so the tallest bar reaches the top. The background is transparent
+    pub fn render(&self, options: &HistogramOptions) -> RgbaImage {
+        let (width, height) = (options.width, options.height);
+        // The channels to draw and their colours
+        // Red, green and blue are added together where they overlap (so all three => white), like Photoshop's
+        let channels: Vec<(&ChannelHistogram, [u8; 3])> = match options.channels {
+            HistogramChannels::Rgb => vec![(&self.red, [255, 0, 0]), (&self.green, [0, 255, 0]), (&self.blue, [0, 0, 255])],
+            HistogramChannels::Luminance => vec![(&self.luminance, [200, 200, 200])],
+        };
+
+        // Each column shows the tallest bin under it (a narrow image has several bins per column, a wide one
+        // several columns per bin), so a single spike never disappears
+        let column_bins = |x: u32| {
+            let first = (x as usize * 256) / width as usize;
+            let last = (((x as usize + 1) * 256) / width as usize).max(first + 1).min(256);
+            first..last
+        };
+        let scale = |count: u32| match options.scale {
+            HistogramScale::Linear => count as f64,
+            // ln_1p => 0 stays 0, and the big spikes (e.g. a lot of pure white sky) don't flatten everything else
+            HistogramScale::Logarithmic => (count as f64).ln_1p(),
+        };
+        let peak = channels.iter().flat_map(|(channel, _)| channel.bins.iter()).map(|&n| scale(n)).fold(0.0, f64::max);
+
+        // How tall each channel's bar is in each column, in pixels
+        let bars: Vec<Vec<u32>> = channels
+            .iter()
+            .map(|(channel, _)| {
+                (0..width)
+                    .map(|x| {
+                        let tallest = channel.bins[column_bins(x)].iter().map(|&n| scale(n)).fold(0.0, f64::max);
+                        if peak > 0.0 { (tallest / peak * height as f64).round() as u32 } else { 0 }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        RgbaImage::from_fn(width, height, |x, y| {
+            // How far up from the bottom this pixel is (the bottom row is 0)
+            let up = height - 1 - y;
+            let mut color = [0u8; 3];
+            let mut covered = false;
+            for ((_, channel_color), bar) in channels.iter().zip(&bars) {
+                if up < bar[x as usize] {
+                    covered = true;
+                    for (total, value) in color.iter_mut().zip(channel_color) {
+                        *total = total.saturating_add(*value);
+                    }
+                }
+            }
+            if covered { Rgba([color[0], color[1], color[2], 255]) } else { Rgba([0, 0, 0, 0]) }
+        })
+    }
+}
+
+// Which histograms render() draws
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistogramChannels {
+    // Red, green and blue on top of each other
+    #[default]
+    Rgb,
+    Luminance,
+}
+
+// How render() turns counts into bar heights
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistogramScale {
+    #[default]
+    Linear,
+    // Squashes the tall bars down, so the small ones are still visible next to them
+    Logarithmic,
+}
+
+fn default_width() -> u32 {
+    256
+}
+
+fn default_height() -> u32 {
+    100
+}
+
+// The biggest histogram image we'll draw, either way
+const MAX_HISTOGRAM_SIZE: u32 = 4096;
+
+// How to draw a histogram
+// In JSON it looks like: { "width": 256, "height": 100, "channels": "luminance", "scale": "logarithmic" } (all optional)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistogramOptions {
+    #[serde(default = "default_width")]
+    pub width: u32,
+    #[serde(default = "default_height")]
+    pub height: u32,
+    #[serde(default)]
+    pub channels: HistogramChannels,
+    #[serde(default)]
+    pub scale: HistogramScale,
+}
+
+impl Default for HistogramOptions {
+    fn default() -> Self {
+        HistogramOptions { width: default_width(), height: default_height(), channels: HistogramChannels::default(), scale: HistogramScale::default() }
+    }
+}
+
+impl HistogramOptions {
+    pub fn from_json(json: &str) -> Result<Self, FilterError> {
+        let options: HistogramOptions = serde_json::from_str(json).map_err(|e| FilterError::InvalidParameter {
+            name: "histogram".to_string(),
+            reason: e.to_string(),
+        })?;
+        options.validate()?;
+        Ok(options)
+    }
+
+    pub fn validate(&self) -> Result<(), FilterError> {
+        for (name, value) in [("width", self.width), ("height", self.height)] {
+            if !(1..=MAX_HISTOGRAM_SIZE).contains(&value) {
+                return Err(FilterError::InvalidParameter {
+                    name: name.to_string(),
+                    reason: format!("{} is outside the range 1 to {}", value, MAX_HISTOGRAM_SIZE),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+// The histogram of an image file, as JSON (see Histogram)
+#[wasm_bindgen]
+pub fn image_histogram(img_data: &[u8]) -> Result<String, JsError> {
+    Ok(try_image_histogram(img_data)?.to_json())
+}
+
+// Draws an image file's histogram, as a PNG
+#[wasm_bindgen]
+pub fn render_histogram(img_data: &[u8], options_json: &str) -> Result<EncodedImage, JsError> {
+    let options = HistogramOptions::from_json(options_json)?;
+    Ok(try_render_histogram(img_data, &options)?)
+}
+
+// The Rust version of image_histogram
+pub fn try_image_histogram(img_data: &[u8]) -> Result<Histogram, FilterError> {
+    let (img, _) = crate::decode_image(img_data)?;
+    Ok(Histogram::from_image(&img))
+}
+
+// The Rust version of render_histogram
+pub fn try_render_histogram(img_data: &[u8], options: &HistogramOptions) -> Result<EncodedImage, FilterError> {
+    options.validate()?;
+    let (img, _) = crate::decode_image(img_data)?;
+    encode_histogram(&Histogram::from_image(&img), options)
+}
+
+pub(crate) fn encode_histogram(histogram: &Histogram, options: &HistogramOptions) -> Result<EncodedImage, FilterError> {
+    output::encode(&histogram.render(options), &OutputOptions::default(), None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 4 opaque pixels:  red 10, 20, 20, 50 => mean 25, stddev 15
+    //                   green 100 everywhere => stddev 0
+    //                   blue 0, 255, 0, 255 => mean 127.5, stddev 127.5
+    fn small_image() -> RgbaImage {
+        let reds = [10, 20, 20, 50];
+        RgbaImage::from_fn(2, 2, |x, y| {
+            let index = (y * 2 + x) as usize;
+            Rgba([reds[index], 100, [0, 255][index % 2], 255])
+        })
+    }
+
+    #[test]
+    fn small_known_image() {
+        let histogram = Histogram::from_image(&small_image());
+        assert_eq!(histogram.pixels, 4);
+
+        let red = &histogram.red;
+        assert_eq!(red.bins.len(), 256);
+        assert_eq!((red.bins[10], red.bins[20], red.bins[50]), (1, 2, 1));
+        assert_eq!(red.bins.iter().sum::<u32>(), 4);
+        assert_eq!((red.min, red.max, red.mean, red.stddev), (10, 50, 25.0, 15.0));
+
+        let green = &histogram.green;
+        assert_eq!(green.bins[100], 4);
+        assert_eq!((green.min, green.max, green.mean, green.stddev), (100, 100, 100.0, 0.0));
+
+        let blue = &histogram.blue;
+        assert_eq!((blue.bins[0], blue.bins[255]), (2, 2));
+        assert_eq!((blue.min, blue.max, blue.mean, blue.stddev), (0, 255, 127.5, 127.5));
+
+        // Luminance counts each pixel's grey
+        for px in small_image().pixels() {
+            assert!(histogram.luminance.bins[GrayscaleMethod::Rec709.luma(px[0], px[1], px[2]) as usize] > 0);
+        }
+        assert_eq!(histogram.luminance.bins.iter().sum::<u32>(), 4);
+    }
+
+    #[test]
+    fn transparent_pixels_are_skipped() {
+        let mut img = RgbaImage::from_pixel(3, 2, Rgba([255, 255, 255, 0]));
+        for (x, y, px) in small_image().enumerate_pixels() {
+            img.put_pixel(x, y, *px);
+        }
+        // Half transparent ones still count
+        img.put_pixel(2, 0, Rgba([10, 100, 0, 1]));
+        let histogram = Histogram::from_image(&img);
+        assert_eq!(histogram.pixels, 5);
+        assert_eq!(histogram.red.bins[255], 0);
+        assert_eq!(histogram.red.bins[10], 2);
+        assert_eq!(histogram.red.max, 50);
+    }
+
+    // No pixels to count => everything 0, rather than NaN from dividing by 0
+    #[test]
+    fn no_pixels() {
+        for img in [RgbaImage::new(0, 0), RgbaImage::new(3, 3)] {
+            let histogram = Histogram::from_image(&img);
+            assert_eq!(histogram.pixels, 0);
+            for channel in [&histogram.red, &histogram.green, &histogram.blue, &histogram.luminance] {
+                assert!(channel.bins.iter().all(|&n| n == 0));
+                assert_eq!((channel.min, channel.max, channel.mean, channel.stddev), (0, 0, 0.0, 0.0));
+            }
+            // Which is still valid JSON (NaN isn't), and draws as an empty chart
+            assert!(serde_json::from_str::<serde_json::Value>(&histogram.to_json()).is_ok());
+            let chart = histogram.render(&HistogramOptions::default());
+            assert!(chart.pixels().all(|px| px[3] == 0));
+        }
+    }
+
+    #[test]
+    fn render_size_and_peak() {
+        // Half black, half value 200: two bars of the same (full) height
+        let img = RgbaImage::from_fn(10, 1, |x, _| if x < 5 { Rgba([0, 0, 0, 255]) } else { Rgba([200, 200, 200, 255]) });
+        let histogram = Histogram::from_image(&img);
+        for (width, height) in [(256, 100), (16, 7), (1000, 3), (1, 1)] {
+            for channels in [HistogramChannels::Rgb, HistogramChannels::Luminance] {
+                let options = HistogramOptions { width, height, channels, ..HistogramOptions::default() };
+                let chart = histogram.render(&options);
+                assert_eq!(chart.dimensions(), (width, height));
+                // The peak reaches the top (and all three channels overlap there, so the RGB chart is white)
+                // (the last column starting at or before bin 200 is the one that covers it)
+                let column = (0..width).rev().find(|&x| x * 256 / width <= 200).unwrap();
+                let top = *chart.get_pixel(column, 0);
+                assert_eq!(top[3], 255, "{}x{}", width, height);
+                if channels == HistogramChannels::Rgb {
+                    assert_eq!(top, Rgba([255, 255, 255, 255]));
+                }
+                // And the columns that cover neither value are empty
+                for x in 0..width {
+                    let first = x * 256 / width;
+                    let last = ((x + 1) * 256 / width).max(first + 1);
+                    let drawn = (0..height).any(|y| chart.get_pixel(x, y)[3] > 0);
+                    assert_eq!(drawn, first == 0 || (first..last).contains(&200), "{}x{}, column {}", width, height, x);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn options_size() {
+        for (width, height) in [(0, 100), (256, 0), (4097, 100), (256, 4097)] {
+            let options = HistogramOptions { width, height, ..HistogramOptions::default() };
+            let name = if width == 256 { "height" } else { "width" };
+            assert!(matches!(options.validate(), Err(FilterError::InvalidParameter { name: param, .. }) if param == name), "{}x{}", width, height);
+        }
+        for (width, height) in [(1, 1), (4096, 4096)] {
+            assert!(HistogramOptions { width, height, ..HistogramOptions::default() }.validate().is_ok());
+        }
+        assert!(HistogramOptions::from_json(r#"{ "width": 0 }"#).is_err());
+        assert_eq!(HistogramOptions::from_json("{}").unwrap(), HistogramOptions::default());
+        assert!(try_render_histogram(&[], &HistogramOptions { width: 5000, ..HistogramOptions::default() })
+            .is_err_and(|e| matches!(e, FilterError::InvalidParameter { .. })));
+    }
+}
@@ -0,0 +1,200 @@
+// The hot inner loops of the simplest per-pixel filters (invert, sepia, posterize and 3x3 convolutions),
+// working on one row of raw RGBA bytes at a time
+//
+// With the "simd" feature (on by default) these use SIMD instructions to do 4 or 8 pixels at once:
+//   x86 / x86_64 => SSE2, or AVX2 if the build has it switched on (e.g. -C target-cpu=native)
+//   wasm32       => simd128, if the build has it switched on (-C target-feature=+simd128)
+// Anything else (or the feature switched off) gets the plain versions in scalar.rs
+// Either way the output is identical, byte for byte: the tests at the bottom check that, 'cargo bench --bench kernels' times them
+// The wasm version can't run natively, so check it still compiles after changing it:
+//   RUSTFLAGS='-C target-feature=+simd128' cargo check --target wasm32-unknown-unknown
+//
+// They're public so you can use them on your own buffers, and so the bench can compare them
+
+#[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64"), target_feature = "sse2"))]
+mod x86;
+#[cfg(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"))]
+mod wasm;
+#[cfg(any(
+    all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64"), target_feature = "sse2"),
+    all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"),
+))]
+mod vector;
+
+pub mod scalar;
+
+// The kernels the filters use: the SIMD ones when this build has them, otherwise the scalar ones
+#[cfg(any(
+    all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64"), target_feature = "sse2"),
+    all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"),
+))]
+pub use vector::{convolve_3x3_row, invert_row, posterize_row, sepia_row};
+#[cfg(not(any(
+    all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64"), target_feature = "sse2"),
+    all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"),
+)))]
+pub use scalar::{convolve_3x3_row, invert_row, posterize_row, sepia_row};
+
+// Which version this build is using, e.g. for printing in benchmarks
+pub const BACKEND: &str = if cfg!(not(feature = "simd")) {
+    "scalar"
+} else if cfg!(all(any(target_arch = "x86", target_arch = "x86_64"), target_feature = "avx2")) {
+    "avx2"
+} else if cfg!(all(any(target_arch = "x86", target_arch = "x86_64"), target_feature = "sse2")) {
+    "sse2"
+} else if cfg!(all(target_arch = "wasm32", target_feature = "simd128")) {
+    "simd128"
+} else {
+    "scalar"
+};
+
+// Every vector type this build can run against the scalar kernels, byte for byte
+// Row lengths go up to 40 pixels, so the left over pixels at the end of a row (handed to the scalar version)
+// are covered for every vector width, along with a row holding every value in every channel
+// AVX2 is only checked when it's switched on: RUSTFLAGS='-C target-cpu=native' cargo test kernels
+#[cfg(test)]
+mod tests {
+    use super::scalar;
+
+    // One backend's version of each kernel
+    struct Kernels {
+        name: &'static str,
+        invert: fn(&mut [u8]),
+        sepia: fn(&mut [u8]),
+        posterize: fn(&mut [u8], [f32; 3], [f32; 4]),
+        convolve: fn([&[f32]; 3], &[f32; 9], &mut [f32]),
+    }
+
+    // The same 4x4 pattern as posterize's ordered dithering
+    const BAYER_4X4: [[f32; 4]; 4] = [[0.0, 8.0, 2.0, 10.0], [12.0, 4.0, 14.0, 6.0], [3.0, 11.0, 1.0, 9.0], [15.0, 7.0, 13.0, 5.0]];
+
+    // A simple xorshift, so the 'random' bytes are the same every run
+    fn random_bytes(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 24) as u8
+            })
+            .collect()
+    }
+
+    // Runs both versions on copies of the row and panics if they don't match
+    fn compare(name: &str, row: &[u8], kernel: impl Fn(&mut [u8]), reference: impl Fn(&mut [u8])) {
+        let (mut actual, mut expected) = (row.to_vec(), row.to_vec());
+        kernel(&mut actual);
+        reference(&mut expected);
+        if let Some(index) = actual.iter().zip(&expected).position(|(a, b)| a != b) {
+            let pixel = index / 4 * 4;
+            panic!(
+                "{} differs at pixel {} of {}: input {:?}, got {:?}, expected {:?}",
+                name,
+                index / 4,
+                row.len() / 4,
+                &row[pixel..pixel + 4],
+                &actual[pixel..pixel + 4],
+                &expected[pixel..pixel + 4]
+            );
+        }
+    }
+
+    fn check(kernels: Kernels) {
+        let mut rows: Vec<Vec<u8>> = (0..=40).map(|pixels| random_bytes(pixels * 4, pixels as u64)).collect();
+        rows.push((0..=255u8).flat_map(|value| [value, 255 - value, value.wrapping_mul(37), value]).collect());
+
+        for row in &rows {
+            compare(&format!("{} invert_row", kernels.name), row, kernels.invert, scalar::invert_row);
+            compare(&format!("{} sepia_row", kernels.name), row, kernels.sepia, scalar::sepia_row);
+
+            for levels in [2u16, 3, 4, 5, 7, 16, 85, 128, 254, 255, 256] {
+                // Different levels per channel, like red_levels / green_levels / blue_levels
+                let steps = [levels, levels / 2 + 2, 257 - levels].map(|n| 255.0 / (n.clamp(2, 256) - 1) as f32);
+                let mut patterns = vec![[0.0f32; 4]];
+                patterns.extend(BAYER_4X4.map(|row| row.map(|threshold| (threshold + 0.5) / 16.0 - 0.5)));
+                for nudges in patterns {
+                    compare(
+                        &format!("{} posterize_row ({} levels)", kernels.name, levels),
+                        row,
+                        |row| (kernels.posterize)(row, steps, nudges),
+                        |row| scalar::posterize_row(row, steps, nudges),
+                    );
+                }
+            }
+        }
+
+        // Convolution: every output width up to 40, with the kinds of values (and weights) the filters use
+        let kernels_3x3: [[f32; 9]; 4] = [
+            [-2.0, -1.0, 0.0, -1.0, 1.0, 1.0, 0.0, 1.0, 2.0],     // emboss
+            [0.0, -1.0, 0.0, -1.0, 5.0, -1.0, 0.0, -1.0, 0.0],    // sharpen
+            [1.0, 2.0, 1.0, 2.0, 4.0, 2.0, 1.0, 2.0, 1.0],        // a small blur
+            [0.13, -7.5, 1e-3, 3.3, 0.7, -0.25, 99.0, -1e3, 0.5], // nonsense
+        ];
+        for width in 0..=40 {
+            let rows: Vec<Vec<f32>> = (0..3)
+                .map(|seed| random_bytes((width + 2) * 4, seed * 100 + width as u64).iter().map(|&value| value as f32 * 0.731).collect())
+                .collect();
+            for weights in &kernels_3x3 {
+                let mut expected = vec![0.0f32; width * 4];
+                let mut actual = vec![1.0f32; width * 4];
+                scalar::convolve_3x3_row([&rows[0], &rows[1], &rows[2]], weights, &mut expected);
+                (kernels.convolve)([&rows[0], &rows[1], &rows[2]], weights, &mut actual);
+                // to_bits => compare the exact floats (and -0.0 isn't 'equal' to 0.0 here)
+                let bits = |values: &[f32]| values.iter().map(|value| value.to_bits()).collect::<Vec<_>>();
+                assert_eq!(bits(&actual), bits(&expected), "{} convolve_3x3_row differs (width {}, weights {:?})", kernels.name, width, weights);
+            }
+        }
+    }
+
+    // Whatever this build's filters use (the scalar ones against themselves without the "simd" feature)
+    #[test]
+    fn build_matches_scalar() {
+        check(Kernels {
+            name: super::BACKEND,
+            invert: super::invert_row,
+            sepia: super::sepia_row,
+            posterize: super::posterize_row,
+            convolve: super::convolve_3x3_row,
+        });
+    }
+
+    #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64"), target_feature = "sse2"))]
+    #[test]
+    fn sse2_matches_scalar() {
+        use super::{vector, x86::F32x4};
+        check(Kernels {
+            name: "sse2",
+            invert: vector::invert_row_with::<F32x4>,
+            sepia: vector::sepia_row_with::<F32x4>,
+            posterize: vector::posterize_row_with::<F32x4>,
+            convolve: vector::convolve_3x3_row_with::<F32x4>,
+        });
+    }
+
+    #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64"), target_feature = "avx2"))]
+    #[test]
+    fn avx2_matches_scalar() {
+        use super::{vector, x86::F32x8};
+        check(Kernels {
+            name: "avx2",
+            invert: vector::invert_row_with::<F32x8>,
+            sepia: vector::sepia_row_with::<F32x8>,
+            posterize: vector::posterize_row_with::<F32x8>,
+            convolve: vector::convolve_3x3_row_with::<F32x8>,
+        });
+    }
+
+    #[cfg(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"))]
+    #[test]
+    fn simd128_matches_scalar() {
+        use super::{vector, wasm::F32x4};
+        check(Kernels {
+            name: "simd128",
+            invert: vector::invert_row_with::<F32x4>,
+            sepia: vector::sepia_row_with::<F32x4>,
+            posterize: vector::posterize_row_with::<F32x4>,
+            convolve: vector::convolve_3x3_row_with::<F32x4>,
+        });
+    }
+}
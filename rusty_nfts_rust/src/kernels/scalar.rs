@@ -0,0 +1,65 @@
+// The plain Rust versions of the kernels, one value at a time
+// These are the 'reference': the SIMD versions have to give exactly the same bytes
+// (they're also what runs on the pixels left over at the end of a row)
+
+// The sepia matrix, one row per output channel: [from red, from green, from blue]
+pub(super) const SEPIA: [[f32; 3]; 3] = [
+    [0.393, 0.769, 0.189], // New red value
+    [0.349, 0.686, 0.168], // New green value
+    [0.272, 0.534, 0.131], // New blue value
+];
+
+// Flips every colour channel (0 <=> 255), leaving alpha alone
+pub fn invert_row(row: &mut [u8]) {
+    for pixel in row.chunks_exact_mut(4) {
+        for value in &mut pixel[..3] {
+            *value = 255 - *value;
+        }
+    }
+}
+
+pub fn sepia_row(row: &mut [u8]) {
+    for pixel in row.chunks_exact_mut(4) {
+        // Extract the red, green, and blue values from the current pixel
+        let (red, green, blue) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+
+        // Apply the sepia transformation formula to each color channel (alpha is unchanged)
+        for (value, [from_red, from_green, from_blue]) in pixel.iter_mut().zip(SEPIA) {
+            *value = (from_red * red + from_green * green + from_blue * blue).min(255.0) as u8;
+        }
+    }
+}
+
+// Snaps every colour to the nearest of its channel's levels, 'steps' being the gap between two levels
+// Each pixel is nudged by nudges[x % 4] of a step first (all 0s => no dithering)
+pub fn posterize_row(row: &mut [u8], steps: [f32; 3], nudges: [f32; 4]) {
+    for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+        let nudge = nudges[x % 4];
+        for (value, step) in pixel.iter_mut().zip(steps) {
+            *value = quantize(*value as f32 + nudge * step, step);
+        }
+    }
+}
+
+// Rounds a value to the nearest level (rather than always down, which made the image darker)
+pub fn quantize(value: f32, step: f32) -> u8 {
+    ((value / step).round() * step).round().clamp(0.0, 255.0) as u8
+}
+
+// One row of a 3x3 convolution, before dividing
+// 'rows' are the image rows above, on and below this one, already turned into floats (RGBA, 4 per pixel)
+// and with one extra pixel on each end, so rows[..][x * 4..] is the pixel to the left of output pixel x
+// 'weights' are the kernel's weights row by row, 'out' gets the sum for every channel of every pixel
+pub fn convolve_3x3_row(rows: [&[f32]; 3], weights: &[f32; 9], out: &mut [f32]) {
+    for (x, sum) in out.chunks_exact_mut(4).enumerate() {
+        let mut total = [0.0f32; 4];
+        for (row, kernel_row) in rows.iter().zip(weights.chunks_exact(3)) {
+            for (kx, weight) in kernel_row.iter().enumerate() {
+                for (total, value) in total.iter_mut().zip(&row[(x + kx) * 4..(x + kx) * 4 + 4]) {
+                    *total += weight * value;
+                }
+            }
+        }
+        sum.copy_from_slice(&total);
+    }
+}
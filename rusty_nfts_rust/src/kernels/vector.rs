@@ -0,0 +1,122 @@
+use super::scalar::{self, SEPIA};
+
+// The SIMD versions of the kernels, written once against the Lanes trait below
+// and then run with whichever vector type this build has (see mod.rs)
+// (the generic versions take the vector type as a parameter, so the tests can run every one this build can)
+
+#[cfg(all(any(target_arch = "x86", target_arch = "x86_64"), target_feature = "avx2"))]
+use super::x86::F32x8 as V;
+#[cfg(all(any(target_arch = "x86", target_arch = "x86_64"), not(target_feature = "avx2")))]
+use super::x86::F32x4 as V;
+#[cfg(target_arch = "wasm32")]
+use super::wasm::F32x4 as V;
+
+// A vector of f32s, plus the handful of operations the kernels need
+// Every operation works on each lane on its own, exactly like the scalar maths does,
+// which is why the results come out identical (there's no fused multiply-add or reordering)
+pub(super) trait Lanes: Copy {
+    // How many f32s are in one vector, always a multiple of 4 (so it holds whole pixels)
+    const LANES: usize;
+
+    fn splat(value: f32) -> Self;
+    // The first LANES values of the slice
+    fn load(values: &[f32]) -> Self;
+    fn store(self, out: &mut [f32]);
+
+    fn add(self, other: Self) -> Self;
+    fn mul(self, other: Self) -> Self;
+    fn div(self, other: Self) -> Self;
+    fn min(self, other: Self) -> Self;
+    fn max(self, other: Self) -> Self;
+    // The same as f32::round, i.e. halves round away from 0 (only needs to work for values well within i32)
+    fn round(self) -> Self;
+
+    // LANES pixels (LANES * 4 bytes of RGBA) => their red, green and blue values, one pixel per lane
+    fn unpack(pixels: &[u8]) -> [Self; 3];
+    // The other way round, over the top of the same pixels (their alpha is kept)
+    // The values must already be 0 - 255, anything after the decimal point is cut off (like 'as u8')
+    fn pack(rgb: [Self; 3], pixels: &mut [u8]);
+    // 255 - value for the colour channels of LANES pixels (this one doesn't need floats at all)
+    fn invert(pixels: &mut [u8]);
+}
+
+pub fn invert_row(row: &mut [u8]) {
+    invert_row_with::<V>(row)
+}
+
+pub(super) fn invert_row_with<V: Lanes>(row: &mut [u8]) {
+    let mut chunks = row.chunks_exact_mut(V::LANES * 4);
+    for chunk in &mut chunks {
+        V::invert(chunk);
+    }
+    scalar::invert_row(chunks.into_remainder());
+}
+
+pub fn sepia_row(row: &mut [u8]) {
+    sepia_row_with::<V>(row)
+}
+
+pub(super) fn sepia_row_with<V: Lanes>(row: &mut [u8]) {
+    let mut chunks = row.chunks_exact_mut(V::LANES * 4);
+    for chunk in &mut chunks {
+        let [red, green, blue] = V::unpack(chunk);
+        // Added up in the same order as the scalar version, so the rounding errors match too
+        let channel = |[from_red, from_green, from_blue]: [f32; 3]| {
+            V::splat(from_red)
+                .mul(red)
+                .add(V::splat(from_green).mul(green))
+                .add(V::splat(from_blue).mul(blue))
+                .min(V::splat(255.0))
+        };
+        V::pack(SEPIA.map(channel), chunk);
+    }
+    scalar::sepia_row(chunks.into_remainder());
+}
+
+pub fn posterize_row(row: &mut [u8], steps: [f32; 3], nudges: [f32; 4]) {
+    posterize_row_with::<V>(row, steps, nudges)
+}
+
+pub(super) fn posterize_row_with<V: Lanes>(row: &mut [u8], steps: [f32; 3], nudges: [f32; 4]) {
+    // LANES is a multiple of 4, so every chunk starts at a multiple of 4 and gets the same nudges in the same order
+    let nudge = V::load(&std::array::from_fn::<f32, 8, _>(|i| nudges[i % 4]));
+    let (zero, max) = (V::splat(0.0), V::splat(255.0));
+
+    let mut chunks = row.chunks_exact_mut(V::LANES * 4);
+    for chunk in &mut chunks {
+        let channels = V::unpack(chunk);
+        let quantized: [V; 3] = std::array::from_fn(|c| {
+            let step = V::splat(steps[c]);
+            let value = channels[c].add(nudge.mul(step));
+            value.div(step).round().mul(step).round().max(zero).min(max)
+        });
+        V::pack(quantized, chunk);
+    }
+    scalar::posterize_row(chunks.into_remainder(), steps, nudges);
+}
+
+pub fn convolve_3x3_row(rows: [&[f32]; 3], weights: &[f32; 9], out: &mut [f32]) {
+    convolve_3x3_row_with::<V>(rows, weights, out)
+}
+
+pub(super) fn convolve_3x3_row_with<V: Lanes>(rows: [&[f32]; 3], weights: &[f32; 9], out: &mut [f32]) {
+    let splatted = weights.map(V::splat);
+
+    // One vector holds LANES / 4 pixels, and neighbouring pixels are next to each other in the rows,
+    // so the same loads work for every pixel in the vector
+    let mut chunks = out.chunks_exact_mut(V::LANES);
+    let mut start = 0;
+    for chunk in &mut chunks {
+        let mut total = V::splat(0.0);
+        for (row, kernel_row) in rows.iter().zip(splatted.chunks_exact(3)) {
+            // The pixels under this vector, plus one either side
+            let window = &row[start..start + V::LANES + 8];
+            for (kx, weight) in kernel_row.iter().enumerate() {
+                total = total.add(weight.mul(V::load(&window[kx * 4..])));
+            }
+        }
+        total.store(chunk);
+        start += V::LANES;
+    }
+    scalar::convolve_3x3_row(rows.map(|row| &row[start..]), weights, chunks.into_remainder());
+}
@@ -0,0 +1,97 @@
+use std::arch::wasm32::*;
+
+use super::vector::Lanes;
+
+// The WebAssembly vector type (the 'simd128' proposal, supported by every current browser)
+// It has to be switched on when compiling, e.g.
+//   RUSTFLAGS='-C target-feature=+simd128' wasm-pack build --target web
+//
+// Same layout as the x86 version: each pixel is one 32 bit lane, red in the lowest byte
+
+// The bits of a pixel's colour channels, i.e. everything but alpha
+const COLOUR_MASK: u32 = 0x00ff_ffff;
+
+#[derive(Clone, Copy)]
+pub(super) struct F32x4(v128);
+
+impl Lanes for F32x4 {
+    const LANES: usize = 4;
+
+    fn splat(value: f32) -> Self {
+        F32x4(f32x4_splat(value))
+    }
+
+    fn load(values: &[f32]) -> Self {
+        let values = &values[..4];
+        // Safety: the slice above is checked to be (at least) 4 long, and v128_load doesn't need it aligned
+        F32x4(unsafe { v128_load(values.as_ptr() as *const v128) })
+    }
+
+    fn store(self, out: &mut [f32]) {
+        let out = &mut out[..4];
+        // Safety: as in load
+        unsafe { v128_store(out.as_mut_ptr() as *mut v128, self.0) }
+    }
+
+    fn add(self, other: Self) -> Self {
+        F32x4(f32x4_add(self.0, other.0))
+    }
+
+    fn mul(self, other: Self) -> Self {
+        F32x4(f32x4_mul(self.0, other.0))
+    }
+
+    fn div(self, other: Self) -> Self {
+        F32x4(f32x4_div(self.0, other.0))
+    }
+
+    fn min(self, other: Self) -> Self {
+        F32x4(f32x4_min(self.0, other.0))
+    }
+
+    fn max(self, other: Self) -> Self {
+        F32x4(f32x4_max(self.0, other.0))
+    }
+
+    fn round(self) -> Self {
+        // f32x4_nearest rounds halves to even, so round the size away from 0 by hand, then put the sign back
+        let size = f32x4_abs(self.0);
+        let whole = f32x4_trunc(size);
+        // The fraction is exact (no rounding), so >= 0.5 is exactly when f32::round goes up
+        let round_up = f32x4_ge(f32x4_sub(size, whole), f32x4_splat(0.5));
+        let rounded = f32x4_add(whole, v128_and(round_up, f32x4_splat(1.0)));
+        F32x4(v128_or(rounded, v128_and(self.0, f32x4_splat(-0.0))))
+    }
+
+    fn unpack(pixels: &[u8]) -> [Self; 3] {
+        let pixels = &pixels[..16];
+        // Safety: the slice above is checked to be 16 bytes (4 pixels)
+        let pixels = unsafe { v128_load(pixels.as_ptr() as *const v128) };
+        let byte = u32x4_splat(0xff);
+        [
+            F32x4(f32x4_convert_u32x4(v128_and(pixels, byte))),
+            F32x4(f32x4_convert_u32x4(v128_and(u32x4_shr(pixels, 8), byte))),
+            F32x4(f32x4_convert_u32x4(v128_and(u32x4_shr(pixels, 16), byte))),
+        ]
+    }
+
+    fn pack([red, green, blue]: [Self; 3], pixels: &mut [u8]) {
+        let pixels = &mut pixels[..16];
+        let pointer = pixels.as_mut_ptr() as *mut v128;
+        // Safety: as in unpack
+        let alpha = v128_andnot(unsafe { v128_load(pointer) }, u32x4_splat(COLOUR_MASK));
+        let colour = v128_or(
+            u32x4_trunc_sat_f32x4(red.0),
+            v128_or(u32x4_shl(u32x4_trunc_sat_f32x4(green.0), 8), u32x4_shl(u32x4_trunc_sat_f32x4(blue.0), 16)),
+        );
+        unsafe { v128_store(pointer, v128_or(colour, alpha)) }
+    }
+
+    fn invert(pixels: &mut [u8]) {
+        let pixels = &mut pixels[..16];
+        let pointer = pixels.as_mut_ptr() as *mut v128;
+        // 255 - value is the same as flipping every bit of the byte
+        // Safety: as in unpack
+        unsafe { v128_store(pointer, v128_xor(v128_load(pointer), u32x4_splat(COLOUR_MASK))) }
+    }
+}
@@ -0,0 +1,202 @@
+#[cfg(target_arch = "x86")]
+use std::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+use super::vector::Lanes;
+
+// The x86 vector types: SSE2 (4 lanes, every x86_64 CPU has it) and AVX2 (8 lanes, most CPUs since ~2013)
+// Which one is used is decided when compiling, AVX2 only when it's switched on, e.g.
+//   RUSTFLAGS='-C target-cpu=native' cargo build --release
+//
+// Each pixel is one 32 bit lane of an integer vector (little endian, so red is the lowest byte),
+// which makes splitting pixels into channels a shift and a mask rather than a shuffle
+//
+// About the 'unsafe's: Rust treats every intrinsic as unsafe unless it can see the CPU has the instructions
+// This file is only compiled when the build has SSE2 (and AVX2 only when it has AVX2), so they always are there,
+// and every load / store goes through a slice that has just been checked to be long enough
+
+// The bits of a pixel's colour channels, i.e. everything but alpha
+const COLOUR_MASK: i32 = 0x00ff_ffff;
+
+// 4 pixels at a time with SSE2 (AVX2 builds use F32x8 instead, and only keep this one for the tests, which check both)
+#[cfg(any(test, not(target_feature = "avx2")))]
+#[derive(Clone, Copy)]
+pub(super) struct F32x4(__m128);
+
+#[cfg(any(test, not(target_feature = "avx2")))]
+impl Lanes for F32x4 {
+    const LANES: usize = 4;
+
+    fn splat(value: f32) -> Self {
+        F32x4(unsafe { _mm_set1_ps(value) })
+    }
+
+    fn load(values: &[f32]) -> Self {
+        let values = &values[..4];
+        F32x4(unsafe { _mm_loadu_ps(values.as_ptr()) })
+    }
+
+    fn store(self, out: &mut [f32]) {
+        let out = &mut out[..4];
+        unsafe { _mm_storeu_ps(out.as_mut_ptr(), self.0) }
+    }
+
+    fn add(self, other: Self) -> Self {
+        F32x4(unsafe { _mm_add_ps(self.0, other.0) })
+    }
+
+    fn mul(self, other: Self) -> Self {
+        F32x4(unsafe { _mm_mul_ps(self.0, other.0) })
+    }
+
+    fn div(self, other: Self) -> Self {
+        F32x4(unsafe { _mm_div_ps(self.0, other.0) })
+    }
+
+    fn min(self, other: Self) -> Self {
+        F32x4(unsafe { _mm_min_ps(self.0, other.0) })
+    }
+
+    fn max(self, other: Self) -> Self {
+        F32x4(unsafe { _mm_max_ps(self.0, other.0) })
+    }
+
+    fn round(self) -> Self {
+        unsafe {
+            // SSE2 can only round to even (or truncate), so: round the size away from 0 by hand, then put the sign back
+            let sign = _mm_and_ps(self.0, _mm_set1_ps(-0.0));
+            let size = _mm_andnot_ps(_mm_set1_ps(-0.0), self.0);
+            let whole = _mm_cvtepi32_ps(_mm_cvttps_epi32(size));
+            // The fraction is exact (no rounding), so >= 0.5 is exactly when f32::round goes up
+            let round_up = _mm_cmpge_ps(_mm_sub_ps(size, whole), _mm_set1_ps(0.5));
+            let rounded = _mm_add_ps(whole, _mm_and_ps(round_up, _mm_set1_ps(1.0)));
+            F32x4(_mm_or_ps(rounded, sign))
+        }
+    }
+
+    fn unpack(pixels: &[u8]) -> [Self; 3] {
+        let pixels = &pixels[..16];
+        unsafe {
+            let pixels = _mm_loadu_si128(pixels.as_ptr() as *const __m128i);
+            let byte = _mm_set1_epi32(0xff);
+            [
+                F32x4(_mm_cvtepi32_ps(_mm_and_si128(pixels, byte))),
+                F32x4(_mm_cvtepi32_ps(_mm_and_si128(_mm_srli_epi32::<8>(pixels), byte))),
+                F32x4(_mm_cvtepi32_ps(_mm_and_si128(_mm_srli_epi32::<16>(pixels), byte))),
+            ]
+        }
+    }
+
+    fn pack([red, green, blue]: [Self; 3], pixels: &mut [u8]) {
+        let pixels = &mut pixels[..16];
+        unsafe {
+            let pointer = pixels.as_mut_ptr() as *mut __m128i;
+            let alpha = _mm_andnot_si128(_mm_set1_epi32(COLOUR_MASK), _mm_loadu_si128(pointer));
+            let colour = _mm_or_si128(
+                _mm_cvttps_epi32(red.0),
+                _mm_or_si128(_mm_slli_epi32::<8>(_mm_cvttps_epi32(green.0)), _mm_slli_epi32::<16>(_mm_cvttps_epi32(blue.0))),
+            );
+            _mm_storeu_si128(pointer, _mm_or_si128(colour, alpha))
+        }
+    }
+
+    fn invert(pixels: &mut [u8]) {
+        let pixels = &mut pixels[..16];
+        unsafe {
+            let pointer = pixels.as_mut_ptr() as *mut __m128i;
+            // 255 - value is the same as flipping every bit of the byte
+            _mm_storeu_si128(pointer, _mm_xor_si128(_mm_loadu_si128(pointer), _mm_set1_epi32(COLOUR_MASK)))
+        }
+    }
+}
+
+// Exactly the same as F32x4, 8 pixels at a time (used instead of it when AVX2 is switched on)
+#[cfg(target_feature = "avx2")]
+#[derive(Clone, Copy)]
+pub(super) struct F32x8(__m256);
+
+#[cfg(target_feature = "avx2")]
+impl Lanes for F32x8 {
+    const LANES: usize = 8;
+
+    fn splat(value: f32) -> Self {
+        F32x8(unsafe { _mm256_set1_ps(value) })
+    }
+
+    fn load(values: &[f32]) -> Self {
+        let values = &values[..8];
+        F32x8(unsafe { _mm256_loadu_ps(values.as_ptr()) })
+    }
+
+    fn store(self, out: &mut [f32]) {
+        let out = &mut out[..8];
+        unsafe { _mm256_storeu_ps(out.as_mut_ptr(), self.0) }
+    }
+
+    fn add(self, other: Self) -> Self {
+        F32x8(unsafe { _mm256_add_ps(self.0, other.0) })
+    }
+
+    fn mul(self, other: Self) -> Self {
+        F32x8(unsafe { _mm256_mul_ps(self.0, other.0) })
+    }
+
+    fn div(self, other: Self) -> Self {
+        F32x8(unsafe { _mm256_div_ps(self.0, other.0) })
+    }
+
+    fn min(self, other: Self) -> Self {
+        F32x8(unsafe { _mm256_min_ps(self.0, other.0) })
+    }
+
+    fn max(self, other: Self) -> Self {
+        F32x8(unsafe { _mm256_max_ps(self.0, other.0) })
+    }
+
+    fn round(self) -> Self {
+        unsafe {
+            // AVX does have a 'round' instruction, but it rounds halves to even, so this is done by hand too
+            let sign = _mm256_and_ps(self.0, _mm256_set1_ps(-0.0));
+            let size = _mm256_andnot_ps(_mm256_set1_ps(-0.0), self.0);
+            let whole = _mm256_round_ps::<{ _MM_FROUND_TO_ZERO | _MM_FROUND_NO_EXC }>(size);
+            let round_up = _mm256_cmp_ps::<_CMP_GE_OQ>(_mm256_sub_ps(size, whole), _mm256_set1_ps(0.5));
+            let rounded = _mm256_add_ps(whole, _mm256_and_ps(round_up, _mm256_set1_ps(1.0)));
+            F32x8(_mm256_or_ps(rounded, sign))
+        }
+    }
+
+    fn unpack(pixels: &[u8]) -> [Self; 3] {
+        let pixels = &pixels[..32];
+        unsafe {
+            let pixels = _mm256_loadu_si256(pixels.as_ptr() as *const __m256i);
+            let byte = _mm256_set1_epi32(0xff);
+            [
+                F32x8(_mm256_cvtepi32_ps(_mm256_and_si256(pixels, byte))),
+                F32x8(_mm256_cvtepi32_ps(_mm256_and_si256(_mm256_srli_epi32::<8>(pixels), byte))),
+                F32x8(_mm256_cvtepi32_ps(_mm256_and_si256(_mm256_srli_epi32::<16>(pixels), byte))),
+            ]
+        }
+    }
+
+    fn pack([red, green, blue]: [Self; 3], pixels: &mut [u8]) {
+        let pixels = &mut pixels[..32];
+        unsafe {
+            let pointer = pixels.as_mut_ptr() as *mut __m256i;
+            let alpha = _mm256_andnot_si256(_mm256_set1_epi32(COLOUR_MASK), _mm256_loadu_si256(pointer));
+            let colour = _mm256_or_si256(
+                _mm256_cvttps_epi32(red.0),
+                _mm256_or_si256(_mm256_slli_epi32::<8>(_mm256_cvttps_epi32(green.0)), _mm256_slli_epi32::<16>(_mm256_cvttps_epi32(blue.0))),
+            );
+            _mm256_storeu_si256(pointer, _mm256_or_si256(colour, alpha))
+        }
+    }
+
+    fn invert(pixels: &mut [u8]) {
+        let pixels = &mut pixels[..32];
+        unsafe {
+            let pointer = pixels.as_mut_ptr() as *mut __m256i;
+            _mm256_storeu_si256(pointer, _mm256_xor_si256(_mm256_loadu_si256(pointer), _mm256_set1_epi32(COLOUR_MASK)))
+        }
+    }
+}
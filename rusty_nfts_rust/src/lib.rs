@@ -1,200 +1,193 @@
 use wasm_bindgen::prelude::*;
-use image::{ImageBuffer, RgbaImage, Rgba};
-use image::imageops::{grayscale, blur, huerotate, invert};
-use image::codecs::png::PngEncoder;
-use image::ColorType;
+use image::{ImageFormat, ImageReader, RgbaImage};
 use std::io::Cursor;
 
+mod error;
+mod filter;
+mod filter_kind;
+pub mod filters;
+mod histogram;
+pub mod kernels;
+mod lut;
+mod output;
+mod parallel;
+mod pipeline;
+mod raw;
+mod registry;
+mod session;
+mod spec;
+#[cfg(all(target_arch = "wasm32", feature = "wasm-threads"))]
+mod threads;
+
+pub use error::FilterError;
+pub use filter::Filter;
+pub use filter_kind::FilterKind;
+pub use histogram::{
+    image_histogram, render_histogram, try_image_histogram, try_render_histogram, ChannelHistogram, Histogram, HistogramChannels,
+    HistogramOptions, HistogramScale,
+};
+pub use lut::{register_lut, try_register_lut, CubeLut, LutFilter, LutInterpolation};
+pub use output::{list_output_formats, EncodedImage, OutputFormat, OutputOptions};
+pub use pipeline::Pipeline;
+use pipeline::run_stages;
+pub use raw::{
+    apply_filter_rgba, apply_pipeline_image_data, apply_pipeline_rgba, filter_rgba, filter_rgba_in_place,
+    rgba_image_from_raw, RgbaPixels,
+};
+pub use registry::{describe_filter, filter_label, list_filters, register_filter, with_registry, FilterRegistry};
+pub use session::ImageSession;
+pub use spec::{FilterSpec, ParamInfo, ParamKind, Params};
+#[cfg(all(target_arch = "wasm32", feature = "wasm-threads"))]
+pub use threads::{init_thread_pool, run_pool_worker};
+
+// The biggest image (in pixels, i.e. width * height) we are willing to decode
+// 40 million RGBA pixels is ~160MB per copy, which is already a lot for a wasm instance
+pub const MAX_IMAGE_PIXELS: u64 = 40_000_000;
+
 // Exposes the apply_filter function to JavaScript, i.e. the function can be called from JavaScript
 #[wasm_bindgen]
 // It takes in a byte array and a string as input and returns a byte array as output
 // '&' => Immutable reference, i.e. I can look but can't touch. It's like read-only. 
 // 'u8' is a byte, i.e. ranges from 0 to 255 (just like a pixel value)
 // 'Vec<u8>' is a vector (i.e. a dynamic array) of bytes (i.e. a dynamic array of pixel values)
+// 'Result' => either Ok(bytes) or Err(error). wasm-bindgen turns the Err into a thrown JS Error,
+// so a broken upload no longer traps the whole wasm instance
+pub fn apply_filter(img_data: &[u8], filter_type: &str) -> Result<Vec<u8>, JsError> {
+    // The name is looked up in the filter registry, so an unknown name is an error rather than a no-op
+    // '?' => if it's an error, return early (JsError can be made from any std::error::Error)
+    Ok(try_apply_spec(img_data, &FilterSpec::new(filter_type))?)
+}
+
+// Same as apply_filter, but takes a JSON FilterSpec so the parameters can be set
+// e.g. apply_filter_with_options(bytes, '{ "filter": "blur", "params": { "sigma": 2.5 } }')
+#[wasm_bindgen]
+pub fn apply_filter_with_options(img_data: &[u8], spec_json: &str) -> Result<Vec<u8>, JsError> {
+    let spec = FilterSpec::from_json(spec_json)?;
+    Ok(try_apply_spec(img_data, &spec)?)
+}
+
+// Runs a whole chain of filters in one call, decoding and encoding only once
+// e.g. apply_pipeline(bytes, '{ "stages": [ { "filter": "sepia" }, { "filter": "sharpen" } ] }')
+#[wasm_bindgen]
+pub fn apply_pipeline(img_data: &[u8], pipeline_json: &str) -> Result<Vec<u8>, JsError> {
+    let pipeline = Pipeline::from_json(pipeline_json)?;
+    Ok(try_apply_pipeline(img_data, &pipeline)?)
+}
+
+// Same as apply_pipeline, but the output format can be chosen too
+// e.g. apply_pipeline_with_output(bytes, '{ "stages": [ { "filter": "sepia" } ] }', '{ "format": "jpeg", "quality": 80 }')
+// The result has the bytes plus their MIME type, so the Blob type doesn't need to be hardcoded
+#[wasm_bindgen]
+pub fn apply_pipeline_with_output(img_data: &[u8], pipeline_json: &str, output_json: &str) -> Result<EncodedImage, JsError> {
+    let pipeline = Pipeline::from_json(pipeline_json)?;
+    let options = OutputOptions::from_json(output_json)?;
+    Ok(try_apply_pipeline_with_output(img_data, &pipeline, &options)?)
+}
+
+// The Rust version of apply_filter, for native callers (and anything that wants the typed error)
+pub fn try_apply_filter(img_data: &[u8], kind: FilterKind) -> Result<Vec<u8>, FilterError> {
+    try_apply_spec(img_data, &FilterSpec::new(kind))
+}
+
+// The Rust version of apply_filter_with_options
+pub fn try_apply_spec(img_data: &[u8], spec: &FilterSpec) -> Result<Vec<u8>, FilterError> {
+    // Set the filter up before decoding, there's no point decoding an image we can't filter
+    let filter = spec.build()?;
 
-pub fn apply_filter(img_data: &[u8], filter_type: &str) -> Vec<u8> {
     // Load the image from memory
     // The image crate supports and automatically detects a range of image formats
-    let img = image::load_from_memory(img_data).expect("Failed to load image");
-    
-    // RGBA8 format is a common format for image processing
-    let img = img.to_rgba8();
-
-    // 'match' is like 'switch' in JS
-    let processed_img: RgbaImage = match filter_type {
-        "grayscale" => {
-            let gray_img = grayscale(&img);
-            // Need to convert the grayscale image to RGBA format
-            // i.e. we iterate over the grayscale image and set the R, G, B values to the same value
-            // and set the alpha value to 255 (i.e. fully opaque)
-            // We use a closure = an anonymous function that doesn't have a name 
-            // Syntax => |input1, input2, ...| { code }
-            ImageBuffer::from_fn(gray_img.width(), gray_img.height(), |x, y| {
-                let luma = gray_img.get_pixel(x, y)[0];
-                Rgba([luma, luma, luma, 255])
-            })
-        },
-        // 5.0 is the amount of blur
-        "blur" => blur(&img, 5.0),
-        // 90 is the angle by which the hue is 'rotated'
-        "huerotate" => huerotate(&img, 90),
-        "invert" => {
-            // Clone the image so that the original image is not modified
-            // mut => mutable reference, i.e. I can look and touch
-            let mut img_clone = img.clone();
-            invert(&mut img_clone);
-            img_clone
-        },
-        "sepia" => apply_sepia(&img),
-        "pixelate" => {
-            // Basically, downscale so that quality is lost and then upscale to original size
-            // Nearest => doesn't blend or smooth the pixels. Instead it just picks the nearest pixel
-            let resized_img = image::imageops::resize(&img, img.width() / 10, img.height() / 10, image::imageops::FilterType::Nearest);
-            // Resize the resized image back to the original size
-            image::imageops::resize(&resized_img, img.width(), img.height(), image::imageops::FilterType::Nearest)
-        },
-        "emboss" => apply_emboss(&img),
-        "sharpen" => apply_sharpen(&img),
-        // 4 is the number of levels (i.e. the number of colors in the image)
-        "posterize" => apply_posterize(&img, 4),
-        // '_' => If not recognized, return the original image
-        _ => img,
-    };
-
-    // Encode the processed image as PNG
-    let mut buffer = Vec::new();
-    // Cursor is a type that allows you to write to a buffer as if it were a file
-    let mut cursor = Cursor::new(&mut buffer);
-    // PngEncoder is a type that allows you to encode an image as a PNG
-    let encoder = PngEncoder::new(&mut cursor);
-    encoder
-        .encode(&processed_img, processed_img.width(), processed_img.height(), ColorType::Rgba8)
-        .expect("Failed to encode image");
-
-    // buffer is returned as a byte array
-    buffer
+    let (img, _) = decode_image(img_data)?;
+
+    let processed_img = filter.apply(&img);
+
+    encode_png(&processed_img)
 }
 
-// kernel is a small grid or matrix that is used in image processing to apply effects and filters
-// for each filter a different kernel is created
-// f32 is a 32-bit floating point number
-// 3 x 3 matrix => middle pixel is the target pixel and the surrounding pixels are multiplied by the surrounding values
-
-fn apply_emboss(img: &RgbaImage) -> RgbaImage {
-    let kernel: [[f32; 3]; 3] = [
-        [-2.0, -1.0, 0.0],
-        [-1.0,  1.0, 1.0],
-        [ 0.0,  1.0, 2.0],
-    ];
-    apply_convolution(img, &kernel)
+// The Rust version of apply_pipeline
+pub fn try_apply_pipeline(img_data: &[u8], pipeline: &Pipeline) -> Result<Vec<u8>, FilterError> {
+    let encoded = try_apply_pipeline_with_output(img_data, pipeline, &OutputOptions::default())?;
+    Ok(encoded.into_bytes())
 }
 
-fn apply_sharpen(img: &RgbaImage) -> RgbaImage {
-    let kernel: [[f32; 3]; 3] = [
-        [ 0.0, -1.0,  0.0],
-        [-1.0,  5.0, -1.0],
-        [ 0.0, -1.0,  0.0],
-    ];
-    apply_convolution(img, &kernel)
+// The Rust version of apply_pipeline_with_output
+pub fn try_apply_pipeline_with_output(img_data: &[u8], pipeline: &Pipeline, options: &OutputOptions) -> Result<EncodedImage, FilterError> {
+    // Same idea as try_apply_spec: set every stage up (and check the options) before decoding
+    let stages = pipeline.build()?;
+    options.validate()?;
+
+    let (img, input_format) = decode_image(img_data)?;
+
+    let processed_img = run_stages(&stages, img);
+
+    output::encode(&processed_img, options, Some(input_format))
+}
+
+// Runs a single filter on an already decoded image
+pub fn apply_spec(img: &RgbaImage, spec: &FilterSpec) -> Result<RgbaImage, FilterError> {
+    let filter = spec.build()?;
+    Ok(filter.apply(img))
 }
 
-fn apply_convolution(img: &RgbaImage, kernel: &[[f32; 3]; 3]) -> RgbaImage {
-    // Get the dimensions (width and height) of the input image
-    let (width, height) = img.dimensions();
-    
-    // Create a new image (output buffer) with the same dimensions as the original image
-    let mut output = RgbaImage::new(width, height);
-
-    // Loop over each pixel in the image, except for the edge pixels
-    // (Edge pixels obviously don't have enough neighbors to apply the 3x3 kernel)
-    for y in 1..(height - 1) { // Start at 1 and end at height-1 to avoid edges
-        for x in 1..(width - 1) { // Start at 1 and end at width-1 to avoid edges
-            
-            // Initialize channel values
-            // These will store the sum of the products of the kernel and the surrounding pixel values
-            let mut sum_r = 0.0;
-            let mut sum_g = 0.0;
-            let mut sum_b = 0.0;
-            let mut sum_a = 0.0;
-
-            // Nested loop to go through each value in the 3x3 kernel
-            for ky in 0..3 { // Loop over the kernel rows (0, 1, 2)
-                for kx in 0..3 { // Loop over the kernel columns (0, 1, 2)
-                    
-                    // Get the pixel value from the original image at the corresponding position
-                    // The position is offset by the current kernel position (kx and ky)
-                    let px = img.get_pixel(x + kx as u32 - 1, y + ky as u32 - 1);
-                    
-                    // Multiply each channel (red, green, blue, alpha) of the pixel by the corresponding kernel value
-                    // and add the result to the respective accumulator
-                    sum_r += kernel[ky][kx] * px[0] as f32; // Red channel
-                    sum_g += kernel[ky][kx] * px[1] as f32; // Green channel
-                    sum_b += kernel[ky][kx] * px[2] as f32; // Blue channel
-                    sum_a += kernel[ky][kx] * px[3] as f32; // Alpha channel
-                }
-            }
-
-            // After processing all the surrounding pixels, clamp the resulting values
-            // This ensures the values are within the valid range for image data (0 to 255)
-            // Then cast the values to u8 (8-bit unsigned integers)
-            output.put_pixel(x, y, Rgba([
-                sum_r.clamp(0.0, 255.0) as u8, // Red channel
-                sum_g.clamp(0.0, 255.0) as u8, // Green channel
-                sum_b.clamp(0.0, 255.0) as u8, // Blue channel
-                sum_a.clamp(0.0, 255.0) as u8, // Alpha channel
-            ]));
-        }
+// Works out the format, checks the size and only then decodes the image into RGBA pixels
+// Checking the size first means a huge (or malicious) upload is rejected before we allocate anything
+// The format is handed back too, for writing the result out in the same format
+pub(crate) fn decode_image(img_data: &[u8]) -> Result<(RgbaImage, ImageFormat), FilterError> {
+    // guess_format looks at the first few bytes (the 'magic number') of the file
+    let format: ImageFormat = image::guess_format(img_data)?;
+
+    // Only reads the header, so this is cheap
+    let (width, height) = ImageReader::with_format(Cursor::new(img_data), format).into_dimensions()?;
+    if width as u64 * height as u64 > MAX_IMAGE_PIXELS {
+        return Err(FilterError::ImageTooLarge { width, height, max_pixels: MAX_IMAGE_PIXELS });
     }
 
-    // Return the processed image stored in the output buffer
-    output
+    let img = image::load_from_memory_with_format(img_data, format)?;
+
+    // RGBA8 format is a common format for image processing
+    Ok((img.to_rgba8(), format))
+}
+
+// Encode the processed image as PNG
+fn encode_png(img: &RgbaImage) -> Result<Vec<u8>, FilterError> {
+    Ok(output::encode(img, &OutputOptions::default(), None)?.into_bytes())
 }
 
-fn apply_sepia(img: &RgbaImage) -> RgbaImage {
-    // Create a mutable clone of the original image so that we can modify it
-    let mut sepia_img = img.clone();
-    
-    // Iterate over each pixel in the cloned image
-    for pixel in sepia_img.pixels_mut() {
-        // Extract the red, green, and blue values from the current pixel
-        let red = pixel[0] as f32;
-        let green = pixel[1] as f32;
-        let blue = pixel[2] as f32;
-
-        // Apply the sepia transformation formula to each color channel
-        let tr = (0.393 * red + 0.769 * green + 0.189 * blue).min(255.0) as u8; // New red value
-        let tg = (0.349 * red + 0.686 * green + 0.168 * blue).min(255.0) as u8; // New green value
-        let tb = (0.272 * red + 0.534 * green + 0.131 * blue).min(255.0) as u8; // New blue value
-
-        // Set the pixel's red, green, and blue channels to the new sepia values
-        pixel[0] = tr;
-        pixel[1] = tg;
-        pixel[2] = tb;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png() -> Vec<u8> {
+        let img = RgbaImage::from_pixel(4, 3, image::Rgba([10, 20, 30, 255]));
+        output::encode(&img, &OutputOptions::default(), None).unwrap().into_bytes()
     }
 
-    // Return the sepia-toned image
-    sepia_img
-}
+    #[test]
+    fn decodes() {
+        let (img, format) = decode_image(&png()).unwrap();
+        assert_eq!((img.dimensions(), format), ((4, 3), ImageFormat::Png));
+    }
 
-fn apply_posterize(img: &RgbaImage, levels: u8) -> RgbaImage {
-    // Create a mutable clone of the original image so that we can modify it
-    let mut posterized_img = img.clone();
-    
-    // Calculate the step size based on the number of levels
-    // This determines how much we reduce the color range
-    let step = 255 / (levels - 1);
-    
-    // Iterate over each pixel in the cloned image
-    for pixel in posterized_img.pixels_mut() {
-        // Apply the 'posterization' by reducing the color resolution
-        // The color is taken to the nearest multiple of the step size
-        pixel[0] = (pixel[0] / step) * step; // Posterize red channel
-        pixel[1] = (pixel[1] / step) * step; // Posterize green channel
-        pixel[2] = (pixel[2] / step) * step; // Posterize blue channel
-        // Alpha channel is left unchanged
+    #[test]
+    fn corrupt_upload() {
+        // A PNG whose header (IHDR) has been scribbled over, so its checksum doesn't match
+        let mut bytes = png();
+        bytes[16..24].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef, 0xde, 0xad, 0xbe, 0xef]);
+        assert!(matches!(decode_image(&bytes), Err(FilterError::Decode(_))), "{:?}", decode_image(&bytes).err());
     }
 
-    // Return the posterized image
-    posterized_img
-}
+    #[test]
+    fn unsupported_format() {
+        let error = decode_image(b"just some text, not an image").unwrap_err();
+        assert!(matches!(error, FilterError::UnsupportedFormat(_)), "{:?}", error);
+    }
 
+    #[test]
+    fn too_large() {
+        // Just a PPM header, with none of its 400 million pixels: the size is checked before anything else is read
+        let error = decode_image(b"P6\n20000 20000\n255\n").unwrap_err();
+        assert_eq!(error, FilterError::ImageTooLarge { width: 20000, height: 20000, max_pixels: MAX_IMAGE_PIXELS });
+        // Just under the limit gets past the check, and fails decoding the (missing) pixels instead
+        let error = decode_image(b"P6\n8000 5000\n255\n").unwrap_err();
+        assert!(!matches!(error, FilterError::ImageTooLarge { .. }), "{:?}", error);
+    }
+}
@@ -0,0 +1,381 @@
+use std::sync::{Arc, Mutex};
+
+use image::{Rgba, RgbaImage};
+use wasm_bindgen::prelude::*;
+
+use crate::error::FilterError;
+use crate::filter::Filter;
+use crate::filter_kind::FilterKind;
+use crate::parallel::map_pixels;
+use crate::registry::{register_filter, with_registry};
+use crate::spec::{ParamInfo, Params};
+
+// Colour grading with LUTs ('look up tables'), loaded from the .cube files that Resolve, Premiere,
+// Photoshop etc. export. A LUT says what every colour should turn into:
+//   1D => a separate curve for each of red, green and blue (like Curves)
+//   3D => a grid of colours, N x N x N, so it can do anything (e.g. push only the skin tones orange)
+// Colours that fall between the grid points are 'interpolated' from the points around them
+//
+// From JS:
+//   register_lut("teal_orange", await file.text());
+//   apply_filter_with_options(bytes, '{ "filter": "teal_orange", "params": { "intensity": 60 } }');
+// The LUT becomes a filter like any other, so it works in pipelines, sessions and the CLI (--lut)
+
+// How to work out a colour that falls between the points of a 3D LUT
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LutInterpolation {
+    // Mixes the 8 corners of the cube the colour is in
+    Trilinear,
+    // Splits that cube into 6 tetrahedrons and mixes the 4 corners of the one the colour is in
+    // Cheaper and more accurate along the greys (it's what most grading software uses)
+    #[default]
+    Tetrahedral,
+}
+
+impl LutInterpolation {
+    // The names used by the "interpolation" parameter
+    pub const NAMES: [&'static str; 2] = ["tetrahedral", "trilinear"];
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "trilinear" => LutInterpolation::Trilinear,
+            _ => LutInterpolation::Tetrahedral,
+        }
+    }
+}
+
+// The biggest LUTs we'll read (65 is the biggest 3D size in common use, 1D ones are often 4096)
+const MAX_3D_SIZE: usize = 256;
+const MAX_1D_SIZE: usize = 65536;
+
+// A parsed .cube file
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeLut {
+    title: Option<String>,
+    // true => a 3D LUT, false => 1D
+    is_3d: bool,
+    // The number of points along each side
+    size: usize,
+    // The input values that map to the first and last points (almost always 0 and 1)
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+    // The output colours, 0 - 1
+    // 1D: size entries. 3D: size^3 entries, red changing fastest, then green, then blue
+    table: Vec<[f32; 3]>,
+}
+
+impl CubeLut {
+    // Reads the text of a .cube file
+    pub fn parse(text: &str) -> Result<Self, FilterError> {
+        let mut title = None;
+        let mut size_1d = None;
+        let mut size_3d = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut table = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            // Errors say which line (counting from 1, like an editor) was wrong
+            let error = |reason: String| invalid_lut(format!("line {}: {}", index + 1, reason));
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+            match keyword {
+                "TITLE" => title = Some(rest.trim_matches('"').to_string()),
+                "LUT_1D_SIZE" => size_1d = Some(parse_size(rest, MAX_1D_SIZE).map_err(error)?),
+                "LUT_3D_SIZE" => size_3d = Some(parse_size(rest, MAX_3D_SIZE).map_err(error)?),
+                "DOMAIN_MIN" => domain_min = parse_numbers::<3>(rest).map_err(error)?,
+                "DOMAIN_MAX" => domain_max = parse_numbers::<3>(rest).map_err(error)?,
+                // Resolve's older way of writing the domain, the same for all 3 channels
+                "LUT_1D_INPUT_RANGE" | "LUT_3D_INPUT_RANGE" => {
+                    let [min, max] = parse_numbers::<2>(rest).map_err(error)?;
+                    (domain_min, domain_max) = ([min; 3], [max; 3]);
+                }
+                // Anything starting with a number (including "nan" and "inf", which parse_numbers then rejects) is one of the table's colours
+                _ if keyword.parse::<f32>().is_ok() => table.push(parse_numbers::<3>(line).map_err(error)?),
+                // Any other keyword (e.g. Resolve's LUT_IN_VIDEO_RANGE) would change how the table is read,
+                // so ignoring it would quietly give the wrong colours
+                _ => return Err(error(format!("unknown keyword '{}'", keyword))),
+            }
+        }
+
+        let (is_3d, size) = match (size_1d, size_3d) {
+            (None, Some(size)) => (true, size),
+            (Some(size), None) => (false, size),
+            (None, None) => return Err(invalid_lut("there's no LUT_1D_SIZE or LUT_3D_SIZE line".to_string())),
+            (Some(_), Some(_)) => return Err(invalid_lut("LUTs with both a 1D and a 3D table aren't supported".to_string())),
+        };
+        let expected = if is_3d { size * size * size } else { size };
+        if table.len() != expected {
+            return Err(invalid_lut(format!("a size {} LUT needs {} colours, found {}", size, expected, table.len())));
+        }
+        if let Some(channel) = (0..3).find(|&c| domain_max[c] <= domain_min[c]) {
+            let name = ["red", "green", "blue"][channel];
+            return Err(invalid_lut(format!("DOMAIN_MAX must be above DOMAIN_MIN (for {})", name)));
+        }
+
+        Ok(CubeLut { title, is_3d, size, domain_min, domain_max, table })
+    }
+
+    // The TITLE from the file, if it had one
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub fn is_3d(&self) -> bool {
+        self.is_3d
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // Looks a colour (0 - 1) up, ignoring 'interpolation' for 1D LUTs (each channel is just a straight line between points)
+    pub fn map(&self, rgb: [f32; 3], interpolation: LutInterpolation) -> [f32; 3] {
+        // Where the colour sits along each side of the table, e.g. 0.5 in a size 33 LUT => 16.0
+        let last = (self.size - 1) as f32;
+        let position: [f32; 3] = std::array::from_fn(|c| {
+            let scaled = (rgb[c] - self.domain_min[c]) / (self.domain_max[c] - self.domain_min[c]);
+            scaled.clamp(0.0, 1.0) * last
+        });
+        // The point before each position (never the last one, so there's always one after it),
+        // and how far towards the next one the position is
+        let start = position.map(|p| (p as usize).min(self.size - 2));
+        let fraction: [f32; 3] = std::array::from_fn(|c| position[c] - start[c] as f32);
+
+        if !self.is_3d {
+            return std::array::from_fn(|c| {
+                let (before, after) = (self.table[start[c]][c], self.table[start[c] + 1][c]);
+                before + (after - before) * fraction[c]
+            });
+        }
+
+        // One of the 8 corners of the cube around the colour, e.g. corner(1, 0, 0) is the next point along red
+        let corner = |r: usize, g: usize, b: usize| {
+            self.table[(start[0] + r) + (start[1] + g) * self.size + (start[2] + b) * self.size * self.size]
+        };
+        let [fr, fg, fb] = fraction;
+        match interpolation {
+            LutInterpolation::Trilinear => {
+                // Mix along red, then green, then blue
+                let lerp = |a: [f32; 3], b: [f32; 3], t: f32| std::array::from_fn(|c| a[c] + (b[c] - a[c]) * t);
+                let g0 = lerp(lerp(corner(0, 0, 0), corner(1, 0, 0), fr), lerp(corner(0, 1, 0), corner(1, 1, 0), fr), fg);
+                let g1 = lerp(lerp(corner(0, 0, 1), corner(1, 0, 1), fr), lerp(corner(0, 1, 1), corner(1, 1, 1), fr), fg);
+                lerp(g0, g1, fb)
+            }
+            LutInterpolation::Tetrahedral => {
+                // Which tetrahedron depends on the order of the fractions, e.g. red > green > blue
+                // walks from the first corner along red, then green, then blue to the opposite corner
+                let (first, last) = (corner(0, 0, 0), corner(1, 1, 1));
+                let (weights, second, third) = if fr > fg {
+                    if fg > fb {
+                        ([1.0 - fr, fr - fg, fg - fb, fb], corner(1, 0, 0), corner(1, 1, 0))
+                    } else if fr > fb {
+                        ([1.0 - fr, fr - fb, fb - fg, fg], corner(1, 0, 0), corner(1, 0, 1))
+                    } else {
+                        ([1.0 - fb, fb - fr, fr - fg, fg], corner(0, 0, 1), corner(1, 0, 1))
+                    }
+                } else if fb > fg {
+                    ([1.0 - fb, fb - fg, fg - fr, fr], corner(0, 0, 1), corner(0, 1, 1))
+                } else if fb > fr {
+                    ([1.0 - fg, fg - fb, fb - fr, fr], corner(0, 1, 0), corner(0, 1, 1))
+                } else {
+                    ([1.0 - fg, fg - fr, fr - fb, fb], corner(0, 1, 0), corner(1, 1, 0))
+                };
+                std::array::from_fn(|c| weights[0] * first[c] + weights[1] * second[c] + weights[2] * third[c] + weights[3] * last[c])
+            }
+        }
+    }
+
+    // Grades the image, 'intensity' (0 - 1) mixing between the original (0) and the full look (1)
+    // Alpha is left as it is
+    pub fn apply(&self, img: &RgbaImage, interpolation: LutInterpolation, intensity: f32) -> RgbaImage {
+        map_pixels(img, |_, _, Rgba([r, g, b, a])| {
+            let original = [r, g, b].map(|value| value as f32 / 255.0);
+            let graded = self.map(original, interpolation);
+            let channel = |c: usize| {
+                let mixed = original[c] + (graded[c] - original[c]) * intensity;
+                (mixed * 255.0 + 0.5).clamp(0.0, 255.0) as u8
+            };
+            Rgba([channel(0), channel(1), channel(2), a])
+        })
+    }
+}
+
+fn invalid_lut(reason: String) -> FilterError {
+    FilterError::InvalidLut(reason)
+}
+
+fn parse_size(text: &str, max: usize) -> Result<usize, String> {
+    match text.parse::<usize>() {
+        Ok(size) if (2..=max).contains(&size) => Ok(size),
+        _ => Err(format!("'{}' isn't a size from 2 to {}", text, max)),
+    }
+}
+
+fn parse_numbers<const N: usize>(text: &str) -> Result<[f32; N], String> {
+    let numbers: Vec<f32> = text
+        .split_whitespace()
+        .map(|number| number.parse::<f32>().ok().filter(|value| value.is_finite()))
+        .collect::<Option<_>>()
+        .ok_or_else(|| format!("'{}' isn't {} numbers", text, N))?;
+    numbers.try_into().map_err(|_| format!("expected {} numbers, got '{}'", N, text))
+}
+
+const LUT_PARAMS: [ParamInfo; 2] = [
+    ParamInfo::choice("interpolation", "Interpolation", &LutInterpolation::NAMES, "tetrahedral"),
+    // 0 => the original image, 100 => the full look
+    ParamInfo::number("intensity", "Intensity", 0.0, 100.0, 100.0, 1.0),
+];
+
+// A LUT as a filter, registered under its own name by register_lut
+// Arc => configure() makes a new filter for every use, and they can all share the one table
+#[derive(Debug, Clone)]
+pub struct LutFilter {
+    name: String,
+    label: String,
+    lut: Arc<CubeLut>,
+    pub interpolation: LutInterpolation,
+    // 0 - 1
+    pub intensity: f32,
+}
+
+impl LutFilter {
+    // The label is the LUT's TITLE, or the name if it doesn't have one
+    pub fn new(name: &str, lut: CubeLut) -> Self {
+        let label = lut.title().filter(|title| !title.is_empty()).unwrap_or(name).to_string();
+        LutFilter { name: name.to_string(), label, lut: Arc::new(lut), interpolation: LutInterpolation::default(), intensity: 1.0 }
+    }
+}
+
+impl Filter for LutFilter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn label(&self) -> &str {
+        &self.label
+    }
+
+    fn params(&self) -> &[ParamInfo] {
+        &LUT_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Box<dyn Filter>, FilterError> {
+        Ok(Box::new(LutFilter {
+            interpolation: LutInterpolation::from_name(params.choice("interpolation")?),
+            intensity: (params.number("intensity")? / 100.0) as f32,
+            ..self.clone()
+        }))
+    }
+
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        self.lut.apply(img, self.interpolation, self.intensity)
+    }
+}
+
+// The names registered by register_lut, so a LUT can be replaced by another one but never a filter that isn't a LUT
+static LUT_NAMES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// Reads a .cube file and adds it to the filter registry under 'name'
+// (a LUT registered again under the same name replaces the old one)
+// The CLI and the frontend name LUTs after their file, so without the check below
+// loading a 'sepia.cube' would quietly replace the built-in sepia filter
+#[wasm_bindgen]
+pub fn register_lut(name: &str, cube: &str) -> Result<(), JsError> {
+    Ok(try_register_lut(name, cube)?)
+}
+
+// The Rust version of register_lut
+pub fn try_register_lut(name: &str, cube: &str) -> Result<(), FilterError> {
+    if name.trim().is_empty() {
+        return Err(FilterError::InvalidParameter { name: "name".to_string(), reason: "a LUT needs a name".to_string() });
+    }
+    let mut lut_names = LUT_NAMES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let is_lut = lut_names.iter().any(|existing| existing == name);
+    let is_other_filter = !is_lut && (name.parse::<FilterKind>().is_ok() || with_registry(|registry| registry.get(name).is_ok()));
+    if is_other_filter {
+        let reason = format!("there's already a filter called '{}', give the LUT another name", name);
+        return Err(FilterError::InvalidParameter { name: "name".to_string(), reason });
+    }
+    register_filter(LutFilter::new(name, CubeLut::parse(cube)?));
+    if !is_lut {
+        lut_names.push(name.to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernels::scalar;
+    use crate::spec::FilterSpec;
+
+    // A 2x2x2 LUT that swaps red and blue
+    const SWAP_RED_BLUE: &str = "TITLE \"Swap\"\nLUT_3D_SIZE 2\n0 0 0\n0 0 1\n0 1 0\n0 1 1\n1 0 0\n1 0 1\n1 1 0\n1 1 1\n";
+
+    fn apply(name: &str, pixel: [u8; 4]) -> [u8; 4] {
+        let filter = FilterSpec::new(name).build().expect("a registered filter");
+        filter.apply(&RgbaImage::from_pixel(1, 1, Rgba(pixel))).get_pixel(0, 0).0
+    }
+
+    #[test]
+    fn builtin_names_are_rejected() {
+        for name in ["sepia", "blur", "grayscale"] {
+            let error = try_register_lut(name, SWAP_RED_BLUE).expect_err("a built-in's name");
+            assert!(matches!(error, FilterError::InvalidParameter { .. }), "{}: {}", name, error);
+        }
+        // The built-in is still there and still sepia
+        let mut expected = [200, 100, 50, 255];
+        scalar::sepia_row(&mut expected);
+        assert_eq!(apply("sepia", [200, 100, 50, 255]), expected);
+        assert_eq!(with_registry(|registry| registry.get("sepia").map(|filter| filter.label().to_string())).unwrap(), "Sepia");
+    }
+
+    #[test]
+    fn luts_replace_luts() {
+        try_register_lut("test_swap", SWAP_RED_BLUE).expect("a new name");
+        assert_eq!(apply("test_swap", [200, 100, 50, 255]), [50, 100, 200, 255]);
+        // Loading the same file again (or a new version of it) is fine
+        let identity = SWAP_RED_BLUE.replace("0 0 1\n0 1 0\n0 1 1\n1 0 0\n1 0 1\n1 1 0", "1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1");
+        try_register_lut("test_swap", &identity).expect("a LUT's name");
+        assert_eq!(apply("test_swap", [200, 100, 50, 255]), [200, 100, 50, 255]);
+    }
+
+    fn lut_error(text: &str) -> String {
+        match CubeLut::parse(text) {
+            Err(FilterError::InvalidLut(reason)) => reason,
+            other => panic!("expected InvalidLut, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn keywords() {
+        let lut = CubeLut::parse(SWAP_RED_BLUE).unwrap();
+        assert_eq!((lut.title(), lut.is_3d(), lut.size()), (Some("Swap"), true, 2));
+
+        // Comments, blank lines and every keyword we know
+        let text = "# made by hand\n\nTITLE \"Ramp\"\nLUT_1D_SIZE 2\nDOMAIN_MIN 0 0 0\nDOMAIN_MAX 2 2 2\n  \n0 0 0\n1 1 1\n";
+        let lut = CubeLut::parse(text).unwrap();
+        assert_eq!((lut.title(), lut.is_3d(), lut.size()), (Some("Ramp"), false, 2));
+        assert_eq!(lut.map([1.0, 0.5, 2.0], LutInterpolation::Tetrahedral), [0.5, 0.25, 1.0]);
+        let resolve = "LUT_1D_INPUT_RANGE 0 2\nLUT_1D_SIZE 2\n0 0 0\n1 1 1\n";
+        assert_eq!(CubeLut::parse(resolve).unwrap().map([1.0, 0.5, 2.0], LutInterpolation::Tetrahedral), [0.5, 0.25, 1.0]);
+    }
+
+    // Keywords we don't know, and rows that aren't 3 finite numbers, are errors that say which line they're on
+    #[test]
+    fn bad_lines() {
+        let with_line = |line: &str| format!("TITLE \"Bad\"\nLUT_1D_SIZE 2\n0 0 0\n{}\n1 1 1\n", line);
+        for line in ["LUT_IN_VIDEO_RANGE", "nan 0 0", "0 inf 0", "-inf 0 0", "NaN NaN NaN", "0 0", "0 0 0 0", "0 0 x", "SIZE 2"] {
+            let reason = lut_error(&with_line(line));
+            assert!(reason.starts_with("line 4: "), "{}: {}", line, reason);
+        }
+        assert!(lut_error(&with_line("LUT_IN_VIDEO_RANGE")).contains("unknown keyword 'LUT_IN_VIDEO_RANGE'"));
+        assert!(lut_error("LUT_3D_SIZE 2\nDOMAIN_MIN 0 nan 0\n").starts_with("line 2: "));
+    }
+}
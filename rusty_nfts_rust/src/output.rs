@@ -0,0 +1,288 @@
+use std::io::Cursor;
+use std::str::FromStr;
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageFormat, RgbImage, RgbaImage, Rgb};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::error::FilterError;
+
+// The formats we can write the filtered image out as
+// In JSON these are lowercase, e.g. "jpeg" or "same_as_input"
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    Jpeg,
+    // Lossless WebP (the pure Rust encoder can't do lossy)
+    #[serde(rename = "webp")]
+    WebP,
+    Bmp,
+    Gif,
+    Tiff,
+    // Whatever format the upload was in (falls back to PNG if we can't write that format)
+    SameAsInput,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 7] = [
+        OutputFormat::Png,
+        OutputFormat::Jpeg,
+        OutputFormat::WebP,
+        OutputFormat::Bmp,
+        OutputFormat::Gif,
+        OutputFormat::Tiff,
+        OutputFormat::SameAsInput,
+    ];
+
+    // The name used in JSON (same as the serde name above)
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpeg",
+            OutputFormat::WebP => "webp",
+            OutputFormat::Bmp => "bmp",
+            OutputFormat::Gif => "gif",
+            OutputFormat::Tiff => "tiff",
+            OutputFormat::SameAsInput => "same_as_input",
+        }
+    }
+
+    // Works out the actual image crate format to write, given the format of the upload
+    fn resolve(self, input_format: Option<ImageFormat>) -> ImageFormat {
+        match self {
+            OutputFormat::Png => ImageFormat::Png,
+            OutputFormat::Jpeg => ImageFormat::Jpeg,
+            OutputFormat::WebP => ImageFormat::WebP,
+            OutputFormat::Bmp => ImageFormat::Bmp,
+            OutputFormat::Gif => ImageFormat::Gif,
+            OutputFormat::Tiff => ImageFormat::Tiff,
+            OutputFormat::SameAsInput => match input_format {
+                // Only the formats above can be written, anything else (ico, tga, ...) becomes PNG
+                Some(format @ (ImageFormat::Png
+                | ImageFormat::Jpeg
+                | ImageFormat::WebP
+                | ImageFormat::Bmp
+                | ImageFormat::Gif
+                | ImageFormat::Tiff)) => format,
+                _ => ImageFormat::Png,
+            },
+        }
+    }
+}
+
+// FromStr => lets us do "jpeg".parse::<OutputFormat>() (e.g. for a command line option)
+impl FromStr for OutputFormat {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OutputFormat::ALL.iter().copied().find(|format| format.name() == s).ok_or_else(|| {
+            let names: Vec<&str> = OutputFormat::ALL.iter().map(|format| format.name()).collect();
+            FilterError::InvalidParameter {
+                name: "format".to_string(),
+                reason: format!("'{}' is not one of {}", s, names.join(", ")),
+            }
+        })
+    }
+}
+
+fn default_quality() -> u8 {
+    90
+}
+
+// How the filtered image should be written out
+// In JSON it looks like: { "format": "jpeg", "quality": 85 } (both are optional)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputOptions {
+    #[serde(default)]
+    pub format: OutputFormat,
+    // JPEG quality from 1 (tiny and ugly) to 100 (big and pretty), ignored by the other formats
+    #[serde(default = "default_quality")]
+    pub quality: u8,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        OutputOptions { format: OutputFormat::default(), quality: default_quality() }
+    }
+}
+
+impl OutputOptions {
+    pub fn new(format: OutputFormat) -> Self {
+        OutputOptions { format, ..OutputOptions::default() }
+    }
+
+    pub fn with_quality(mut self, quality: u8) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, FilterError> {
+        let options: OutputOptions = serde_json::from_str(json).map_err(|e| FilterError::InvalidParameter {
+            name: "output".to_string(),
+            reason: e.to_string(),
+        })?;
+        options.validate()?;
+        Ok(options)
+    }
+
+    pub fn validate(&self) -> Result<(), FilterError> {
+        if !(1..=100).contains(&self.quality) {
+            return Err(FilterError::InvalidParameter {
+                name: "quality".to_string(),
+                reason: format!("{} is outside the range 1 to 100", self.quality),
+            });
+        }
+        Ok(())
+    }
+}
+
+// The encoded bytes plus what they are, so the frontend doesn't have to guess the Blob type
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodedImage {
+    bytes: Vec<u8>,
+    format: ImageFormat,
+}
+
+#[wasm_bindgen]
+impl EncodedImage {
+    // A copy of the bytes (JavaScript gets a Uint8Array)
+    #[wasm_bindgen(getter)]
+    pub fn bytes(&self) -> Vec<u8> {
+        self.bytes.clone()
+    }
+
+    // e.g. "image/jpeg", ready to use as a Blob type
+    #[wasm_bindgen(getter, js_name = mimeType)]
+    pub fn mime_type(&self) -> String {
+        self.format.to_mime_type().to_string()
+    }
+
+    // e.g. "jpg", for naming the downloaded file
+    #[wasm_bindgen(getter)]
+    pub fn extension(&self) -> String {
+        self.format.extensions_str()[0].to_string()
+    }
+}
+
+impl EncodedImage {
+    pub fn format(&self) -> ImageFormat {
+        self.format
+    }
+
+    // Takes the bytes without copying them
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+// Writes the image in the requested format
+// input_format is the format of the upload (only used by SameAsInput)
+pub fn encode(img: &RgbaImage, options: &OutputOptions, input_format: Option<ImageFormat>) -> Result<EncodedImage, FilterError> {
+    options.validate()?;
+    let format = options.format.resolve(input_format);
+
+    let mut bytes = Vec::new();
+    // Cursor is a type that allows you to write to a buffer as if it were a file
+    let mut cursor = Cursor::new(&mut bytes);
+
+    let result = match format {
+        // JPEG has no alpha channel, so flatten the image onto white first
+        // (otherwise transparent areas come out as whatever colour happens to be hiding under them)
+        ImageFormat::Jpeg => {
+            let encoder = JpegEncoder::new_with_quality(&mut cursor, options.quality);
+            flatten_onto_white(img).write_with_encoder(encoder)
+        }
+        // Everything else can take RGBA as it is
+        other => DynamicImage::ImageRgba8(img.clone()).write_to(&mut cursor, other),
+    };
+    result.map_err(|e| FilterError::Encode(e.to_string()))?;
+
+    Ok(EncodedImage { bytes, format })
+}
+
+fn flatten_onto_white(img: &RgbaImage) -> RgbImage {
+    RgbImage::from_fn(img.width(), img.height(), |x, y| {
+        let pixel = img.get_pixel(x, y);
+        let alpha = pixel[3] as f32 / 255.0;
+        // Mix each channel with white (255) based on how opaque the pixel is
+        let blend = |channel: u8| (channel as f32 * alpha + 255.0 * (1.0 - alpha)).round() as u8;
+        Rgb([blend(pixel[0]), blend(pixel[1]), blend(pixel[2])])
+    })
+}
+
+// The names of every output format, for the frontend's format <select>
+#[wasm_bindgen]
+pub fn list_output_formats() -> Vec<String> {
+    OutputFormat::ALL.iter().map(|format| format.name().to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn same_as_input() {
+        for format in [ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::WebP, ImageFormat::Bmp, ImageFormat::Gif, ImageFormat::Tiff] {
+            assert_eq!(OutputFormat::SameAsInput.resolve(Some(format)), format);
+        }
+        // Formats we can read but not write, or no input at all => PNG
+        for input in [Some(ImageFormat::Ico), Some(ImageFormat::Tga), Some(ImageFormat::Qoi), None] {
+            assert_eq!(OutputFormat::SameAsInput.resolve(input), ImageFormat::Png);
+        }
+        // The others ignore the input
+        assert_eq!(OutputFormat::Bmp.resolve(Some(ImageFormat::Jpeg)), ImageFormat::Bmp);
+    }
+
+    #[test]
+    fn mime_types_and_extensions() {
+        let img = RgbaImage::from_pixel(3, 2, Rgba([200, 100, 50, 255]));
+        let expected = [
+            (OutputFormat::Png, "image/png", "png"),
+            (OutputFormat::Jpeg, "image/jpeg", "jpg"),
+            (OutputFormat::WebP, "image/webp", "webp"),
+            (OutputFormat::Bmp, "image/bmp", "bmp"),
+            (OutputFormat::Gif, "image/gif", "gif"),
+            (OutputFormat::Tiff, "image/tiff", "tiff"),
+        ];
+        for (format, mime_type, extension) in expected {
+            let encoded = encode(&img, &OutputOptions::new(format), None).unwrap();
+            assert_eq!((encoded.mime_type().as_str(), encoded.extension().as_str()), (mime_type, extension), "{}", format.name());
+            // And it really is that format
+            assert_eq!(image::guess_format(&encoded.bytes()).unwrap(), encoded.format());
+        }
+    }
+
+    #[test]
+    fn quality_range() {
+        let img = RgbaImage::new(1, 1);
+        for quality in [0, 101] {
+            let options = OutputOptions::new(OutputFormat::Jpeg).with_quality(quality);
+            assert!(matches!(encode(&img, &options, None), Err(FilterError::InvalidParameter { .. })), "{}", quality);
+            let json = format!(r#"{{ "format": "jpeg", "quality": {} }}"#, quality);
+            assert!(matches!(OutputOptions::from_json(&json), Err(FilterError::InvalidParameter { .. })), "{}", quality);
+        }
+        for quality in [1, 100] {
+            assert!(encode(&img, &OutputOptions::new(OutputFormat::Jpeg).with_quality(quality), None).is_ok());
+        }
+        assert_eq!(OutputOptions::from_json("{}"), Ok(OutputOptions::default()));
+    }
+
+    #[test]
+    fn jpeg_flattens_onto_white() {
+        let img = RgbaImage::from_fn(16, 16, |x, _| if x < 8 { Rgba([0, 0, 0, 0]) } else { Rgba([255, 0, 0, 128]) });
+        let flat = flatten_onto_white(&img);
+        assert_eq!(flat.get_pixel(0, 0).0, [255, 255, 255]);
+        assert_eq!(flat.get_pixel(15, 0).0, [255, 127, 127]);
+
+        // Through the real encoder too (JPEG is lossy, so only roughly, away from the edge between the halves)
+        let encoded = encode(&img, &OutputOptions::new(OutputFormat::Jpeg).with_quality(100), None).unwrap();
+        let decoded = image::load_from_memory(&encoded.bytes()).unwrap().to_rgb8();
+        let close = |a: [u8; 3], b: [u8; 3]| a.iter().zip(b).all(|(&a, b)| a.abs_diff(b) <= 4);
+        assert!(close(decoded.get_pixel(2, 8).0, [255, 255, 255]), "{:?}", decoded.get_pixel(2, 8));
+        assert!(close(decoded.get_pixel(13, 8).0, [255, 127, 127]), "{:?}", decoded.get_pixel(13, 8));
+    }
+}
@@ -0,0 +1,93 @@
+use image::{Rgba, RgbaImage};
+
+// Helpers for splitting filter work up by rows
+//
+// With the "parallel" feature the rows are shared out across every CPU core (using rayon),
+// without it they just run one after the other. Either way every row is worked out on its own,
+// exactly the same way, so the result is identical (bit for bit) whichever way it was run
+
+// Calls 'f' with the index and contents of every chunk of 'chunk_len' values in 'buffer'
+// (the last chunk may be shorter), e.g. one row of an image at a time
+// 'Sync' => 'f' is shared between threads, so it can't hold on to anything that isn't thread safe
+#[cfg(feature = "parallel")]
+pub(crate) fn for_each_chunk<T: Send>(buffer: &mut [T], chunk_len: usize, f: impl Fn(usize, &mut [T]) + Sync) {
+    use rayon::prelude::*;
+    buffer.par_chunks_mut(chunk_len.max(1)).enumerate().for_each(|(index, chunk)| f(index, chunk));
+}
+
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn for_each_chunk<T: Send>(buffer: &mut [T], chunk_len: usize, f: impl Fn(usize, &mut [T]) + Sync) {
+    buffer.chunks_mut(chunk_len.max(1)).enumerate().for_each(|(index, chunk)| f(index, chunk));
+}
+
+// A new image of the given size, filled in a row at a time
+// 'f' gets the row number and that row's raw RGBA bytes (width * 4 of them)
+pub(crate) fn build_rows(width: u32, height: u32, f: impl Fn(u32, &mut [u8]) + Sync) -> RgbaImage {
+    let mut img = RgbaImage::new(width, height);
+    for_each_chunk(&mut img, width as usize * 4, |y, row| f(y as u32, row));
+    img
+}
+
+// A copy of the image with every pixel passed through 'f' (which also gets the pixel's x and y)
+pub(crate) fn map_pixels(img: &RgbaImage, f: impl Fn(u32, u32, Rgba<u8>) -> Rgba<u8> + Sync) -> RgbaImage {
+    let mut output = img.clone();
+    for_each_chunk(&mut output, img.width() as usize * 4, |y, row| {
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let Rgba(new) = f(x as u32, y as u32, Rgba([pixel[0], pixel[1], pixel[2], pixel[3]]));
+            pixel.copy_from_slice(&new);
+        }
+    });
+    output
+}
+
+// The parallel build has to give exactly the same bytes as the serial one
+// Both run in the same test binary: the serial run uses a pool with a single thread (so every row is done one after
+// the other on that thread), the parallel run a pool with a few threads, so the rows really are shared out
+// even on a machine with one core
+#[cfg(all(test, feature = "parallel"))]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use crate::filter_kind::FilterKind;
+    use crate::spec::FilterSpec;
+
+    // Tall enough for several bands of box blur rows (BOX_BAND_ROWS) and pixelate blocks,
+    // with an odd width and some (half and fully) transparent pixels
+    fn pattern() -> RgbaImage {
+        RgbaImage::from_fn(101, 150, |x, y| {
+            let alpha = [255, 0, 128, 255, 30][((x + 2 * y) % 5) as usize];
+            Rgba([(x * 37 + y * 11) as u8, (x * 5 + y * 71 + 40) as u8, (200 + x * y * 13) as u8, alpha])
+        })
+    }
+
+    fn run(filter: &FilterSpec, img: &RgbaImage, threads: usize) -> RgbaImage {
+        let filter = filter.build().expect("a valid spec");
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().expect("a thread pool");
+        pool.install(|| filter.apply(img))
+    }
+
+    #[test]
+    fn parallel_matches_serial() {
+        let img = pattern();
+        // Every built-in filter with its defaults, then the other blur and convolution paths
+        let mut specs: Vec<FilterSpec> = FilterKind::ALL.iter().map(|&kind| FilterSpec::new(kind)).collect();
+        specs.extend([
+            FilterSpec::new("blur").with("method", "gaussian").with("sigma", 3.0),
+            FilterSpec::new("blur").with("method", "box").with("sigma", 12.0),
+            FilterSpec::new("blur").with("sigma", 20.0).with("alpha", "preserve"),
+            FilterSpec::new("convolve").with("kernel", serde_json::json!([[1, 2, 3, 2, 1], [0, 1, 0, 1, 0], [-1, 0, 4, 0, -1]])),
+            FilterSpec::new("emboss").with("border", "wrap"),
+            FilterSpec::new("sharpen").with("border", "crop"),
+            FilterSpec::new("grayscale").with("method", "lightness"),
+            FilterSpec::new("posterize").with("dither", "ordered"),
+            FilterSpec::new("morphology").with("shape", "square").with("radius", 5),
+            FilterSpec::new("morphology").with("operation", "close").with("mode", "luminance").with("border", "wrap"),
+        ]);
+        for spec in &specs {
+            let expected = run(spec, &img, 1);
+            let output = run(spec, &img, 4);
+            assert_eq!(output.dimensions(), expected.dimensions(), "{}", spec.to_json());
+            assert!(output.as_raw() == expected.as_raw(), "{} differs when run in parallel", spec.to_json());
+        }
+    }
+}
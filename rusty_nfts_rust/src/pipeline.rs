@@ -0,0 +1,111 @@
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+use crate::error::FilterError;
+use crate::filter::Filter;
+use crate::spec::FilterSpec;
+
+// An ordered list of filters, e.g. sepia => sharpen => posterize
+// The image is decoded once, every stage works on the in-memory pixels, and it's encoded once at the end
+// (instead of a PNG encode + decode between every filter)
+// In JSON it looks like: { "stages": [ { "filter": "sepia" }, { "filter": "posterize", "params": { "levels": 3 } } ] }
+// so it can be saved and replayed later
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pipeline {
+    pub stages: Vec<FilterSpec>,
+}
+
+impl Pipeline {
+    // An empty pipeline (which leaves the image as it is)
+    pub fn new() -> Self {
+        Pipeline::default()
+    }
+
+    // Builder style, e.g. Pipeline::new().then(FilterKind::Sepia).then(FilterKind::Sharpen)
+    // 'impl Into<FilterSpec>' => takes either a full FilterSpec or just a FilterKind
+    pub fn then(mut self, stage: impl Into<FilterSpec>) -> Self {
+        self.stages.push(stage.into());
+        self
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, FilterError> {
+        serde_json::from_str(json).map_err(|e| FilterError::InvalidParameter {
+            name: "pipeline".to_string(),
+            reason: e.to_string(),
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Pipeline is always serialisable")
+    }
+
+    // Looks up and configures every stage up front, so a bad last stage doesn't waste the work of the earlier ones
+    // The parameter name in the error says which stage it was, e.g. "stages[2].levels"
+    pub fn build(&self) -> Result<Vec<Box<dyn Filter>>, FilterError> {
+        self.stages
+            .iter()
+            .enumerate()
+            .map(|(index, stage)| {
+                stage.build().map_err(|err| match err {
+                    FilterError::InvalidParameter { name, reason } => FilterError::InvalidParameter {
+                        name: format!("stages[{}].{}", index, name),
+                        reason,
+                    },
+                    other => other,
+                })
+            })
+            // collect() on an iterator of Results stops at the first error
+            .collect()
+    }
+
+    pub fn validate(&self) -> Result<(), FilterError> {
+        self.build().map(|_| ())
+    }
+
+    // Runs every stage, in order, on an already decoded image
+    pub fn apply(&self, img: &RgbaImage) -> Result<RgbaImage, FilterError> {
+        let stages = self.build()?;
+        Ok(run_stages(&stages, img.clone()))
+    }
+}
+
+// Runs already built stages in order, each one taking the output of the one before it
+pub(crate) fn run_stages(stages: &[Box<dyn Filter>], img: RgbaImage) -> RgbaImage {
+    let mut current = img;
+    for stage in stages {
+        current = stage.apply(&current);
+    }
+    current
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter_kind::FilterKind;
+
+    #[test]
+    fn json_round_trip() {
+        let pipeline = Pipeline::new()
+            .then(FilterKind::Sepia)
+            .then(FilterSpec::new("posterize").with("levels", 3).with("dither", "ordered"))
+            .then(FilterSpec::new("convolve").with("kernel", serde_json::json!([[0, -1, 0], [-1, 5, -1], [0, -1, 0]])));
+        let json = pipeline.to_json();
+        assert_eq!(Pipeline::from_json(&json), Ok(pipeline));
+        // Stages without params leave "params" out, and come back with none
+        assert!(json.starts_with(r#"{"stages":[{"filter":"sepia"},"#), "{}", json);
+        assert_eq!(Pipeline::from_json(r#"{ "stages": [] }"#), Ok(Pipeline::new()));
+        assert!(matches!(Pipeline::from_json(r#"{ "stages": [{ "params": {} }] }"#), Err(FilterError::InvalidParameter { .. })));
+    }
+
+    #[test]
+    fn errors_name_the_stage() {
+        let pipeline = Pipeline::new().then(FilterKind::Sepia).then(FilterKind::Invert).then(FilterSpec::new("posterize").with("levels", 1));
+        match pipeline.validate() {
+            Err(FilterError::InvalidParameter { name, .. }) => assert_eq!(name, "stages[2].levels"),
+            other => panic!("expected an InvalidParameter, got {:?}", other.err()),
+        }
+        // Other errors are passed on as they are
+        let pipeline = Pipeline::new().then(FilterKind::Sepia).then(FilterSpec::new("sephia"));
+        assert_eq!(pipeline.validate().err(), Some(FilterError::UnknownFilter("sephia".to_string())));
+    }
+}
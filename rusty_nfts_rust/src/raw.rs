@@ -0,0 +1,184 @@
+use image::RgbaImage;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::Clamped;
+
+use crate::error::FilterError;
+use crate::pipeline::{run_stages, Pipeline};
+use crate::spec::FilterSpec;
+use crate::MAX_IMAGE_PIXELS;
+
+// These work on raw RGBA pixels (4 bytes per pixel, row by row) instead of encoded image files
+// That's exactly the layout of a canvas ImageData, so a live preview can skip decoding and
+// encoding entirely and just hand its pixels straight to Rust and back
+
+// Filters the pixels in place with a single JSON FilterSpec
+// From JS: apply_filter_rgba(imageData.width, imageData.height, new Uint8Array(imageData.data.buffer), spec)
+// wasm-bindgen copies the changed pixels back into the array when the call returns
+#[wasm_bindgen]
+pub fn apply_filter_rgba(width: u32, height: u32, pixels: &mut [u8], spec_json: &str) -> Result<(), JsError> {
+    let spec = FilterSpec::from_json(spec_json)?;
+    let pipeline = Pipeline::new().then(spec);
+    Ok(filter_rgba_in_place(width, height, pixels, &pipeline)?)
+}
+
+// Same as apply_filter_rgba, but with a whole JSON pipeline
+#[wasm_bindgen]
+pub fn apply_pipeline_rgba(width: u32, height: u32, pixels: &mut [u8], pipeline_json: &str) -> Result<(), JsError> {
+    let pipeline = Pipeline::from_json(pipeline_json)?;
+    Ok(filter_rgba_in_place(width, height, pixels, &pipeline)?)
+}
+
+// Takes the pixels of an ImageData and returns new ones, ready for 'new ImageData(result, width, height)'
+// Clamped => JS sees a Uint8ClampedArray, which is what ImageData uses
+#[wasm_bindgen]
+pub fn apply_pipeline_image_data(width: u32, height: u32, pixels: Clamped<Vec<u8>>, pipeline_json: &str) -> Result<Clamped<Vec<u8>>, JsError> {
+    let pipeline = Pipeline::from_json(pipeline_json)?;
+    // .0 => the Vec inside the Clamped wrapper
+    Ok(Clamped(filter_rgba(width, height, pixels.0, &pipeline)?))
+}
+
+// The Rust version of apply_pipeline_rgba
+pub fn filter_rgba_in_place(width: u32, height: u32, pixels: &mut [u8], pipeline: &Pipeline) -> Result<(), FilterError> {
+    let filtered = filter_rgba(width, height, pixels.to_vec(), pipeline)?;
+    pixels.copy_from_slice(&filtered);
+    Ok(())
+}
+
+// The Rust version of apply_pipeline_image_data
+// The filtered image always has the same size as the input (the pixels are written back into the same buffer)
+pub fn filter_rgba(width: u32, height: u32, pixels: Vec<u8>, pipeline: &Pipeline) -> Result<Vec<u8>, FilterError> {
+    let stages = pipeline.build()?;
+    let img = rgba_image_from_raw(width, height, pixels)?;
+
+    let processed_img = run_stages(&stages, img);
+
+    if processed_img.dimensions() != (width, height) {
+        return Err(FilterError::InvalidParameter {
+            name: "pixels".to_string(),
+            reason: format!(
+                "the filters changed the image size to {}x{}, raw buffers must stay {}x{}",
+                processed_img.width(),
+                processed_img.height(),
+                width,
+                height
+            ),
+        });
+    }
+
+    Ok(processed_img.into_raw())
+}
+
+// Raw RGBA pixels together with their size, for when the size can change (e.g. a Crop border)
+// From JS: new ImageData(result.pixels, result.width, result.height)
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaPixels {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl RgbaPixels {
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // A copy of the pixels (JavaScript gets a Uint8ClampedArray, what ImageData wants)
+    #[wasm_bindgen(getter)]
+    pub fn pixels(&self) -> Clamped<Vec<u8>> {
+        Clamped(self.pixels.clone())
+    }
+}
+
+impl RgbaPixels {
+    // Takes the pixels without copying them
+    pub fn into_image(self) -> RgbaImage {
+        RgbaImage::from_raw(self.width, self.height, self.pixels).expect("RgbaPixels always has width * height * 4 bytes")
+    }
+}
+
+impl From<RgbaImage> for RgbaPixels {
+    fn from(img: RgbaImage) -> Self {
+        RgbaPixels { width: img.width(), height: img.height(), pixels: img.into_raw() }
+    }
+}
+
+// Wraps raw pixels up as an RgbaImage, checking the buffer is the right length first
+pub fn rgba_image_from_raw(width: u32, height: u32, pixels: Vec<u8>) -> Result<RgbaImage, FilterError> {
+    if width as u64 * height as u64 > MAX_IMAGE_PIXELS {
+        return Err(FilterError::ImageTooLarge { width, height, max_pixels: MAX_IMAGE_PIXELS });
+    }
+
+    // 4 bytes per pixel => R, G, B, A
+    // (the size check above keeps this under 160 million, so it can't overflow even with wasm's 32 bit usize)
+    let expected = width as usize * height as usize * 4;
+    if pixels.len() != expected {
+        return Err(FilterError::InvalidParameter {
+            name: "pixels".to_string(),
+            reason: format!(
+                "expected {} bytes for a {}x{} RGBA image, got {}",
+                expected,
+                width,
+                height,
+                pixels.len()
+            ),
+        });
+    }
+
+    // from_raw only fails if the length is wrong, which we've just checked
+    Ok(RgbaImage::from_raw(width, height, pixels).expect("buffer length was checked above"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter_kind::FilterKind;
+
+    fn is_invalid_pixels(result: Result<RgbaImage, FilterError>) -> bool {
+        matches!(result, Err(FilterError::InvalidParameter { name, .. }) if name == "pixels")
+    }
+
+    #[test]
+    fn buffer_length() {
+        assert!(rgba_image_from_raw(3, 2, vec![7; 24]).is_ok());
+        assert!(is_invalid_pixels(rgba_image_from_raw(3, 2, vec![7; 23])));
+        assert!(is_invalid_pixels(rgba_image_from_raw(3, 2, vec![7; 25])));
+        assert!(is_invalid_pixels(rgba_image_from_raw(3, 2, vec![])));
+        assert!(rgba_image_from_raw(0, 0, vec![]).is_ok());
+    }
+
+    #[test]
+    fn huge_dimensions() {
+        // width * height * 4 would overflow a 32 bit usize (or even a u64 for u32::MAX squared * 4),
+        // the pixel limit has to catch it first
+        for (width, height) in [(u32::MAX, u32::MAX), (65536, 65536), (u32::MAX, 2)] {
+            assert_eq!(
+                rgba_image_from_raw(width, height, vec![0; 16]).unwrap_err(),
+                FilterError::ImageTooLarge { width, height, max_pixels: MAX_IMAGE_PIXELS }
+            );
+        }
+    }
+
+    #[test]
+    fn in_place() {
+        let mut pixels = vec![10, 20, 30, 40, 200, 100, 0, 255];
+        filter_rgba_in_place(2, 1, &mut pixels, &Pipeline::new().then(FilterKind::Invert)).unwrap();
+        assert_eq!(pixels, [245, 235, 225, 40, 55, 155, 255, 255]);
+        // A short buffer is left alone
+        let mut short = vec![1; 7];
+        assert!(filter_rgba_in_place(2, 1, &mut short, &Pipeline::new().then(FilterKind::Invert)).is_err());
+        assert_eq!(short, [1; 7]);
+        // And so is one whose size the filters would change
+        let mut pixels = vec![9; 5 * 5 * 4];
+        let crop = Pipeline::new().then(FilterSpec::new("sharpen").with("border", "crop"));
+        assert!(matches!(filter_rgba_in_place(5, 5, &mut pixels, &crop), Err(FilterError::InvalidParameter { .. })));
+        assert_eq!(pixels, [9; 5 * 5 * 4]);
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::{OnceLock, RwLock};
+use wasm_bindgen::prelude::*;
+
+use crate::error::FilterError;
+use crate::filter::Filter;
+use crate::filters;
+use crate::spec::{FilterSpec, Params};
+
+// Every filter we know about, looked up by name
+// A Vec (rather than a HashMap) keeps them in the order they were registered, which is the order
+// the frontend shows them in. There are only a handful, so a linear search is plenty fast
+#[derive(Default)]
+pub struct FilterRegistry {
+    filters: Vec<Box<dyn Filter>>,
+}
+
+impl FilterRegistry {
+    // An empty registry
+    pub fn new() -> Self {
+        FilterRegistry::default()
+    }
+
+    // A registry holding the built-in filters (grayscale, blur, sepia, ...)
+    pub fn with_builtins() -> Self {
+        let mut registry = FilterRegistry::new();
+        filters::register_builtins(&mut registry);
+        registry
+    }
+
+    // Adds a filter. If one with the same name already exists it is replaced (keeping its position),
+    // so a downstream crate can swap out a built-in filter for its own version
+    pub fn register(&mut self, filter: impl Filter + 'static) {
+        let filter: Box<dyn Filter> = Box::new(filter);
+        match self.filters.iter().position(|existing| existing.name() == filter.name()) {
+            Some(index) => self.filters[index] = filter,
+            None => self.filters.push(filter),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Result<&dyn Filter, FilterError> {
+        self.filters
+            .iter()
+            .find(|filter| filter.name() == name)
+            .map(|filter| filter.as_ref())
+            .ok_or_else(|| FilterError::UnknownFilter(name.to_string()))
+    }
+
+    // Every registered filter, in order
+    pub fn filters(&self) -> impl Iterator<Item = &dyn Filter> {
+        self.filters.iter().map(|filter| filter.as_ref())
+    }
+
+    // Finds the filter named in the spec and configures it with the spec's parameters
+    pub fn build(&self, spec: &FilterSpec) -> Result<Box<dyn Filter>, FilterError> {
+        let filter = self.get(&spec.filter)?;
+        let params = Params::new(filter.name(), filter.params(), &spec.params)?;
+        filter.configure(&params)
+    }
+}
+
+// The global registry, created (with the built-ins) the first time it's used
+// OnceLock => initialised exactly once, RwLock => many readers or one writer at a time
+static REGISTRY: OnceLock<RwLock<FilterRegistry>> = OnceLock::new();
+
+fn global() -> &'static RwLock<FilterRegistry> {
+    REGISTRY.get_or_init(|| RwLock::new(FilterRegistry::with_builtins()))
+}
+
+// Adds a filter to the global registry, so it can be used by name everywhere
+// (FilterSpecs, pipelines, apply_filter and the frontend's list_filters)
+// Call this at startup, before filtering anything
+pub fn register_filter(filter: impl Filter + 'static) {
+    // A panic while holding the lock 'poisons' it. The registry is still fine to use, so carry on
+    let mut registry = global().write().unwrap_or_else(|poisoned| poisoned.into_inner());
+    registry.register(filter);
+}
+
+// Gives the closure read access to the global registry
+pub fn with_registry<R>(f: impl FnOnce(&FilterRegistry) -> R) -> R {
+    let registry = global().read().unwrap_or_else(|poisoned| poisoned.into_inner());
+    f(&registry)
+}
+
+// The names of every filter, so the frontend can build its <select> from Rust
+#[wasm_bindgen]
+pub fn list_filters() -> Vec<String> {
+    with_registry(|registry| registry.filters().map(|filter| filter.name().to_string()).collect())
+}
+
+// The display label for a filter name, e.g. "huerotate" => "Hue Rotate"
+#[wasm_bindgen]
+pub fn filter_label(name: &str) -> Result<String, JsError> {
+    Ok(with_registry(|registry| registry.get(name).map(|filter| filter.label().to_string()))?)
+}
+
+// The parameters of a filter as JSON (name, label, min, max, default, step, integer), for building sliders
+#[wasm_bindgen]
+pub fn describe_filter(name: &str) -> Result<String, JsError> {
+    let json = with_registry(|registry| {
+        registry.get(name).map(|filter| serde_json::to_string(filter.params()))
+    })??;
+    Ok(json)
+}
@@ -0,0 +1,183 @@
+use image::{ImageFormat, RgbaImage};
+use wasm_bindgen::prelude::*;
+
+use crate::error::FilterError;
+use crate::histogram::{encode_histogram, Histogram, HistogramOptions};
+use crate::output::{self, EncodedImage, OutputOptions};
+use crate::pipeline::{run_stages, Pipeline};
+use crate::raw::RgbaPixels;
+use crate::spec::FilterSpec;
+
+// Holds an upload that has already been decoded, so lots of filters can be tried on it
+// without running load_from_memory + to_rgba8 every single time
+//
+// 'current' is the image with every applied filter so far, 'original' is kept for reset()
+// From JS:
+//   const session = new ImageSession(bytes);
+//   session.apply('{ "filter": "sepia" }');
+//   const preview = session.preview('{ "stages": [ { "filter": "blur" } ] }', '{ "format": "png" }');
+//   session.free(); // when done, the pixels live in wasm memory
+#[wasm_bindgen]
+pub struct ImageSession {
+    original: RgbaImage,
+    current: RgbaImage,
+    // The format of the upload, for exporting with OutputFormat::SameAsInput
+    input_format: Option<ImageFormat>,
+}
+
+#[wasm_bindgen]
+impl ImageSession {
+    // Decodes the upload once
+    #[wasm_bindgen(constructor)]
+    pub fn new(img_data: &[u8]) -> Result<ImageSession, JsError> {
+        Ok(ImageSession::try_new(img_data)?)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.current.width()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.current.height()
+    }
+
+    // Applies a single JSON FilterSpec on top of everything applied so far
+    pub fn apply(&mut self, spec_json: &str) -> Result<(), JsError> {
+        let spec = FilterSpec::from_json(spec_json)?;
+        Ok(self.try_apply(&Pipeline::new().then(spec))?)
+    }
+
+    // Applies a whole JSON pipeline on top of everything applied so far
+    pub fn apply_pipeline(&mut self, pipeline_json: &str) -> Result<(), JsError> {
+        let pipeline = Pipeline::from_json(pipeline_json)?;
+        Ok(self.try_apply(&pipeline)?)
+    }
+
+    // Runs a pipeline on the current image and returns the encoded result, without keeping it
+    // (handy for slider previews: the session doesn't change until apply() is called)
+    pub fn preview(&self, pipeline_json: &str, output_json: &str) -> Result<EncodedImage, JsError> {
+        let pipeline = Pipeline::from_json(pipeline_json)?;
+        let options = OutputOptions::from_json(output_json)?;
+        Ok(self.try_preview(&pipeline, &options)?)
+    }
+
+    // Same as preview, but returns raw RGBA pixels for drawing straight onto a canvas
+    // They come with their size, since it isn't always the session's (e.g. after a stage with a Crop border)
+    pub fn preview_rgba(&self, pipeline_json: &str) -> Result<RgbaPixels, JsError> {
+        let pipeline = Pipeline::from_json(pipeline_json)?;
+        Ok(self.try_preview_image(&pipeline)?.into())
+    }
+
+    // The current image's histogram, as JSON (see histogram.rs)
+    pub fn histogram(&self) -> String {
+        self.current_histogram().to_json()
+    }
+
+    // Draws the current image's histogram, as a PNG
+    pub fn render_histogram(&self, options_json: &str) -> Result<EncodedImage, JsError> {
+        let options = HistogramOptions::from_json(options_json)?;
+        Ok(self.try_render_histogram(&options)?)
+    }
+
+    // Throws away every applied filter
+    pub fn reset(&mut self) {
+        self.current = self.original.clone();
+    }
+
+    // Encodes the current image in the chosen format
+    pub fn export(&self, output_json: &str) -> Result<EncodedImage, JsError> {
+        let options = OutputOptions::from_json(output_json)?;
+        Ok(self.try_export(&options)?)
+    }
+}
+
+// The Rust versions of the methods above
+impl ImageSession {
+    pub fn try_new(img_data: &[u8]) -> Result<Self, FilterError> {
+        let (img, format) = crate::decode_image(img_data)?;
+        Ok(ImageSession { original: img.clone(), current: img, input_format: Some(format) })
+    }
+
+    // Starts a session from pixels that are already decoded
+    pub fn from_image(img: RgbaImage) -> Self {
+        ImageSession { original: img.clone(), current: img, input_format: None }
+    }
+
+    pub fn original(&self) -> &RgbaImage {
+        &self.original
+    }
+
+    pub fn current(&self) -> &RgbaImage {
+        &self.current
+    }
+
+    pub fn try_apply(&mut self, pipeline: &Pipeline) -> Result<(), FilterError> {
+        self.current = self.try_preview_image(pipeline)?;
+        Ok(())
+    }
+
+    pub fn try_preview_image(&self, pipeline: &Pipeline) -> Result<RgbaImage, FilterError> {
+        let stages = pipeline.build()?;
+        Ok(run_stages(&stages, self.current.clone()))
+    }
+
+    pub fn try_preview(&self, pipeline: &Pipeline, options: &OutputOptions) -> Result<EncodedImage, FilterError> {
+        options.validate()?;
+        let img = self.try_preview_image(pipeline)?;
+        output::encode(&img, options, self.input_format)
+    }
+
+    pub fn current_histogram(&self) -> Histogram {
+        Histogram::from_image(&self.current)
+    }
+
+    pub fn try_render_histogram(&self, options: &HistogramOptions) -> Result<EncodedImage, FilterError> {
+        options.validate()?;
+        encode_histogram(&self.current_histogram(), options)
+    }
+
+    pub fn try_export(&self, options: &OutputOptions) -> Result<EncodedImage, FilterError> {
+        output::encode(&self.current, options, self.input_format)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter_kind::FilterKind;
+    use image::Rgba;
+
+    fn session() -> ImageSession {
+        ImageSession::from_image(RgbaImage::from_fn(6, 5, |x, y| Rgba([(x * 40) as u8, (y * 50) as u8, 90, 255])))
+    }
+
+    #[test]
+    fn reset_restores_the_original() {
+        let mut session = session();
+        session.try_apply(&Pipeline::new().then(FilterKind::Invert).then(FilterSpec::new("sharpen").with("border", "crop"))).unwrap();
+        assert_eq!(session.current().dimensions(), (4, 3));
+        session.reset();
+        assert_eq!(session.current(), session.original());
+        assert_eq!((session.width(), session.height()), (6, 5));
+    }
+
+    #[test]
+    fn preview_leaves_current_alone() {
+        let mut session = session();
+        session.try_apply(&Pipeline::new().then(FilterKind::Sepia)).unwrap();
+        let applied = session.current().clone();
+
+        let crop = Pipeline::new().then(FilterSpec::new("emboss").with("border", "crop"));
+        let preview = session.try_preview_image(&crop).unwrap();
+        assert_eq!(session.current(), &applied);
+        assert!(session.try_preview(&crop, &OutputOptions::default()).is_ok());
+        assert_eq!(session.current(), &applied);
+
+        // preview_rgba's pixels come with the cropped size
+        let pixels = RgbaPixels::from(preview.clone());
+        assert_eq!((pixels.width(), pixels.height(), pixels.pixels().0.len()), (4, 3, 4 * 3 * 4));
+        assert_eq!(pixels.into_image(), preview);
+    }
+}
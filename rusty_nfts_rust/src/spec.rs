@@ -0,0 +1,356 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::error::FilterError;
+use crate::filter::Filter;
+use crate::registry::with_registry;
+
+// Describes one adjustable knob of a filter (e.g. the blur sigma)
+// The frontend uses this to build its controls, and Params uses it to validate values
+// In JSON the kind is flattened in, e.g. { "name": "sigma", "label": "Amount", "type": "number", "min": 0.1, ... }
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ParamInfo {
+    pub name: &'static str,
+    pub label: &'static str,
+    #[serde(flatten)]
+    pub kind: ParamKind,
+}
+
+// What sort of value a parameter takes
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ParamKind {
+    // A number in a range (a slider in the UI)
+    Number {
+        min: f64,
+        max: f64,
+        default: f64,
+        // How far one 'tick' of a slider should move
+        step: f64,
+        // true => only whole numbers are allowed (e.g. posterize levels)
+        integer: bool,
+    },
+    // One of a fixed set of names (a <select> in the UI)
+    Choice {
+        options: &'static [&'static str],
+        default: &'static str,
+    },
+    // A colour written as "#rrggbb" or "#rrggbbaa"
+    Color {
+        default: &'static str,
+    },
+    // A grid of numbers written as rows, e.g. [[0, -1, 0], [-1, 5, -1], [0, -1, 0]]
+    Matrix {
+        default: &'static [&'static [f64]],
+    },
+}
+
+// 'const fn' => can be used to build the const parameter tables in each filter
+impl ParamInfo {
+    pub const fn number(name: &'static str, label: &'static str, min: f64, max: f64, default: f64, step: f64) -> Self {
+        ParamInfo { name, label, kind: ParamKind::Number { min, max, default, step, integer: false } }
+    }
+
+    pub const fn integer(name: &'static str, label: &'static str, min: f64, max: f64, default: f64) -> Self {
+        ParamInfo { name, label, kind: ParamKind::Number { min, max, default, step: 1.0, integer: true } }
+    }
+
+    pub const fn choice(name: &'static str, label: &'static str, options: &'static [&'static str], default: &'static str) -> Self {
+        ParamInfo { name, label, kind: ParamKind::Choice { options, default } }
+    }
+
+    pub const fn color(name: &'static str, label: &'static str, default: &'static str) -> Self {
+        ParamInfo { name, label, kind: ParamKind::Color { default } }
+    }
+
+    pub const fn matrix(name: &'static str, label: &'static str, default: &'static [&'static [f64]]) -> Self {
+        ParamInfo { name, label, kind: ParamKind::Matrix { default } }
+    }
+}
+
+// A filter plus the parameters to run it with
+// In JSON it looks like: { "filter": "blur", "params": { "sigma": 2.5 } }
+// Any parameter that is left out uses its default, so { "filter": "blur" } works too
+// 'filter' is a name rather than a FilterKind so filters registered by other crates can be used too
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FilterSpec {
+    pub filter: String,
+    // #[serde(default)] => if "params" is missing from the JSON, start with an empty map
+    // (and when saving, leave "params" out if there aren't any)
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub params: Map<String, Value>,
+}
+
+impl FilterSpec {
+    // A spec with every parameter left at its default
+    // 'impl ToString' => takes a FilterKind or a plain name
+    pub fn new(filter: impl ToString) -> Self {
+        FilterSpec { filter: filter.to_string(), params: Map::new() }
+    }
+
+    // Builder style setter, e.g. FilterSpec::new(FilterKind::Blur).with("sigma", 2.5)
+    // 'impl Into<Value>' => accepts anything serde_json can turn into a JSON value (numbers, strings, ...)
+    pub fn with(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.params.insert(name.to_string(), value.into());
+        self
+    }
+
+    // Reads a spec from its JSON form
+    pub fn from_json(json: &str) -> Result<Self, FilterError> {
+        serde_json::from_str(json).map_err(|e| FilterError::InvalidParameter {
+            name: "spec".to_string(),
+            reason: e.to_string(),
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        // A FilterSpec is always valid JSON, so this can't actually fail
+        serde_json::to_string(self).expect("FilterSpec is always serialisable")
+    }
+
+    // Looks the filter up in the registry and configures it with these parameters
+    pub fn build(&self) -> Result<Box<dyn Filter>, FilterError> {
+        with_registry(|registry| registry.build(self))
+    }
+
+    // Checks the filter exists and every parameter is valid, without keeping the result
+    // Doing this up front means a bad spec fails before we spend any time decoding or filtering
+    pub fn validate(&self) -> Result<(), FilterError> {
+        self.build().map(|_| ())
+    }
+}
+
+// Lets native code write 'FilterKind::Sepia.into()' wherever a spec is expected
+impl From<crate::FilterKind> for FilterSpec {
+    fn from(filter: crate::FilterKind) -> Self {
+        FilterSpec::new(filter)
+    }
+}
+
+// The parameters of a FilterSpec, checked against a filter's schema
+// This is what Filter::configure gets, so a filter never has to validate anything itself
+pub struct Params<'a> {
+    filter: &'a str,
+    schema: &'a [ParamInfo],
+    values: &'a Map<String, Value>,
+}
+
+impl<'a> Params<'a> {
+    // Checks every parameter that was given: it must exist in the schema and have a valid value
+    pub fn new(filter: &'a str, schema: &'a [ParamInfo], values: &'a Map<String, Value>) -> Result<Self, FilterError> {
+        let params = Params { filter, schema, values };
+
+        for name in values.keys() {
+            if !schema.iter().any(|info| info.name == name) {
+                return Err(params.unknown(name));
+            }
+        }
+        for info in schema {
+            match info.kind {
+                ParamKind::Number { .. } => params.number(info.name).map(|_| ())?,
+                ParamKind::Choice { .. } => params.choice(info.name).map(|_| ())?,
+                ParamKind::Color { .. } => params.color(info.name).map(|_| ())?,
+                ParamKind::Matrix { .. } => params.matrix(info.name).map(|_| ())?,
+            }
+        }
+        Ok(params)
+    }
+
+    // The value of a numeric parameter, falling back to its default if it wasn't given
+    pub fn number(&self, name: &str) -> Result<f64, FilterError> {
+        let (min, max, default, integer) = match self.info(name)?.kind {
+            ParamKind::Number { min, max, default, integer, .. } => (min, max, default, integer),
+            _ => return Err(self.wrong_kind(name, "number")),
+        };
+
+        let value = match self.values.get(name) {
+            None => return Ok(default),
+            Some(value) => value.as_f64().ok_or_else(|| invalid(name, format!("expected a number, got {}", value)))?,
+        };
+
+        // NaN fails both comparisons, so it is rejected here too
+        if !(value >= min && value <= max) {
+            return Err(invalid(name, format!("{} is outside the range {} to {}", value, min, max)));
+        }
+        if integer && value.fract() != 0.0 {
+            return Err(invalid(name, format!("{} is not a whole number", value)));
+        }
+        Ok(value)
+    }
+
+    // The value of a choice parameter (always one of its options), falling back to its default
+    pub fn choice(&self, name: &str) -> Result<&'a str, FilterError> {
+        let (options, default) = match self.info(name)?.kind {
+            ParamKind::Choice { options, default } => (options, default),
+            _ => return Err(self.wrong_kind(name, "choice")),
+        };
+
+        let value = match self.values.get(name) {
+            None => return Ok(default),
+            Some(value) => value.as_str().ok_or_else(|| invalid(name, format!("expected a string, got {}", value)))?,
+        };
+
+        // Hand back the 'static option rather than the string from the JSON
+        options
+            .iter()
+            .copied()
+            .find(|option| *option == value)
+            .ok_or_else(|| invalid(name, format!("'{}' is not one of {}", value, options.join(", "))))
+    }
+
+    // The value of a colour parameter as [r, g, b, a], falling back to its default
+    pub fn color(&self, name: &str) -> Result<[u8; 4], FilterError> {
+        let default = match self.info(name)?.kind {
+            ParamKind::Color { default } => default,
+            _ => return Err(self.wrong_kind(name, "color")),
+        };
+
+        let value = match self.values.get(name) {
+            None => default,
+            Some(value) => value.as_str().ok_or_else(|| invalid(name, format!("expected a string, got {}", value)))?,
+        };
+
+        parse_hex_color(value).ok_or_else(|| invalid(name, format!("'{}' is not a colour like #rrggbb or #rrggbbaa", value)))
+    }
+
+    // The value of a matrix parameter as a list of rows, falling back to its default
+    // Every row has the same length and there is at least one number
+    pub fn matrix(&self, name: &str) -> Result<Vec<Vec<f64>>, FilterError> {
+        let default = match self.info(name)?.kind {
+            ParamKind::Matrix { default } => default,
+            _ => return Err(self.wrong_kind(name, "matrix")),
+        };
+
+        let rows: Vec<Vec<f64>> = match self.values.get(name) {
+            None => default.iter().map(|row| row.to_vec()).collect(),
+            Some(value) => {
+                let not_a_matrix = || invalid(name, format!("expected a list of rows of numbers, got {}", value));
+                value
+                    .as_array()
+                    .ok_or_else(not_a_matrix)?
+                    .iter()
+                    .map(|row| {
+                        row.as_array()
+                            .ok_or_else(not_a_matrix)?
+                            .iter()
+                            .map(|number| number.as_f64().ok_or_else(not_a_matrix))
+                            .collect()
+                    })
+                    .collect::<Result<_, _>>()?
+            }
+        };
+
+        let width = rows.first().map_or(0, |row| row.len());
+        if width == 0 {
+            return Err(invalid(name, "the matrix is empty".to_string()));
+        }
+        if rows.iter().any(|row| row.len() != width) {
+            return Err(invalid(name, "every row must have the same number of values".to_string()));
+        }
+        Ok(rows)
+    }
+
+    fn info(&self, name: &str) -> Result<&'a ParamInfo, FilterError> {
+        self.schema.iter().find(|info| info.name == name).ok_or_else(|| self.unknown(name))
+    }
+
+    fn unknown(&self, name: &str) -> FilterError {
+        invalid(name, format!("'{}' has no parameter with this name", self.filter))
+    }
+
+    fn wrong_kind(&self, name: &str, expected: &str) -> FilterError {
+        invalid(name, format!("'{}' does not have a {} parameter with this name", self.filter, expected))
+    }
+}
+
+fn invalid(name: &str, reason: String) -> FilterError {
+    FilterError::InvalidParameter { name: name.to_string(), reason }
+}
+
+// "#rrggbb" => [r, g, b, 255], "#rrggbbaa" => [r, g, b, a]
+fn parse_hex_color(value: &str) -> Option<[u8; 4]> {
+    let hex = value.strip_prefix('#')?;
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return None;
+    }
+    // Each channel is two hex digits, e.g. "ff" => 255
+    let channel = |i: usize| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok();
+    let alpha = if hex.len() == 8 { channel(3)? } else { 255 };
+    Some([channel(0)?, channel(1)?, channel(2)?, alpha])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const SCHEMA: [ParamInfo; 5] = [
+        ParamInfo::number("amount", "Amount", -1.0, 1.0, 0.5, 0.1),
+        ParamInfo::integer("levels", "Levels", 2.0, 8.0, 4.0),
+        ParamInfo::choice("mode", "Mode", &["fast", "slow"], "fast"),
+        ParamInfo::color("color", "Colour", "#ff000080"),
+        ParamInfo::matrix("kernel", "Kernel", &[&[1.0, 2.0], &[3.0, 4.0]]),
+    ];
+
+    // Validates 'values' (a JSON object) against SCHEMA, handing back the name of the bad parameter
+    fn check(values: Value) -> Result<(), String> {
+        let values = values.as_object().expect("an object").clone();
+        match Params::new("test", &SCHEMA, &values) {
+            Ok(_) => Ok(()),
+            Err(FilterError::InvalidParameter { name, .. }) => Err(name),
+            Err(error) => panic!("wrong error: {}", error),
+        }
+    }
+
+    #[test]
+    fn defaults() {
+        let values = Map::new();
+        let params = Params::new("test", &SCHEMA, &values).unwrap();
+        assert_eq!(params.number("amount"), Ok(0.5));
+        assert_eq!(params.number("levels"), Ok(4.0));
+        assert_eq!(params.choice("mode"), Ok("fast"));
+        assert_eq!(params.color("color"), Ok([255, 0, 0, 128]));
+        assert_eq!(params.matrix("kernel"), Ok(vec![vec![1.0, 2.0], vec![3.0, 4.0]]));
+    }
+
+    #[test]
+    fn unknown_keys() {
+        assert_eq!(check(json!({ "amout": 0.5 })), Err("amout".to_string()));
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(check(json!({ "amount": -1.0, "levels": 8 })), Ok(()));
+        for bad in [json!(1.01), json!(-2), json!("0.5"), json!(null), Value::from(f64::NAN)] {
+            assert_eq!(check(json!({ "amount": bad })), Err("amount".to_string()), "{}", bad);
+        }
+        for bad in [json!(3.5), json!(1), json!(9)] {
+            assert_eq!(check(json!({ "levels": bad })), Err("levels".to_string()), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn choices() {
+        assert_eq!(check(json!({ "mode": "slow" })), Ok(()));
+        for bad in [json!("Slow"), json!("medium"), json!(1)] {
+            assert_eq!(check(json!({ "mode": bad })), Err("mode".to_string()), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn colors() {
+        let values = json!({ "color": "#0a0B0c" }).as_object().unwrap().clone();
+        assert_eq!(Params::new("test", &SCHEMA, &values).unwrap().color("color"), Ok([10, 11, 12, 255]));
+        for bad in [json!("0a0b0c"), json!("#0a0b0"), json!("#0a0b0c0d0"), json!("#gg0000"), json!("#ééé"), json!(255)] {
+            assert_eq!(check(json!({ "color": bad })), Err("color".to_string()), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn matrices() {
+        assert_eq!(check(json!({ "kernel": [[1, 2, 3]] })), Ok(()));
+        for bad in [json!([]), json!([[]]), json!([[1, 2], [3]]), json!([1, 2]), json!([[1, "2"]]), json!("[[1]]")] {
+            assert_eq!(check(json!({ "kernel": bad })), Err("kernel".to_string()), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::sync::{Condvar, Mutex};
+
+use rayon::ThreadBuilder;
+use wasm_bindgen::prelude::*;
+
+// Multithreading in the browser (the experimental "wasm-threads" feature, so far only tested in Node, see the README)
+//
+// Browsers don't let wasm start threads itself, so rayon's threads are run by Web Workers instead:
+//   1. initThreadPool(n) asks js/thread_pool.js to start n Web Workers, sharing this module and its memory with them
+//   2. each worker calls run_pool_worker(), which waits for a thread to turn up in the queue
+//   3. once they're all waiting, the JS calls PoolBuilder::build(), which sets rayon up so that each of its
+//      n threads is put in the queue instead of started, and each worker takes one and runs it (forever)
+// After that the "parallel" code in parallel.rs shares its rows out across the workers like it would natively
+//
+// The workers have to be waiting before the pool is built: rayon doesn't return from building it
+// until every one of its threads is running
+//
+// This needs a build with shared memory (see the README), and that build only loads on a page
+// that is 'cross-origin isolated' (crossOriginIsolated === true in JS). The frontend checks for that
+// and uses the normal single threaded build otherwise
+//
+// Note: rayon blocks while it waits for the workers, which the browser doesn't allow on the main thread,
+// so the filters have to be called from a Web Worker too (the frontend's filterWorker.ts does this)
+
+#[wasm_bindgen(module = "/js/thread_pool.js")]
+extern "C" {
+    // Starts the workers and builds the pool once they have all loaded this module
+    // Returns a Promise that resolves when the pool is ready
+    #[wasm_bindgen(js_name = startWorkers)]
+    fn start_workers(module: JsValue, memory: JsValue, builder: PoolBuilder) -> JsValue;
+}
+
+// The threads rayon wants started, waiting for a worker to pick them up
+// (a plain Mutex + Condvar rather than a channel: std's channel never woke a worker blocked in recv(),
+// while a Condvar wake (which rayon uses itself) gets through fine)
+static QUEUE: Mutex<Vec<ThreadBuilder>> = Mutex::new(Vec::new());
+static THREAD_QUEUED: Condvar = Condvar::new();
+
+// From JS: await initThreadPool(navigator.hardwareConcurrency)
+// Can only be called once (rayon's global pool can't be rebuilt)
+#[wasm_bindgen(js_name = initThreadPool)]
+pub fn init_thread_pool(num_threads: usize) -> JsValue {
+    start_workers(wasm_bindgen::module(), wasm_bindgen::memory(), PoolBuilder { num_threads: num_threads.max(1) })
+}
+
+// Handed to startWorkers, which builds the pool once its workers are waiting for threads
+#[wasm_bindgen]
+pub struct PoolBuilder {
+    num_threads: usize,
+}
+
+#[wasm_bindgen]
+impl PoolBuilder {
+    #[wasm_bindgen(js_name = numThreads)]
+    pub fn num_threads(&self) -> usize {
+        self.num_threads
+    }
+
+    // Blocks until every worker has picked up its thread (fine, the JS calls this from a Web Worker)
+    pub fn build(self) -> Result<(), JsError> {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(self.num_threads)
+            // Instead of starting a thread, queue it up for a worker
+            .spawn_handler(|thread| {
+                QUEUE.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(thread);
+                THREAD_QUEUED.notify_one();
+                Ok(())
+            })
+            .build_global()
+            .map_err(|e| JsError::new(&format!("Failed to start the thread pool: {}", e)))
+    }
+}
+
+// Called by each worker in js/thread_pool.js, never returns (the worker becomes one of rayon's threads)
+#[wasm_bindgen(js_name = runPoolWorker)]
+pub fn run_pool_worker() {
+    let mut queue = QUEUE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let thread = loop {
+        match queue.pop() {
+            Some(thread) => break thread,
+            None => queue = THREAD_QUEUED.wait(queue).unwrap_or_else(|poisoned| poisoned.into_inner()),
+        }
+    };
+    drop(queue);
+    thread.run();
+}